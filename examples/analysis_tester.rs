@@ -0,0 +1,117 @@
+use glyph::analyze_full_program_struct;
+
+const CLOCKS_SOURCE: &str = ".DATA SEGMENT\nVAR DW 5\nENDS\n.CODE SEGMENT\nstart:\nMOV AX, BX\nMOV AX, VAR\nADD AX, [BX+SI+2]\nMUL CL\nJNE start\nREP MOVSB\nSHL AX, CL\nENDS";
+
+fn main() {
+    // (name, source, clock count expected on each line)
+    let programs = vec![
+        (
+            "clock counts",
+            CLOCKS_SOURCE,
+            vec![
+                (6, "2"),
+                (7, "10"),
+                (8, "9+EA(11)=20"),
+                (9, "70-77"),
+                (10, "16/4"),
+                (11, "9+17/rep"),
+                (12, "8+4/bit"),
+            ],
+        ),
+        (
+            // A macro call takes the time of everything it expands to
+            "expanded line",
+            "twice macro\nMOV AX, BX\nMUL CL\nendm\n.CODE SEGMENT\ntwice\nENDS",
+            vec![(6, "2 + 70-77")],
+        ),
+    ];
+
+    let mut failed = false;
+
+    for (name, source, expected) in programs {
+        let result = analyze_full_program_struct(source);
+        for (line, text) in expected {
+            let timing = result
+                .line_analysis
+                .iter()
+                .find(|l| l.line_number == line)
+                .and_then(|l| l.timing.as_ref());
+            match timing {
+                Some(timing) if timing.text == text => {
+                    println!("PASS: '{}' -> Line {}: {}", name, line, text)
+                }
+                _ => {
+                    println!(
+                        "FAIL: '{}' -> Line {} Expected {}, Got {:?}",
+                        name,
+                        line,
+                        text,
+                        timing.map(|t| &t.text)
+                    );
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    // (name, source, lines expected to warn about an unset flag)
+    let flag_programs = vec![
+        (
+            "carry never set",
+            ".CODE SEGMENT\nstart:\nJC start\nENDS",
+            vec![3],
+        ),
+        // MUL leaves ZF undefined
+        (
+            "zero flag after mul",
+            ".CODE SEGMENT\nstart:\nMUL CL\nJZ start\nENDS",
+            vec![4],
+        ),
+        (
+            "compare",
+            ".CODE SEGMENT\nstart:\nCMP AX, BX\nJE start\nENDS",
+            vec![],
+        ),
+        // DOS reports errors in CF
+        (
+            "after int",
+            ".CODE SEGMENT\nstart:\nMOV AH, 1\nINT 21h\nJC start\nENDS",
+            vec![],
+        ),
+        (
+            "after call",
+            ".CODE SEGMENT\nstart:\nCALL check\nJC start\ncheck:\nRET\nENDS",
+            vec![],
+        ),
+    ];
+
+    for (name, source, expected) in flag_programs {
+        let warned: Vec<usize> = analyze_full_program_struct(source)
+            .warnings
+            .iter()
+            .filter_map(|w| w.strip_prefix("Line ")?.split(':').next()?.parse().ok())
+            .collect();
+        if warned == expected {
+            println!("PASS: '{}' -> {:?}", name, warned);
+        } else {
+            println!(
+                "FAIL: '{}' -> Expected warnings on {:?}, Got {:?}",
+                name, expected, warned
+            );
+            failed = true;
+        }
+    }
+
+    // Each instruction once, the branch falling through and no repetitions
+    let total = analyze_full_program_struct(CLOCKS_SOURCE).timing.clocks;
+    if total == 123 {
+        println!("PASS: 'program total' -> {}", total);
+    } else {
+        println!("FAIL: 'program total' -> Expected 123, Got {}", total);
+        failed = true;
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,60 @@
+use glyph::DebugSession;
+
+const SOURCE: &str = ".code segment\norg 100h\nstart:\njmp main\ncount dw 0\n\nmain:\nmov cx, 3\nagain:\ninc count\nloop again\nmov ax, 4C00h\nint 21h\nends\nend start";
+
+fn check<T: PartialEq + std::fmt::Debug>(name: &str, got: T, expected: T) -> bool {
+    if got == expected {
+        println!("PASS: '{}' -> {:?}", name, got);
+        true
+    } else {
+        println!("FAIL: '{}' -> Expected {:?}, Got {:?}", name, expected, got);
+        false
+    }
+}
+
+fn main() {
+    let Ok(mut session) = DebugSession::new(SOURCE, true) else {
+        println!("FAIL: 'load' -> Does not load");
+        std::process::exit(1);
+    };
+
+    let results = [
+        check("first line", session.current_line(), Some(4)),
+        // Lines without code break on the next one that has some
+        check("label line", session.add_breakpoint(7), Some(8)),
+        check("blank line", session.add_breakpoint(6), Some(8)),
+        check("code line", session.add_breakpoint(11), Some(11)),
+        check("after the code", session.add_breakpoint(14), None),
+        check("label", session.add_label_breakpoint("again"), Some(10)),
+        check(
+            "unknown label",
+            session.add_label_breakpoint("nowhere"),
+            None,
+        ),
+        check("breakpoints", session.breakpoints(), vec![8, 10, 11]),
+        check("remove", session.remove_breakpoint(11), true),
+        check("remove twice", session.remove_breakpoint(11), false),
+        check("watch variable", session.watch_variable("count"), true),
+        check(
+            "watch unknown variable",
+            session.watch_variable("missing"),
+            false,
+        ),
+        check("watch register", session.watch_register("ax"), true),
+        check(
+            "watch unknown register",
+            session.watch_register("qx"),
+            false,
+        ),
+        check("no steps yet", session.steps(), 0.0),
+        check("trace off", session.trace_json_lines(), None),
+    ];
+
+    // Recording starts empty; each step then adds one JSON line
+    session.record_trace(100);
+    let trace = check("trace on", session.trace_json_lines(), Some(String::new()));
+
+    if results.contains(&false) || !trace {
+        std::process::exit(1);
+    }
+}
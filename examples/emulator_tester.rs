@@ -0,0 +1,139 @@
+use glyph::Emulator;
+
+/// Steps a test program may take before it is considered stuck.
+const MAX_STEPS: u32 = 10_000;
+
+/// Segment DOS would load the program at; .EXE images start 10h paragraphs
+/// later, after the PSP.
+const LOAD_SEGMENT: u16 = 0x1000;
+
+fn main() {
+    // (name, source, .COM or .EXE, segment, offset, bytes expected there after HLT)
+    let programs = vec![
+        (
+            "sum loop",
+            ".code segment\norg 100h\nstart:\njmp main\nsum dw 0\nmain:\nmov cx, 5\nmov ax, 0\nnext:\nadd ax, cx\nloop next\nmov sum, ax\nhlt\nends\nend start",
+            true,
+            LOAD_SEGMENT,
+            0x102,
+            vec![0x0F, 0x00],
+        ),
+        (
+            "call, push and pop",
+            ".code segment\norg 100h\nstart:\njmp main\nresult dw 0\nmain:\nmov ax, 21\npush ax\ncall double\npop bx\nadd ax, bx\nmov result, ax\nhlt\ndouble:\nshl ax, 1\nret\nends\nend start",
+            true,
+            LOAD_SEGMENT,
+            0x102,
+            vec![0x3F, 0x00],
+        ),
+        (
+            // DS must be loaded with the relocated data segment
+            "exe data segment",
+            ".stack segment\ndw 32 dup(0)\nends\n.data segment\nvalue dw 0\nends\n.code segment\nstart:\nmov ax, @data\nmov ds, ax\nmov value, 1234h\nhlt\nends\nend start",
+            false,
+            LOAD_SEGMENT + 0x10 + 4,
+            0,
+            vec![0x34, 0x12],
+        ),
+    ];
+
+    let mut failed = false;
+
+    for (name, source, com, segment, offset, expected) in programs {
+        let Ok(mut emulator) = Emulator::new(source, com) else {
+            println!("FAIL: '{}' -> Does not load", name);
+            failed = true;
+            continue;
+        };
+        let outcome = emulator.run(MAX_STEPS).ok();
+        let memory = emulator.read_memory(segment, offset, expected.len());
+        if outcome.as_deref() != Some("halted") {
+            println!("FAIL: '{}' -> Did not halt: {:?}", name, outcome);
+            failed = true;
+        } else if memory != expected {
+            println!(
+                "FAIL: '{}' -> At {:04X}:{:04X} expected {:02X?}, Got {:02X?}",
+                name, segment, offset, expected, memory
+            );
+            failed = true;
+        } else {
+            println!("PASS: '{}' -> {:02X?}", name, memory);
+        }
+    }
+
+    // (name, source, .COM or .EXE, keyboard input, console output, exit code)
+    let console_programs = vec![
+        (
+            "com hello",
+            ".code segment\norg 100h\nstart:\njmp main\nmsg db 'Hola$'\nmain:\nmov ah, 09h\nlea dx, msg\nint 21h\nmov ax, 4C00h\nint 21h\nends\nend start",
+            true,
+            "",
+            "Hola",
+            0,
+        ),
+        (
+            "print digits",
+            ".code segment\norg 100h\nstart:\nmov cx, 5\nmov dl, '0'\nnext:\nmov ah, 2\nint 21h\ninc dl\nloop next\nmov ax, 4C05h\nint 21h\nends\nend start",
+            true,
+            "",
+            "01234",
+            5,
+        ),
+        (
+            // Function 01h echoes the key it reads
+            "keyboard",
+            ".code segment\norg 100h\nstart:\nmov ah, 1\nint 21h\ninc al\nmov dl, al\nmov ah, 2\nint 21h\nmov ax, 4C00h\nint 21h\nends\nend start",
+            true,
+            "a",
+            "ab",
+            0,
+        ),
+        (
+            // Function 09h prints from DS:DX
+            "exe hello",
+            ".stack segment\ndw 32 dup(0)\nends\n.data segment\nmsg db 'Hi$'\nends\n.code segment\nstart:\nmov ax, @data\nmov ds, ax\nmov ah, 9\nlea dx, msg\nint 21h\nmov ax, 4C00h\nint 21h\nends\nend start",
+            false,
+            "",
+            "Hi",
+            0,
+        ),
+        (
+            "simplified segments and procedures",
+            ".model small\n.stack\n.data\nnum dw 42\n.code\nmain proc\n.startup\nmov ax, num\ncall print\n.exit 2\nmain endp\nprint proc\nmov bl, 10\ndiv bl\nmov dh, ah\nmov dl, al\nadd dl, '0'\nmov ah, 2\nint 21h\nmov dl, dh\nadd dl, '0'\nint 21h\nret\nprint endp\nend main",
+            false,
+            "",
+            "42",
+            2,
+        ),
+    ];
+
+    for (name, source, com, input, output, exit_code) in console_programs {
+        let Ok(mut emulator) = Emulator::new(source, com) else {
+            println!("FAIL: '{}' -> Does not load", name);
+            failed = true;
+            continue;
+        };
+        emulator.provide_input(input);
+        let outcome = emulator.run(MAX_STEPS).ok();
+        if outcome.as_deref() != Some("halted") {
+            println!("FAIL: '{}' -> Did not halt: {:?}", name, outcome);
+            failed = true;
+        } else if emulator.output() != output || emulator.exit_code() != Some(exit_code) {
+            println!(
+                "FAIL: '{}' -> Expected {:?} (exit {}), Got {:?} (exit {:?})",
+                name,
+                output,
+                exit_code,
+                emulator.output(),
+                emulator.exit_code()
+            );
+            failed = true;
+        } else {
+            println!("PASS: '{}' -> {:?}", name, output);
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
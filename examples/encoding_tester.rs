@@ -0,0 +1,118 @@
+use glyph::analyze_full_program_struct;
+
+fn main() {
+    // (name, source, machine code expected on each line)
+    let programs = vec![
+        (
+            // The variable's width picks FE or FF; mod=00 r/m=110 is a direct address
+            "direct memory operands",
+            ".DATA SEGMENT\nVAR DW 5\nB1 DB 1\nENDS\n.CODE SEGMENT\nINC VAR\nINC B1\nINC AX\nENDS",
+            vec![(6, "FF 06 00 00"), (7, "FE 06 02 00"), (8, "40")],
+        ),
+        (
+            // Pass one cannot know a variable's offset is small, so it always
+            // takes a disp16
+            "base, index and variable",
+            ".DATA SEGMENT\nVAR DW 5\nTAB DB 1\nENDS\n.CODE SEGMENT\nINC VAR[BX+SI+2]\nINC VAR[BP]\nINC TAB[BX][SI]\nINC ES:VAR[DI]\nINC VAR[BX+200h]\nINC TAB[SI-3]\nENDS",
+            vec![
+                (6, "FF 80 02 00"),
+                (7, "FF 86 00 00"),
+                (8, "FE 80 02 00"),
+                (9, "26 FF 85 00 00"),
+                (10, "FF 87 00 02"),
+                (11, "FE 84 FF FF"),
+            ],
+        ),
+        (
+            // disp8 when it fits, disp16 otherwise; [BP] always needs one
+            "base, index and displacement",
+            ".CODE SEGMENT\nINC WORD PTR [BX+SI+2]\nINC WORD PTR [BP]\nINC BYTE PTR [BX][SI]\nINC WORD PTR [BX+200h]\nINC BYTE PTR [SI-3]\nENDS",
+            vec![
+                (2, "FF 40 02"),
+                (3, "FF 46 00"),
+                (4, "FE 00"),
+                (5, "FF 87 00 02"),
+                (6, "FE 44 FD"),
+            ],
+        ),
+        (
+            // One of each operand form the instruction table knows
+            "instruction table",
+            ".DATA SEGMENT\nVAR DW 5\nENDS\n.CODE SEGMENT\nMOV AX, BX\nMOV VAR, 1234h\nADD AL, 5\nSUB CX, VAR\nSHL BX, 1\nMUL CL\nPUSH AX\nXCHG AX, BX\nMOVSB\nENDS",
+            vec![
+                (5, "89 D8"),
+                (6, "C7 06 00 00 34 12"),
+                (7, "04 05"),
+                (8, "2B 0E 00 00"),
+                (9, "D1 E3"),
+                (10, "F6 E1"),
+                (11, "50"),
+                (12, "93"),
+                (13, "A4"),
+            ],
+        ),
+        (
+            // `=` can be redefined; a text equate stands for its text
+            "equates",
+            "K EQU 10\nIDX EQU <BX>\nN = 1\n.CODE SEGMENT\nMOV AX, K\nMOV AX, N\nN = 2\nMOV AL, N\nINC IDX\nENDS",
+            vec![(5, "B8 0A 00"), (6, "B8 01 00"), (8, "B0 02"), (9, "43")],
+        ),
+        (
+            "expressions",
+            ".DATA SEGMENT\nVAR DW 5\nTAB DB 4 DUP(1)\nENDS\n.CODE SEGMENT\nMOV AX, (3+4)*2 MOD 5\nMOV AX, OFFSET TAB + 1\nMOV AL, HIGH 1234h\nMOV CX, LENGTH TAB\nMOV CX, SIZE TAB\nMOV DX, TYPE VAR\nMOV AX, 1 SHL 4 OR 3\nMOV AX, OFFSET $\nMOV AL, TAB[2+1]\nENDS",
+            vec![
+                (3, "01 01 01 01"),
+                (6, "B8 04 00"),
+                (7, "B8 03 00"),
+                (8, "B0 12"),
+                (9, "B9 04 00"),
+                (10, "B9 04 00"),
+                (11, "BA 02 00"),
+                (12, "B8 13 00"),
+                (13, "B8 14 00"),
+                (14, "A0 05 00"),
+            ],
+        ),
+        (
+            // A far procedure is called through its segment and returns with RETF
+            "procedures",
+            ".code segment\nmain proc\ncall helper\ncall remote\nret\nmain endp\nhelper proc near\nret 2\nhelper endp\nremote proc far\nret\nremote endp\nends",
+            vec![
+                (3, "E8 06 00"),
+                (4, "9A 0C 00 00 00"),
+                (5, "C3"),
+                (8, "C2 02 00"),
+                (11, "CB"),
+            ],
+        ),
+    ];
+
+    let mut failed = false;
+
+    for (name, source, expected) in programs {
+        let result = analyze_full_program_struct(source);
+        for (line, code) in expected {
+            let Some(analysis) = result.line_analysis.iter().find(|l| l.line_number == line) else {
+                println!("FAIL: '{}' -> Line {} not analyzed", name, line);
+                failed = true;
+                continue;
+            };
+            match (&analysis.machine_code, &analysis.error_message) {
+                (Some(got), None) if got == code => {
+                    println!("PASS: '{}' -> Line {}: {}", name, line, code)
+                }
+                (got, error) => {
+                    println!(
+                        "FAIL: '{}' -> Line {} Expected {}, Got {:?} {:?}",
+                        name, line, code, got, error
+                    );
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
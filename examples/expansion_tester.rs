@@ -0,0 +1,104 @@
+use glyph::{
+    analyze_full_program_struct, assemble_com, clear_include_files, register_include_file,
+};
+
+const IF_SOURCE: &str = "DEBUG EQU 0\nCOM EQU 1\n.code segment\nstart:\nIF DEBUG\nint 3\nELSE\nnop\nENDIF\nIF COM\ninc ax\nENDIF\nIFE COM\ninc cx\nENDIF\nIFDEF MISSING\ninc bx\nENDIF\nIFNDEF MISSING\ninc dx\nENDIF\nret\nends\nend start";
+
+fn main() {
+    // Read by the "include" program
+    register_include_file("defs.inc", "K EQU 7\nload_k macro\nmov al, K\nendm");
+    register_include_file(
+        "guarded.inc",
+        "IFNDEF GUARDED_INC\nGUARDED_INC EQU 1\nG EQU 3\nENDIF",
+    );
+    register_include_file("nop.inc", "nop");
+
+    // (name, source, whole .COM image)
+    let programs = vec![
+        (
+            "macro with parameter",
+            "putc macro c\nmov dl, c\nmov ah, 2\nint 21h\nendm\n.code segment\nstart:\nputc 'A'\nputc 'B'\nret\nends\nend start",
+            vec![
+                0xB2, 0x41, 0xB4, 0x02, 0xCD, 0x21, 0xB2, 0x42, 0xB4, 0x02, 0xCD, 0x21, 0xC3,
+            ],
+        ),
+        (
+            // Each expansion gets its own label
+            "local labels",
+            "pause macro\nlocal again\nagain:\nloop again\nendm\n.code segment\nstart:\npause\npause\nret\nends\nend start",
+            vec![0xE2, 0xFE, 0xE2, 0xFE, 0xC3],
+        ),
+        ("if and else", IF_SOURCE, vec![0x90, 0x40, 0x42, 0xC3]),
+        (
+            "if with comparison",
+            "COM EQU 1\n.code segment\nstart:\nIF COM EQ 1\ninc ax\nENDIF\nIF COM GT 1\ninc bx\nENDIF\nret\nends\nend start",
+            vec![0x40, 0xC3],
+        ),
+        (
+            "rept, irp and irpc",
+            ".code segment\nstart:\nrept 3\ninc ax\nendm\nirp r, <bx, cx>\npush r\nendm\nirpc d, 12\nmov al, d\nendm\nret\nends\nend start",
+            vec![0x40, 0x40, 0x40, 0x53, 0x51, 0xB0, 0x01, 0xB0, 0x02, 0xC3],
+        ),
+        (
+            // & joins a parameter to the text around it, even inside quotes
+            "& operator",
+            "mark macro n\nlbl&n:\nendm\n.code segment\nstart:\nirpc c, xy\ndb '&c'\nendm\nmark 1\njmp lbl1\nends\nend start",
+            vec![0x78, 0x79, 0xEB, 0xFE],
+        ),
+        (
+            "include",
+            ".code segment\nstart:\ninclude defs.inc\nload_k\nmov bl, K\nret\nends\nend start",
+            vec![0xB0, 0x07, 0xB3, 0x07, 0xC3],
+        ),
+        (
+            // A guard keeps the second copy out; without one the text repeats
+            "include twice",
+            ".code segment\nstart:\ninclude guarded.inc\ninclude guarded.inc\ninclude nop.inc\ninclude nop.inc\nmov al, G\nret\nends\nend start",
+            vec![0x90, 0x90, 0xB0, 0x03, 0xC3],
+        ),
+    ];
+
+    let mut failed = false;
+
+    for (name, source, expected) in programs {
+        match assemble_com(source) {
+            Ok(image) if image == expected => {
+                println!("PASS: '{}' -> {} bytes", name, image.len())
+            }
+            Ok(image) => {
+                println!(
+                    "FAIL: '{}' -> Expected {:02X?}, Got {:02X?}",
+                    name, expected, image
+                );
+                failed = true;
+            }
+            Err(errors) => {
+                println!("FAIL: '{}' -> Does not assemble: {:?}", name, errors);
+                failed = true;
+            }
+        }
+    }
+
+    clear_include_files();
+
+    // Lines skipped by IF stay in the analysis, marked inactive
+    let inactive: Vec<usize> = analyze_full_program_struct(IF_SOURCE)
+        .line_analysis
+        .iter()
+        .filter(|l| l.inactive)
+        .map(|l| l.line_number)
+        .collect();
+    if inactive == [6, 14, 17] {
+        println!("PASS: 'inactive lines' -> {:?}", inactive);
+    } else {
+        println!(
+            "FAIL: 'inactive lines' -> Expected [6, 14, 17], Got {:?}",
+            inactive
+        );
+        failed = true;
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -7,6 +7,11 @@ fn main() {
         (".CODE SEGMENT\nMOV AX, 21h\nENDS", false, ""),
         (".DATA SEGMENT\nvar1 DW 100 DUP(0)\nENDS", false, ""),
         (".CODE SEGMENT\nstart:\nEND start", false, ""),
+        (
+            "COM EQU 1\n.CODE SEGMENT\nIF COM EQ 1\nMOV AX, 2 GE 1\nENDIF\nENDS",
+            false,
+            "",
+        ),
         // Invalid Cases (Should Fail)
         (".stacks segment", true, "Declaración de segmento inválida"),
         (
@@ -0,0 +1,87 @@
+use glyph::analyze_full_program_struct;
+
+/// `count` copies of a 3-byte instruction, to put distance between labels.
+fn filler(count: usize) -> String {
+    "mov ax, 1\n".repeat(count)
+}
+
+fn main() {
+    // (name, source, machine code expected on each line)
+    let programs = vec![(
+        // Backward and close forward jumps are short, a far one is near
+        "jump sizes",
+        format!(
+            ".code segment\nstart:\njmp close\n{}close:\njmp start\njmp distant\njne start\n{}distant:\nret\nends\nend start",
+            filler(4),
+            filler(50)
+        ),
+        vec![
+            (3, "EB 0C"),
+            (9, "EB F0"),
+            (10, "E9 98 00"),
+            (11, "75 EB"),
+            (63, "C3"),
+        ],
+    ), (
+        // Labels after instructions of every length land where the bytes do
+        "exact sizes",
+        ".code segment\nstart:\njmp done\nmov ax, bx\nadd ax, 1000h\ninc cx\nshl ax, 1\nmov word ptr [bx+200h], 5\ndone:\njmp start\nends\nend start".to_string(),
+        vec![(3, "EB 0E"), (8, "C7 87 00 02 05 00"), (10, "EB EE")],
+    ), (
+        // A later ORG moves the location counter forward
+        "org",
+        ".code segment\norg 100h\nstart:\njmp main\norg 110h\nmain:\nmov ax, offset main\nret\nends\nend start".to_string(),
+        vec![(4, "EB 0E"), (7, "B8 10 01")],
+    ), (
+        // Data is padded with zeros, code with NOPs
+        "align and even",
+        ".data segment\nb db 1\nalign 4\nx dw 1234h\ndb 1\neven\ny dw 5678h\nends\n.code segment\nnop\nalign 4\nmov ax, offset x\nmov bx, offset y\nends".to_string(),
+        vec![
+            (3, "00 00 00"),
+            (6, "00"),
+            (11, "90 90 90"),
+            (12, "B8 04 00"),
+            (13, "BB 08 00"),
+        ],
+    ), (
+        // Promoting the first JMP moves the second one a byte closer to a
+        // target pinned by ALIGN; it must stay near as pass one sized it
+        "sticky near jump across align",
+        format!(
+            ".code segment\nstart:\njmp far_away\n{}jmp done\n{}align 16\ndone:\nret\n{}nop\nnop\nfar_away:\nret\nends\nend start",
+            "nop\n".repeat(12),
+            filler(40),
+            filler(66)
+        ),
+        vec![(3, "E9 56 01"), (16, "E9 7E 00"), (59, "C3"), (129, "C3")],
+    )];
+
+    let mut failed = false;
+
+    for (name, source, expected) in programs {
+        let result = analyze_full_program_struct(&source);
+        for (line, code) in expected {
+            let Some(analysis) = result.line_analysis.iter().find(|l| l.line_number == line) else {
+                println!("FAIL: '{}' -> Line {} not analyzed", name, line);
+                failed = true;
+                continue;
+            };
+            match (&analysis.machine_code, &analysis.error_message) {
+                (Some(got), None) if got == code => {
+                    println!("PASS: '{}' -> Line {}: {}", name, line, code)
+                }
+                (got, error) => {
+                    println!(
+                        "FAIL: '{}' -> Line {} Expected {}, Got {:?} {:?}",
+                        name, line, code, got, error
+                    );
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
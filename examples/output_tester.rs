@@ -0,0 +1,108 @@
+use glyph::{assemble_com, assemble_exe, assemble_intel_hex, assemble_srecord};
+use std::fmt::Debug;
+
+const COM_SOURCE: &str = ".code segment\norg 100h\nstart:\njmp main\nmsg db 'Hola$'\nmain:\nmov ah, 09h\nlea dx, msg\nint 21h\nmov ax, 4C00h\nint 21h\nends\nend start";
+
+/// Stack at paragraph 0, data at 4, code at 5; `mov ax, @data` needs a
+/// relocation.
+const EXE_SOURCE: &str = ".stack segment\ndw 32 dup(0)\nends\n.data segment\nmsg db 'Hi$'\nends\n.code segment\nstart:\nmov ax, @data\nmov ds, ax\nmov ah, 9\nlea dx, msg\nint 21h\nmov ax, 4C00h\nint 21h\nends\nend start";
+
+const EXE_CODE: [u8; 18] = [
+    0xB8, 0x04, 0x00, 0x8E, 0xD8, 0xB4, 0x09, 0x8D, 0x16, 0x00, 0x00, 0xCD, 0x21, 0xB8, 0x00, 0x4C,
+    0xCD, 0x21,
+];
+
+fn check<T: PartialEq + Debug>(name: &str, got: Result<T, Vec<String>>, expected: T) -> bool {
+    match got {
+        Ok(output) if output == expected => {
+            println!("PASS: '{}'", name);
+            true
+        }
+        Ok(output) => {
+            println!(
+                "FAIL: '{}' -> Expected {:02X?}, Got {:02X?}",
+                name, expected, output
+            );
+            false
+        }
+        Err(errors) => {
+            println!("FAIL: '{}' -> Does not assemble: {:?}", name, errors);
+            false
+        }
+    }
+}
+
+fn main() {
+    let com = vec![
+        0xEB, 0x05, b'H', b'o', b'l', b'a', b'$', 0xB4, 0x09, 0x8D, 0x16, 0x02, 0x01, 0xCD, 0x21,
+        0xB8, 0x00, 0x4C, 0xCD, 0x21,
+    ];
+
+    #[rustfmt::skip]
+    let mut exe: Vec<u8> = vec![
+        b'M', b'Z',
+        0x82, 0x00, // 130 bytes in the last page
+        0x01, 0x00, // one page
+        0x01, 0x00, // one relocation
+        0x02, 0x00, // two header paragraphs
+        0x00, 0x00, // no extra memory: the program has a stack
+        0xFF, 0xFF,
+        0x00, 0x00, 0x40, 0x00, // SS:SP = 0000:0040
+        0x00, 0x00, // checksum
+        0x00, 0x00, 0x05, 0x00, // CS:IP = 0005:0000
+        0x1C, 0x00, // relocation table
+        0x00, 0x00, // overlay
+        0x01, 0x00, 0x05, 0x00, // the immediate of `mov ax, @data`
+    ];
+    exe.extend([0; 0x40]);
+    exe.extend(b"Hi$");
+    exe.resize(0x20 + 0x50, 0);
+    exe.extend(EXE_CODE);
+
+    let intel_hex = [
+        ":1000000000000000000000000000000000000000F0",
+        ":1000100000000000000000000000000000000000E0",
+        ":1000200000000000000000000000000000000000D0",
+        ":1000300000000000000000000000000000000000C0",
+        ":020000020004F8",
+        ":0300000048692428",
+        ":020000020005F7",
+        ":10000000B804008ED8B4098D160000CD21B8004C7C",
+        ":02001000CD2100",
+        ":0400000300050000F4",
+        ":00000001FF",
+    ];
+
+    let srecord = [
+        "S0080000676C797068D3",
+        "S113000000000000000000000000000000000000EC",
+        "S113001000000000000000000000000000000000DC",
+        "S113002000000000000000000000000000000000CC",
+        "S113003000000000000000000000000000000000BC",
+        "S1060040486924E4",
+        "S1130050B804008ED8B4098D160000CD21B8004C28",
+        "S1050060CD21AC",
+        "S5030007F5",
+        "S9030050AC",
+    ];
+
+    let lines = |text: String| text.lines().map(str::to_string).collect::<Vec<_>>();
+    let results = [
+        check("com", assemble_com(COM_SOURCE), com),
+        check("exe", assemble_exe(EXE_SOURCE), exe),
+        check(
+            "intel hex",
+            assemble_intel_hex(EXE_SOURCE).map(lines),
+            intel_hex.map(String::from).to_vec(),
+        ),
+        check(
+            "s-record",
+            assemble_srecord(EXE_SOURCE).map(lines),
+            srecord.map(String::from).to_vec(),
+        ),
+    ];
+
+    if results.contains(&false) {
+        std::process::exit(1);
+    }
+}
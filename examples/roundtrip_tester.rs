@@ -0,0 +1,55 @@
+use glyph::{assemble_com, disassemble_com};
+
+fn main() {
+    let programs = vec![
+        (
+            "hello",
+            ".code segment\norg 100h\nstart:\njmp main\nmsg db 'Hola, mundo!$'\nmain:\nmov ah, 09h\nlea dx, msg\nint 21h\nmov ax, 4C00h\nint 21h\nends\nend start",
+        ),
+        (
+            "addressing",
+            ".code segment\nstart:\nmov al, [bx+si+5]\nmov byte ptr [di], 0FFh\nadd word ptr es:[bp-4], 7\ninc word ptr [1234h]\nmov ax, [0200h]\nlds si, [bx]\npush ds\npop es\nret\nends\nend start",
+        ),
+        (
+            "branches",
+            ".code segment\nstart:\nmov cx, 5\nagain:\ncall sub1\nloop again\njcxz done\ncmp ax, 0FFFFh\njne start\ndone:\nint 20h\nsub1:\nrep movsb\nrepe cmpsb\nret 2\nends\nend start",
+        ),
+    ];
+
+    let mut failed = false;
+
+    for (name, source) in programs {
+        let original = match assemble_com(source) {
+            Ok(bytes) => bytes,
+            Err(errors) => {
+                println!("FAIL: '{}' -> Does not assemble: {:?}", name, errors);
+                failed = true;
+                continue;
+            }
+        };
+        let listing = disassemble_com(&original);
+        match assemble_com(&listing) {
+            Ok(bytes) if bytes == original => {
+                println!("PASS: '{}' -> {} bytes round-trip", name, bytes.len())
+            }
+            Ok(bytes) => {
+                println!(
+                    "FAIL: '{}' -> Expected {:02X?}, Got {:02X?}\n{}",
+                    name, original, bytes, listing
+                );
+                failed = true;
+            }
+            Err(errors) => {
+                println!(
+                    "FAIL: '{}' -> Disassembly does not assemble: {:?}\n{}",
+                    name, errors, listing
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,57 @@
+use glyph::{analyze_full_program_struct, clear_include_files, register_include_file};
+
+fn main() {
+    register_include_file("defs.inc", "; constants\nK EQU 7");
+
+    // (name, source, symbols and the source line that defines each)
+    let programs = vec![
+        (
+            // .MODEL, .STACK and .STARTUP add lines the source does not have
+            "simplified segments",
+            ".model small\n.stack 100h\n.data\nmsg db 'Hola$'\n.code\nmain proc\n.startup\nmov dx, offset msg\n.exit 0\nmain endp\n.data\nn dw 5\nend",
+            vec![("msg", 4), ("main", 6), ("n", 12), ("@Startup", 7)],
+        ),
+        (
+            // Symbols from an include file or a macro belong to the line
+            // that brought them in
+            "includes and macros",
+            "include defs.inc\nmark macro name\nname:\nendm\n.code segment\nstart:\nmark lbl1\nnop\nmark lbl2\nret\nends\nend start",
+            vec![("K", 1), ("start", 6), ("lbl1", 7), ("lbl2", 9)],
+        ),
+    ];
+
+    let mut failed = false;
+
+    for (name, source, expected) in programs {
+        let result = analyze_full_program_struct(source);
+        if !result.errors.is_empty() {
+            println!("FAIL: '{}' -> Does not assemble: {:?}", name, result.errors);
+            failed = true;
+            continue;
+        }
+        for (symbol, line) in expected {
+            match result.symbol_table.iter().find(|s| s.name == symbol) {
+                Some(record) if record.line == line => {
+                    println!("PASS: '{}' -> '{}' on line {}", name, symbol, line)
+                }
+                Some(record) => {
+                    println!(
+                        "FAIL: '{}' -> '{}' Expected line {}, Got {}",
+                        name, symbol, line, record.line
+                    );
+                    failed = true;
+                }
+                None => {
+                    println!("FAIL: '{}' -> '{}' not in the symbol table", name, symbol);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    clear_include_files();
+
+    if failed {
+        std::process::exit(1);
+    }
+}
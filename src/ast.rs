@@ -1,18 +1,129 @@
 // src/ast.rs
 use serde::Serialize;
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize)]
 pub enum Operand {
     Register(String),
     Immediate(u64, String),
-    Memory { base: String, offset: Option<i64> },
+    /// `seg:[base + index + displacement]`, e.g. `ES:[DI]`, `var[SI]`, `[BX+SI+2]`.
+    Memory {
+        segment: Option<String>,
+        base: Option<String>,
+        index: Option<String>,
+        /// Everything between the brackets that is not a register (`var + 2`, `N*4`).
+        displacement: Option<Expr>,
+    },
+    /// `BYTE PTR [BX]`: explicit operand size for memory references.
+    Ptr {
+        size: String,
+        target: Box<Operand>,
+    },
     Label(String),
     StringLiteral(String),
+    /// `N + 1`, `-5`, `OFFSET msg`: folded once the symbols in it are known.
+    Expression(Expr),
     // NEW VARIANTS
-    Dup { count: u64, value: Box<Operand> },
+    Dup {
+        count: Expr,
+        value: Box<Operand>,
+    },
     Uninitialized,
 }
 
+/// Assembly-time expression tree.
+#[derive(Debug, Clone, Serialize)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    /// `$`: offset of the statement being assembled.
+    Here,
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    High,
+    Low,
+    Offset,
+    Seg,
+    Size,
+    Length,
+    Type,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+    /// Comparisons give -1 (all bits set) when true and 0 when false.
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Negate => "-",
+            Self::Not => "NOT",
+            Self::High => "HIGH",
+            Self::Low => "LOW",
+            Self::Offset => "OFFSET",
+            Self::Seg => "SEG",
+            Self::Size => "SIZE",
+            Self::Length => "LENGTH",
+            Self::Type => "TYPE",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "MOD",
+            Self::Shl => "SHL",
+            Self::Shr => "SHR",
+            Self::And => "AND",
+            Self::Or => "OR",
+            Self::Xor => "XOR",
+            Self::Eq => "EQ",
+            Self::Ne => "NE",
+            Self::Lt => "LT",
+            Self::Le => "LE",
+            Self::Gt => "GT",
+            Self::Ge => "GE",
+        };
+        write!(f, "{}", text)
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub enum Statement {
     Instruction {
@@ -33,9 +144,12 @@ pub enum Statement {
         value: Operand,
     },
     // NEW VARIANT
+    /// `NAME EQU value` or `NAME = value`.
     Constant {
         name: String,
         value: Operand,
+        /// Defined with `=`, which may assign the name again later on.
+        redefinable: bool,
     },
     Data {
         directive: String,
@@ -45,9 +159,85 @@ pub enum Statement {
         name: String,
         args: Vec<Operand>,
     },
+    /// `NAME PROC [NEAR|FAR]`: starts a procedure, NEAR unless stated.
+    Procedure {
+        name: String,
+        distance: Distance,
+    },
+    /// `NAME ENDP`
+    ProcedureEnd {
+        name: String,
+    },
     Unknown,
 }
 
+/// How a procedure is called and returns: within its segment (CALL rel16,
+/// RET) or from anywhere (CALL seg:offset, RETF).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Distance {
+    Near,
+    Far,
+}
+
+impl fmt::Display for Distance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Near => write!(f, "NEAR"),
+            Self::Far => write!(f, "FAR"),
+        }
+    }
+}
+
+/// Memory model chosen with `.MODEL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MemoryModel {
+    /// Code and data in one segment, for a .COM program.
+    Tiny,
+    Small,
+    /// Far code, near data.
+    Medium,
+    /// Near code, far data.
+    Compact,
+    Large,
+}
+
+impl MemoryModel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "TINY" => Some(Self::Tiny),
+            "SMALL" => Some(Self::Small),
+            "MEDIUM" => Some(Self::Medium),
+            "COMPACT" => Some(Self::Compact),
+            "LARGE" => Some(Self::Large),
+            _ => None,
+        }
+    }
+
+    /// Value of `@Model`, as MASM numbers them.
+    pub fn number(self) -> i64 {
+        match self {
+            Self::Tiny => 1,
+            Self::Small => 2,
+            Self::Compact => 3,
+            Self::Medium => 4,
+            Self::Large => 5,
+        }
+    }
+
+    /// Distance of a PROC that does not state one.
+    pub fn code_distance(self) -> Distance {
+        match self {
+            Self::Medium | Self::Large => Distance::Far,
+            _ => Distance::Near,
+        }
+    }
+
+    /// Data is reached through far pointers by default.
+    pub fn far_data(self) -> bool {
+        matches!(self, Self::Compact | Self::Large)
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Spanned<T> {
     pub node: T,
@@ -0,0 +1,650 @@
+// src/disassembler.rs
+//! Turns 8086 machine code back into Glyph source.
+//!
+//! Decoding uses the same [`isa::table`] the encoder does. Code is found by
+//! following control flow from the entry point, so bytes no path reaches
+//! (strings, tables) come out as `DB`. Every decoded instruction is encoded
+//! again before it is accepted; bytes the assembler would encode differently
+//! are kept as `DB` too, which makes `assemble → disassemble → assemble`
+//! byte-identical.
+
+use crate::ast::{BinaryOp, Expr, Operand, Statement, UnaryOp};
+use crate::isa::{Form, ModRm, Op, Size, Width, table::INSTRUCTIONS};
+use crate::semantics::encoder::encode_at;
+use crate::semantics::validator::{DataType, SymbolInfo, SymbolType};
+use std::collections::{BTreeMap, HashMap};
+
+const REG16: [&str; 8] = ["AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI"];
+const REG8: [&str; 8] = ["AL", "CL", "DL", "BL", "AH", "CH", "DH", "BH"];
+const SREG: [&str; 4] = ["ES", "CS", "SS", "DS"];
+
+/// Shortest run of printable bytes shown as a string instead of single bytes.
+const MIN_STRING_RUN: usize = 3;
+
+/// One line of a disassembly.
+#[derive(Debug, Clone)]
+pub struct DisassembledLine {
+    pub address: u64,
+    pub bytes: Vec<u8>,
+    /// Label defined at `address` (`start`, or `L` + address for jump targets).
+    pub label: Option<String>,
+    /// `Statement::Instruction`, or `Statement::Data` for bytes that are not code.
+    pub statement: Statement,
+}
+
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    /// Address of the first byte (100h for a .COM file).
+    pub origin: u64,
+    pub lines: Vec<DisassembledLine>,
+}
+
+/// An instruction decoded at some position of the image.
+#[derive(Debug, Clone)]
+struct Decoded {
+    mnemonic: String,
+    operands: Vec<Operand>,
+    len: usize,
+    /// Absolute address a relative branch goes to.
+    target: Option<u64>,
+    /// Execution never continues with the next instruction.
+    ends_flow: bool,
+}
+
+/// Disassembles `image` loaded at `origin`, starting execution at its first byte.
+pub fn disassemble(image: &[u8], origin: u64) -> Disassembly {
+    let mut code = trace_code(image, origin);
+
+    // Branches need a label where they land: the start of an instruction or a data byte
+    let placeable = |code: &BTreeMap<usize, Decoded>, pos: usize| {
+        pos < image.len()
+            && (code.contains_key(&pos)
+                || code
+                    .range(..pos)
+                    .next_back()
+                    .is_none_or(|(start, d)| start + d.len <= pos))
+    };
+    let unplaceable: Vec<usize> = code
+        .iter()
+        .filter(|(_, d)| {
+            d.target
+                .is_some_and(|t| !position(t, origin).is_some_and(|p| placeable(&code, p)))
+        })
+        .map(|(pos, _)| *pos)
+        .collect();
+    for pos in unplaceable {
+        code.remove(&pos);
+    }
+
+    let mut labels: BTreeMap<usize, String> = code
+        .values()
+        .filter_map(|d| position(d.target?, origin))
+        .map(|pos| (pos, format!("L{:04X}", origin + pos as u64)))
+        .collect();
+    labels.insert(0, "start".to_string());
+    for decoded in code.values_mut() {
+        if let Some(pos) = decoded.target.and_then(|t| position(t, origin)) {
+            decoded.operands = vec![Operand::Label(labels[&pos].clone())];
+        }
+    }
+
+    // Keep only what the encoder reproduces exactly
+    let symbols: HashMap<String, SymbolInfo> = labels
+        .iter()
+        .map(|(pos, name)| (name.clone(), label_symbol(origin + *pos as u64)))
+        .collect();
+    code.retain(|pos, d| {
+        encode_at(&d.mnemonic, &d.operands, &symbols, origin + *pos as u64)
+            == image[*pos..*pos + d.len]
+    });
+
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    while pos < image.len() {
+        let (len, statement) = match code.get(&pos) {
+            Some(d) => (
+                d.len,
+                Statement::Instruction {
+                    mnemonic: d.mnemonic.clone(),
+                    operands: d.operands.clone(),
+                },
+            ),
+            None => data_run(image, pos, |p| {
+                code.contains_key(&p) || labels.contains_key(&p)
+            }),
+        };
+        lines.push(DisassembledLine {
+            address: origin + pos as u64,
+            bytes: image[pos..pos + len].to_vec(),
+            label: labels.get(&pos).cloned(),
+            statement,
+        });
+        pos += len;
+    }
+    Disassembly { origin, lines }
+}
+
+/// Image position of an absolute address.
+fn position(address: u64, origin: u64) -> Option<usize> {
+    address.checked_sub(origin).map(|p| p as usize)
+}
+
+fn label_symbol(offset: u64) -> SymbolInfo {
+    SymbolInfo {
+        type_: SymbolType::Label,
+        data_type: DataType::None,
+        segment: "CODE".to_string(),
+        offset: Some(offset),
+        line_defined: 0,
+        references: Vec::new(),
+        length: 1,
+        value: None,
+    }
+}
+
+/// Follows every path from the entry point; returns the instructions found
+/// by image position. Paths stop at bytes that do not decode or that overlap
+/// an instruction found earlier.
+fn trace_code(image: &[u8], origin: u64) -> BTreeMap<usize, Decoded> {
+    let mut code: BTreeMap<usize, Decoded> = BTreeMap::new();
+    let mut claimed = vec![false; image.len()];
+    let mut pending = vec![0];
+
+    while let Some(mut pos) = pending.pop() {
+        let mut exit_requested = false;
+        while pos < image.len() && !claimed[pos] {
+            let Some(decoded) = decode(image, origin, pos) else {
+                break;
+            };
+            let end = pos + decoded.len;
+            if claimed[pos..end].iter().any(|c| *c) {
+                break;
+            }
+            claimed[pos..end].fill(true);
+            if let Some(target) = decoded.target.and_then(|t| position(t, origin)) {
+                pending.push(target);
+            }
+
+            // INT 20h, or INT 21h right after AH was set to 4Ch, ends the program
+            let ends_flow = decoded.ends_flow
+                || match (decoded.mnemonic.as_str(), decoded.operands.as_slice()) {
+                    ("INT", [Operand::Immediate(0x20, _)]) => true,
+                    ("INT", [Operand::Immediate(0x21, _)]) => exit_requested,
+                    _ => false,
+                };
+            exit_requested = match (decoded.mnemonic.as_str(), decoded.operands.as_slice()) {
+                ("MOV", [Operand::Register(r), Operand::Immediate(v, _)]) if r == "AH" => {
+                    *v == 0x4C
+                }
+                ("MOV", [Operand::Register(r), Operand::Immediate(v, _)]) if r == "AX" => {
+                    *v >> 8 == 0x4C
+                }
+                _ => exit_requested,
+            };
+
+            code.insert(pos, decoded);
+            if ends_flow {
+                break;
+            }
+            pos = end;
+        }
+    }
+    code
+}
+
+/// Bytes from `pos` up to the next code or label (`stop`), as one `DB`:
+/// a run of printable characters, or else a single byte.
+fn data_run(image: &[u8], pos: usize, stop: impl Fn(usize) -> bool) -> (usize, Statement) {
+    let printable = |b: u8| (0x20..=0x7E).contains(&b) && b != b'\'';
+    let mut end = pos;
+    while end < image.len() && printable(image[end]) && (end == pos || !stop(end)) {
+        end += 1;
+    }
+    let value = if end - pos >= MIN_STRING_RUN {
+        Operand::StringLiteral(String::from_utf8_lossy(&image[pos..end]).into_owned())
+    } else {
+        end = pos + 1;
+        immediate(image[pos] as u64)
+    };
+    (
+        end - pos,
+        Statement::Data {
+            directive: "DB".to_string(),
+            value,
+        },
+    )
+}
+
+/// A number written the way the lexer reads it back: decimal below 10,
+/// otherwise hex with a leading digit (`0FFh`).
+fn immediate(value: u64) -> Operand {
+    let text = if value < 10 {
+        value.to_string()
+    } else {
+        let hex = format!("{:X}h", value);
+        if hex.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            format!("0{}", hex)
+        } else {
+            hex
+        }
+    };
+    Operand::Immediate(value, text)
+}
+
+fn decode(image: &[u8], origin: u64, pos: usize) -> Option<Decoded> {
+    let mut at = pos;
+    let mut segment = None;
+    let mut rep = None;
+    if let b @ (0x26 | 0x2E | 0x36 | 0x3E) = *image.get(at)? {
+        segment = Some(SREG[((b >> 3) & 3) as usize]);
+        at += 1;
+    }
+    // REP only combines with string instructions; alone it is its own statement
+    if let (Some(&b @ (0xF2 | 0xF3)), Some(&next)) = (image.get(at), image.get(at + 1))
+        && matches!(next, 0xA4..=0xA7 | 0xAA..=0xAF)
+    {
+        rep = Some(b);
+        at += 1;
+    }
+
+    // Implied forms first so 90h is NOP rather than XCHG AX, AX
+    let forms = || {
+        INSTRUCTIONS
+            .iter()
+            .flat_map(|i| i.forms.iter().map(move |f| (i, f)))
+    };
+    let (instruction, mut operands, end, relative) = forms()
+        .filter(|(_, f)| f.operands.is_empty())
+        .chain(forms().filter(|(_, f)| !f.operands.is_empty()))
+        .find_map(|(i, f)| decode_form(image, at, f).map(|(ops, end, rel)| (i, ops, end, rel)))?;
+
+    if let Some(name) = segment {
+        let memory = operands.iter_mut().find_map(|op| match op {
+            Operand::Memory { segment, .. } => Some(segment),
+            Operand::Ptr { target, .. } => match target.as_mut() {
+                Operand::Memory { segment, .. } => Some(segment),
+                _ => None,
+            },
+            _ => None,
+        })?;
+        *memory = Some(name.to_string());
+    }
+
+    let mut mnemonic = instruction.mnemonic.to_string();
+    if let Some(prefix) = rep {
+        let compares = matches!(mnemonic.as_str(), "CMPSB" | "CMPSW" | "SCASB" | "SCASW");
+        let prefix = match (prefix, compares) {
+            (0xF2, _) => "REPNE",
+            (_, true) => "REPE",
+            _ => "REP",
+        };
+        mnemonic = format!("{} {}", prefix, mnemonic);
+    }
+
+    // Relative displacements count from the end of the instruction, within the segment
+    let target = relative.map(|disp| (origin as i64 + end as i64 + disp) as u64 & 0xFFFF);
+    let ends_flow = matches!(
+        instruction.mnemonic,
+        "JMP" | "RET" | "RETF" | "IRET" | "HLT"
+    );
+    Some(Decoded {
+        mnemonic,
+        operands,
+        len: end - pos,
+        target,
+        ends_flow,
+    })
+}
+
+/// Operands if the bytes at `at` are an encoding of `form`, the position
+/// after the instruction and the displacement of a relative branch.
+fn decode_form(image: &[u8], at: usize, form: &Form) -> Option<(Vec<Operand>, usize, Option<i64>)> {
+    let n = form.opcode.len();
+    let bytes = image.get(at..at + n)?;
+    if bytes[..n - 1] != form.opcode[..n - 1] {
+        return None;
+    }
+    let (last, base) = (bytes[n - 1], form.opcode[n - 1]);
+
+    let fixed = if form.size == Size::Byte {
+        Width::Byte
+    } else {
+        Width::Word
+    };
+    let mut opcode_reg = None;
+    let width = if form.reg_in_opcode {
+        if form.operands.contains(&Op::Sreg) {
+            (last & !0x18 == base).then_some(())?;
+            opcode_reg = Some((last >> 3) & 3);
+        } else {
+            (last & !7 == base).then_some(())?;
+            opcode_reg = Some(last & 7);
+        }
+        fixed
+    } else if form.size == Size::WidthBit {
+        (last & !1 == base).then_some(())?;
+        if last & 1 == 1 {
+            Width::Word
+        } else {
+            Width::Byte
+        }
+    } else {
+        (last == base).then_some(())?;
+        fixed
+    };
+
+    let mut pos = at + n;
+    let mut modrm = None;
+    if form.modrm != ModRm::None {
+        let byte = *image.get(pos)?;
+        pos += 1;
+        if let ModRm::Digit(digit) = form.modrm
+            && (byte >> 3) & 7 != digit
+        {
+            return None;
+        }
+        modrm = Some((byte, decode_rm(image, &mut pos, byte, width)?));
+    }
+    let reg_field = modrm.as_ref().map(|(byte, _)| (byte >> 3) & 7);
+
+    let register = |code: u8, width: Width| {
+        let names = if width == Width::Word { REG16 } else { REG8 };
+        Operand::Register(names[code as usize].to_string())
+    };
+    let mut read = |len: usize| -> Option<u64> {
+        let bytes = image.get(pos..pos + len)?;
+        pos += len;
+        Some(bytes.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64))
+    };
+
+    let mut operands = Vec::new();
+    let mut relative = None;
+    for op in form.operands {
+        let operand = match op {
+            Op::Reg => register(opcode_reg.or(reg_field)?, width),
+            Op::Sreg => {
+                let code = opcode_reg.or(reg_field)?;
+                Operand::Register(SREG.get(code as usize)?.to_string())
+            }
+            Op::RegMem => modrm.as_ref()?.1.clone(),
+            Op::Mem => match &modrm.as_ref()?.1 {
+                memory @ Operand::Memory { .. } => memory.clone(),
+                _ => return None,
+            },
+            Op::Acc => register(0, width),
+            Op::Moffs => memory(None, None, read(2)? as i64),
+            Op::Imm => immediate(read(if width == Width::Word { 2 } else { 1 })?),
+            Op::Imm8 => immediate(read(1)?),
+            Op::Imm16 => immediate(read(2)?),
+            Op::SImm8 => immediate(read(1)? as u8 as i8 as i16 as u16 as u64),
+            Op::One => immediate(1),
+            Op::Three => immediate(3),
+            Op::Cl => register(1, Width::Byte),
+            Op::Dx => register(2, Width::Word),
+            Op::Rel8 => {
+                relative = Some(read(1)? as u8 as i8 as i64);
+                Operand::Uninitialized
+            }
+            Op::Rel16 => {
+                relative = Some(read(2)? as u16 as i16 as i64);
+                Operand::Uninitialized
+            }
+            // A segment:offset pointer has no source form without the
+            // procedure it names: leave the bytes as data
+            Op::FarPtr => return None,
+        };
+        operands.push(operand);
+    }
+
+    // Without a register operand the size has to be spelled out
+    let sized_by_register = form
+        .operands
+        .iter()
+        .any(|op| matches!(op, Op::Reg | Op::Acc | Op::Sreg));
+    if !sized_by_register {
+        for operand in &mut operands {
+            if matches!(operand, Operand::Memory { .. }) {
+                let size = if width == Width::Word { "WORD" } else { "BYTE" };
+                *operand = Operand::Ptr {
+                    size: size.to_string(),
+                    target: Box::new(operand.clone()),
+                };
+            }
+        }
+    }
+    Some((operands, pos, relative))
+}
+
+/// The r/m operand of a ModR/M byte, reading its displacement.
+fn decode_rm(image: &[u8], pos: &mut usize, modrm: u8, width: Width) -> Option<Operand> {
+    let (mode, rm) = (modrm >> 6, modrm & 7);
+    if mode == 3 {
+        let names = if width == Width::Word { REG16 } else { REG8 };
+        return Some(Operand::Register(names[rm as usize].to_string()));
+    }
+    let mut read = |len: usize| -> Option<u64> {
+        let bytes = image.get(*pos..*pos + len)?;
+        *pos += len;
+        Some(bytes.iter().rev().fold(0, |acc, b| (acc << 8) | *b as u64))
+    };
+    if mode == 0 && rm == 6 {
+        return Some(memory(None, None, read(2)? as i64));
+    }
+    let (base, index) = match rm {
+        0 => (Some("BX"), Some("SI")),
+        1 => (Some("BX"), Some("DI")),
+        2 => (Some("BP"), Some("SI")),
+        3 => (Some("BP"), Some("DI")),
+        4 => (None, Some("SI")),
+        5 => (None, Some("DI")),
+        6 => (Some("BP"), None),
+        _ => (Some("BX"), None),
+    };
+    let displacement = match mode {
+        0 => 0,
+        1 => read(1)? as u8 as i8 as i64,
+        _ => read(2)? as i64,
+    };
+    Some(memory(base, index, displacement))
+}
+
+fn memory(base: Option<&str>, index: Option<&str>, displacement: i64) -> Operand {
+    Operand::Memory {
+        segment: None,
+        base: base.map(str::to_string),
+        index: index.map(str::to_string),
+        displacement: Some(Expr::Number(displacement)),
+    }
+}
+
+impl Disassembly {
+    /// Glyph source for the disassembly; with origin 100h it assembles back
+    /// to the same bytes as a .COM program.
+    pub fn to_source(&self) -> String {
+        let size: usize = self.lines.iter().map(|l| l.bytes.len()).sum();
+        let mut out = format!(
+            "; Disassembly of {} bytes at {:04X}h\n.code segment\n    org {}\n",
+            size,
+            self.origin,
+            immediate_text(self.origin)
+        );
+        if self.lines.is_empty() {
+            out.push_str("start:\n");
+        }
+        for line in &self.lines {
+            if let Some(label) = &line.label {
+                out.push_str(&format!("{}:\n", label));
+            }
+            let text = format!("    {}", statement_text(&line.statement));
+            out.push_str(&format!(
+                "{:<36}; {:04X}  {}\n",
+                text,
+                line.address,
+                crate::semantics::encoder::to_hex(&line.bytes)
+            ));
+        }
+        out.push_str("ends\nend start\n");
+        out
+    }
+}
+
+fn immediate_text(value: u64) -> String {
+    match immediate(value) {
+        Operand::Immediate(_, text) => text,
+        _ => value.to_string(),
+    }
+}
+
+/// MASM text of a statement, as listed for macro expansions.
+pub fn statement_text(statement: &Statement) -> String {
+    match statement {
+        Statement::Instruction { mnemonic, operands } if operands.is_empty() => {
+            mnemonic.to_lowercase()
+        }
+        Statement::Instruction { mnemonic, operands } => format!(
+            "{} {}",
+            mnemonic.to_lowercase(),
+            operands
+                .iter()
+                .map(operand_text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Statement::Data { directive, value } => {
+            format!("{} {}", directive.to_lowercase(), operand_text(value))
+        }
+        Statement::Label(name) => format!("{}:", name),
+        Statement::Variable {
+            name,
+            directive,
+            value,
+        } => format!(
+            "{} {} {}",
+            name,
+            directive.to_lowercase(),
+            operand_text(value)
+        ),
+        Statement::Constant {
+            name,
+            value,
+            redefinable,
+        } => format!(
+            "{} {} {}",
+            name,
+            if *redefinable { "=" } else { "equ" },
+            operand_text(value)
+        ),
+        Statement::Directive { name, args } if args.is_empty() => name.to_lowercase(),
+        Statement::Directive { name, args } => format!(
+            "{} {}",
+            name.to_lowercase(),
+            args.iter().map(operand_text).collect::<Vec<_>>().join(", ")
+        ),
+        Statement::Procedure { name, distance } => {
+            format!("{} proc {}", name, distance.to_string().to_lowercase())
+        }
+        Statement::ProcedureEnd { name } => format!("{} endp", name),
+        Statement::Segment { name } => name.to_lowercase(),
+        Statement::SegmentEnd => "ends".to_string(),
+        Statement::End { label: Some(label) } => format!("end {}", label),
+        Statement::End { label: None } => "end".to_string(),
+        Statement::Unknown => String::new(),
+    }
+}
+
+pub fn operand_text(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(r) => r.to_lowercase(),
+        Operand::Immediate(_, text) => text.clone(),
+        Operand::Memory {
+            segment,
+            base,
+            index,
+            displacement,
+        } => {
+            let registers: Vec<String> = base
+                .iter()
+                .chain(index.iter())
+                .map(|r| r.to_lowercase())
+                .collect();
+            let mut text = registers.join("+");
+            match displacement {
+                Some(Expr::Number(d)) if *d < 0 => {
+                    text.push_str(&format!("-{}", immediate_text(d.unsigned_abs())));
+                }
+                Some(Expr::Number(d)) if *d == 0 && !registers.is_empty() => {}
+                Some(expr) => {
+                    if !registers.is_empty() {
+                        text.push('+');
+                    }
+                    text.push_str(&expression_text(expr));
+                }
+                None => {}
+            }
+            match segment {
+                Some(seg) => format!("{}:[{}]", seg.to_lowercase(), text),
+                None => format!("[{}]", text),
+            }
+        }
+        Operand::Ptr { size, target } => {
+            format!("{} ptr {}", size.to_lowercase(), operand_text(target))
+        }
+        Operand::Label(name) => name.clone(),
+        Operand::StringLiteral(s) => format!("'{}'", s),
+        Operand::Dup { count, value } => {
+            format!("{} dup({})", expression_text(count), operand_text(value))
+        }
+        Operand::Uninitialized => "?".to_string(),
+        Operand::Expression(expr) => expression_text(expr),
+    }
+}
+
+fn expression_text(expr: &Expr) -> String {
+    // Operands of an operator need parentheses when they bind more loosely
+    let operand = |e: &Expr, weakest: u8| match e {
+        Expr::Binary { op, .. } if precedence(*op) < weakest => {
+            format!("({})", expression_text(e))
+        }
+        // NOT sits between AND and + / -
+        Expr::Unary {
+            op: UnaryOp::Not, ..
+        } if weakest > 1 => format!("({})", expression_text(e)),
+        _ => expression_text(e),
+    };
+    match expr {
+        Expr::Number(value) if *value < 0 => format!("-{}", immediate_text(value.unsigned_abs())),
+        Expr::Number(value) => immediate_text(*value as u64),
+        Expr::Symbol(name) => name.clone(),
+        Expr::Here => "$".to_string(),
+        Expr::Unary {
+            op: UnaryOp::Negate,
+            operand: inner,
+        } => format!("-{}", operand(inner, u8::MAX)),
+        Expr::Unary { op, operand: inner } => {
+            format!(
+                "{} {}",
+                op.to_string().to_lowercase(),
+                operand(inner, u8::MAX)
+            )
+        }
+        Expr::Binary { op, left, right } => format!(
+            "{} {} {}",
+            operand(left, precedence(*op)),
+            op.to_string().to_lowercase(),
+            operand(right, precedence(*op) + 1)
+        ),
+    }
+}
+
+/// Binding strength of a binary operator, as the parser groups them.
+fn precedence(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or | BinaryOp::Xor => 0,
+        BinaryOp::And => 1,
+        BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            2
+        }
+        BinaryOp::Add | BinaryOp::Sub => 3,
+        _ => 4,
+    }
+}
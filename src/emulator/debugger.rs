@@ -0,0 +1,484 @@
+// src/emulator/debugger.rs
+//! Source-level debugging on top of `Machine`.
+//!
+//! A `SourceMap` ties every statement that produced bytes to the address it
+//! was loaded at, so breakpoints can be set by line or label and every stop
+//! reports the source line being executed.
+
+use super::memory::physical;
+use super::registers::{CS, DS, ES, Registers, SP, SS};
+use super::{EmulatorError, LOAD_SEGMENT, Machine, PSP_PARAGRAPHS};
+use crate::ast::{LineNode, Program, Statement};
+use crate::semantics::validator::{DataType, SymbolInfo, SymbolType, segment_kind};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Where the statements of an assembled program live once loaded.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// Physical address of each statement's first byte -> 1-based source line.
+    lines: BTreeMap<usize, usize>,
+    /// Label and variable names (uppercase) -> (segment, offset, is a word).
+    symbols: HashMap<String, (u16, u16, bool)>,
+}
+
+impl SourceMap {
+    /// Builds the map for a program loaded by `Machine::load_com` (`com`) or
+    /// `Machine::load_exe`, using the addresses `pass_one` assigned. `lines`
+    /// holds the source line of each statement.
+    pub fn new(
+        lines: &[usize],
+        program: &Program,
+        address_map: &HashMap<usize, u64>,
+        machine_code_map: &HashMap<usize, Vec<u8>>,
+        symbols: &HashMap<String, SymbolInfo>,
+        com: bool,
+    ) -> Self {
+        // Segment each kind of segment ends up at in memory
+        let segment_of = |kind: &str| -> u16 {
+            if com {
+                return LOAD_SEGMENT;
+            }
+            let frame = symbols
+                .get(kind)
+                .filter(|s| matches!(s.type_, SymbolType::Segment))
+                .and_then(|s| s.offset)
+                .unwrap_or(0);
+            (LOAD_SEGMENT + PSP_PARAGRAPHS).wrapping_add(frame as u16)
+        };
+
+        let mut map = SourceMap::default();
+        let mut segment = segment_of("CODE");
+        for (index, spanned) in program.iter().enumerate() {
+            if let LineNode::Statement(Statement::Segment { name }) = &spanned.node
+                && let Some(kind) = segment_kind(name)
+            {
+                segment = segment_of(kind);
+            }
+            if machine_code_map.get(&index).is_none_or(|b| b.is_empty()) {
+                continue;
+            }
+            let Some(&address) = address_map.get(&index) else {
+                continue;
+            };
+            let line = lines.get(index).copied().unwrap_or(index + 1);
+            map.lines
+                .entry(physical(segment, address as u16))
+                .or_insert(line);
+        }
+
+        for (name, info) in symbols {
+            if !matches!(
+                info.type_,
+                SymbolType::Label | SymbolType::Procedure(_) | SymbolType::Variable
+            ) {
+                continue;
+            }
+            if let Some(offset) = info.offset {
+                map.symbols.insert(
+                    name.to_uppercase(),
+                    (
+                        segment_of(&info.segment),
+                        offset as u16,
+                        !matches!(info.data_type, DataType::Byte),
+                    ),
+                );
+            }
+        }
+        map
+    }
+
+    /// Source line of the instruction starting at `segment:offset`.
+    pub fn line_at(&self, segment: u16, offset: u16) -> Option<usize> {
+        self.lines.get(&physical(segment, offset)).copied()
+    }
+
+    /// First statement with code on `line` or, if it has none, on the
+    /// closest line after it: (physical address, line).
+    fn resolve_line(&self, line: usize) -> Option<(usize, usize)> {
+        self.lines
+            .iter()
+            .filter(|(_, l)| **l >= line)
+            .min_by_key(|(address, l)| (**l, **address))
+            .map(|(address, l)| (*address, *l))
+    }
+
+    /// Code following a label: (physical address, line).
+    fn resolve_label(&self, name: &str) -> Option<(usize, usize)> {
+        let (segment, offset, _) = self.symbols.get(&name.to_uppercase())?;
+        let address = physical(*segment, *offset);
+        self.lines
+            .range(address..)
+            .next()
+            .map(|(address, line)| (*address, *line))
+    }
+}
+
+/// A value shown at every stop; `run` stops when one changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watch {
+    /// AX..DI, AL..BH, CS/DS/ES/SS, IP or FLAGS.
+    Register(String),
+    /// A byte or word at `segment:offset`.
+    Memory {
+        name: String,
+        segment: u16,
+        offset: u16,
+        word: bool,
+    },
+}
+
+impl Watch {
+    fn name(&self) -> &str {
+        match self {
+            Watch::Register(name) | Watch::Memory { name, .. } => name,
+        }
+    }
+
+    fn read(&self, machine: &Machine) -> u16 {
+        match self {
+            Watch::Register(name) => register_value(&machine.registers, name).unwrap_or(0),
+            Watch::Memory {
+                segment,
+                offset,
+                word: true,
+                ..
+            } => machine.memory.read_word(*segment, *offset),
+            Watch::Memory {
+                segment, offset, ..
+            } => machine.memory.read_byte(*segment, *offset) as u16,
+        }
+    }
+}
+
+/// Register by name, as written in source.
+fn register_value(registers: &Registers, name: &str) -> Option<u16> {
+    const WORDS: [&str; 8] = ["AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI"];
+    const BYTES: [&str; 8] = ["AL", "CL", "DL", "BL", "AH", "CH", "DH", "BH"];
+    let upper = name.to_uppercase();
+    if let Some(code) = WORDS.iter().position(|r| *r == upper) {
+        return Some(registers.general[code]);
+    }
+    if let Some(code) = BYTES.iter().position(|r| *r == upper) {
+        return Some(registers.byte(code as u8) as u16);
+    }
+    match upper.as_str() {
+        "ES" => Some(registers.segment[ES]),
+        "CS" => Some(registers.segment[CS]),
+        "SS" => Some(registers.segment[SS]),
+        "DS" => Some(registers.segment[DS]),
+        "IP" => Some(registers.ip),
+        "FLAGS" => Some(registers.flags),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// One instruction (or one stepped-over CALL) completed.
+    Step,
+    Breakpoint,
+    /// Reached the line given to `run_to_line`.
+    Cursor,
+    WatchChanged,
+    Halted,
+    StepLimit,
+    WaitingForInput,
+    /// Moved back through the recorded trace.
+    Rewound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WatchValue {
+    pub name: String,
+    pub value: u16,
+    /// Differs from the value at the previous stop.
+    pub changed: bool,
+}
+
+/// Where and why execution stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stop {
+    pub reason: StopReason,
+    /// Source line at CS:IP, when CS:IP is the start of a statement.
+    pub line: Option<usize>,
+    pub cs: u16,
+    pub ip: u16,
+    pub watches: Vec<WatchValue>,
+}
+
+/// A debugging session: a loaded machine plus breakpoints and watches.
+#[derive(Debug)]
+pub struct Debugger {
+    pub machine: Machine,
+    map: SourceMap,
+    /// Physical addresses.
+    breakpoints: BTreeSet<usize>,
+    watches: Vec<(Watch, u16)>,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine, map: SourceMap) -> Self {
+        Debugger {
+            machine,
+            map,
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+        }
+    }
+
+    /// Source line at CS:IP.
+    pub fn current_line(&self) -> Option<usize> {
+        let r = &self.machine.registers;
+        self.map.line_at(r.segment[CS], r.ip)
+    }
+
+    /// Sets a breakpoint on `line`, or on the next line with code. Returns
+    /// the line it ended up on.
+    pub fn add_line_breakpoint(&mut self, line: usize) -> Option<usize> {
+        let (address, line) = self.map.resolve_line(line)?;
+        self.breakpoints.insert(address);
+        Some(line)
+    }
+
+    /// Sets a breakpoint on the code following `label`. Returns its line.
+    pub fn add_label_breakpoint(&mut self, label: &str) -> Option<usize> {
+        let (address, line) = self.map.resolve_label(label)?;
+        self.breakpoints.insert(address);
+        Some(line)
+    }
+
+    /// Removes the breakpoint `add_line_breakpoint(line)` would set.
+    pub fn remove_line_breakpoint(&mut self, line: usize) -> bool {
+        self.map
+            .resolve_line(line)
+            .is_some_and(|(address, _)| self.breakpoints.remove(&address))
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Lines that have a breakpoint, in order.
+    pub fn breakpoint_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .filter_map(|address| self.map.lines.get(address).copied())
+            .collect();
+        lines.sort_unstable();
+        lines
+    }
+
+    /// Watches a register by name. Returns `false` for unknown names.
+    pub fn watch_register(&mut self, name: &str) -> bool {
+        if register_value(&self.machine.registers, name).is_none() {
+            return false;
+        }
+        self.add_watch(Watch::Register(name.to_uppercase()));
+        true
+    }
+
+    /// Watches a byte or word of memory.
+    pub fn watch_memory(&mut self, segment: u16, offset: u16, word: bool) {
+        let name = format!("{:04X}:{:04X}", segment, offset);
+        self.add_watch(Watch::Memory {
+            name,
+            segment,
+            offset,
+            word,
+        });
+    }
+
+    /// Watches a variable (or label) by name, sized by its definition.
+    /// Returns `false` when the program defines no such symbol.
+    pub fn watch_variable(&mut self, name: &str) -> bool {
+        let Some(&(segment, offset, word)) = self.map.symbols.get(&name.to_uppercase()) else {
+            return false;
+        };
+        self.add_watch(Watch::Memory {
+            name: name.to_uppercase(),
+            segment,
+            offset,
+            word,
+        });
+        true
+    }
+
+    fn add_watch(&mut self, watch: Watch) {
+        let value = watch.read(&self.machine);
+        self.watches.push((watch, value));
+    }
+
+    pub fn remove_watch(&mut self, name: &str) -> bool {
+        let before = self.watches.len();
+        self.watches
+            .retain(|(watch, _)| !watch.name().eq_ignore_ascii_case(name));
+        self.watches.len() != before
+    }
+
+    /// Current watch values without recording them as seen.
+    pub fn watch_values(&self) -> Vec<WatchValue> {
+        self.watches
+            .iter()
+            .map(|(watch, last)| {
+                let value = watch.read(&self.machine);
+                WatchValue {
+                    name: watch.name().to_string(),
+                    value,
+                    changed: value != *last,
+                }
+            })
+            .collect()
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self) -> Result<Stop, EmulatorError> {
+        Ok(match self.advance()? {
+            Some(reason) => self.stop(reason),
+            None => self.stop(StopReason::Step),
+        })
+    }
+
+    /// Like `step`, but a CALL runs until it returns (or something inside
+    /// it stops execution first).
+    pub fn step_over(&mut self, max_steps: u64) -> Result<Stop, EmulatorError> {
+        if !self.at_call() {
+            return self.step();
+        }
+        let sp = self.machine.registers.general[SP];
+        if let Some(reason) = self.advance()? {
+            return Ok(self.stop(reason));
+        }
+        // The CALL pushed its return address; it is reached again once the
+        // stack is back where it was.
+        let r = &self.machine.registers;
+        let (ss, top) = (r.segment[SS], r.general[SP]);
+        let return_ip = self.machine.memory.read_word(ss, top);
+        let return_cs = if sp.wrapping_sub(top) == 4 {
+            self.machine.memory.read_word(ss, top.wrapping_add(2))
+        } else {
+            r.segment[CS]
+        };
+        let target = physical(return_cs, return_ip);
+        self.resume(max_steps, |r| {
+            (physical(r.segment[CS], r.ip) == target && r.general[SP] == sp)
+                .then_some(StopReason::Step)
+        })
+    }
+
+    /// Runs until a breakpoint, a watch change, the end of the program or
+    /// `max_steps` instructions.
+    pub fn run(&mut self, max_steps: u64) -> Result<Stop, EmulatorError> {
+        self.resume(max_steps, |_| None)
+    }
+
+    /// Runs until the code on `line` (or the next line with code) is about
+    /// to execute, stopping earlier at breakpoints and watch changes.
+    pub fn run_to_line(
+        &mut self,
+        line: usize,
+        max_steps: u64,
+    ) -> Result<Option<Stop>, EmulatorError> {
+        let Some((target, _)) = self.map.resolve_line(line) else {
+            return Ok(None);
+        };
+        self.resume(max_steps, |r| {
+            (physical(r.segment[CS], r.ip) == target).then_some(StopReason::Cursor)
+        })
+        .map(Some)
+    }
+
+    /// Goes back to the state before instruction number `step`; needs a
+    /// trace started with `Machine::start_trace`.
+    pub fn rewind_to(&mut self, step: u64) -> Result<Stop, EmulatorError> {
+        self.machine.rewind_to(step)?;
+        Ok(self.stop(StopReason::Rewound))
+    }
+
+    /// Undoes the last `count` instructions.
+    pub fn step_back(&mut self, count: u64) -> Result<Stop, EmulatorError> {
+        self.rewind_to(self.machine.steps.saturating_sub(count))
+    }
+
+    /// The recorded trace as JSON lines, each tied to its source line.
+    pub fn trace_json_lines(&self) -> Option<String> {
+        let trace = self.machine.trace.as_ref()?;
+        Some(trace.to_json_lines(|cs, ip| self.map.line_at(cs, ip)))
+    }
+
+    /// Steps until `target` or another stop condition says so. The
+    /// instruction at the starting CS:IP always runs, so resuming from a
+    /// breakpoint does not stop on it again.
+    fn resume(
+        &mut self,
+        max_steps: u64,
+        target: impl Fn(&Registers) -> Option<StopReason>,
+    ) -> Result<Stop, EmulatorError> {
+        for _ in 0..max_steps {
+            if let Some(reason) = self.advance()? {
+                return Ok(self.stop(reason));
+            }
+            let r = &self.machine.registers;
+            if let Some(reason) = target(r) {
+                return Ok(self.stop(reason));
+            }
+            if self.breakpoints.contains(&physical(r.segment[CS], r.ip)) {
+                return Ok(self.stop(StopReason::Breakpoint));
+            }
+            if self.watch_values().iter().any(|w| w.changed) {
+                return Ok(self.stop(StopReason::WatchChanged));
+            }
+        }
+        Ok(self.stop(StopReason::StepLimit))
+    }
+
+    /// Executes one instruction; `Some` when the program cannot go on.
+    fn advance(&mut self) -> Result<Option<StopReason>, EmulatorError> {
+        if self.machine.halted {
+            return Ok(Some(StopReason::Halted));
+        }
+        match self.machine.step() {
+            Ok(()) if self.machine.halted => Ok(Some(StopReason::Halted)),
+            Ok(()) => Ok(None),
+            Err(EmulatorError::WaitingForInput) => Ok(Some(StopReason::WaitingForInput)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether the instruction at CS:IP (after any prefixes) is a CALL.
+    fn at_call(&self) -> bool {
+        let r = &self.machine.registers;
+        let (cs, mut ip) = (r.segment[CS], r.ip);
+        let mut opcode = self.machine.memory.read_byte(cs, ip);
+        while matches!(opcode, 0x26 | 0x2E | 0x36 | 0x3E | 0xF0 | 0xF2 | 0xF3) {
+            ip = ip.wrapping_add(1);
+            opcode = self.machine.memory.read_byte(cs, ip);
+        }
+        match opcode {
+            0xE8 | 0x9A => true,
+            0xFF => {
+                let modrm = self.machine.memory.read_byte(cs, ip.wrapping_add(1));
+                matches!((modrm >> 3) & 7, 2 | 3)
+            }
+            _ => false,
+        }
+    }
+
+    /// Reports the current position and marks the watch values as seen.
+    fn stop(&mut self, reason: StopReason) -> Stop {
+        let watches = self.watch_values();
+        for ((_, last), current) in self.watches.iter_mut().zip(&watches) {
+            *last = current.value;
+        }
+        let r = &self.machine.registers;
+        Stop {
+            reason,
+            line: self.current_line(),
+            cs: r.segment[CS],
+            ip: r.ip,
+            watches,
+        }
+    }
+}
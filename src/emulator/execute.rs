@@ -0,0 +1,984 @@
+// src/emulator/execute.rs
+use super::memory::physical;
+use super::registers::*;
+use super::{EmulatorError, Machine};
+
+/// Where an operand lives once its ModR/M byte has been decoded.
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Register(u8),
+    Memory(u16, u16),
+}
+
+struct ModRm {
+    reg: u8,
+    rm: Location,
+}
+
+/// Repeat prefix in effect for a string instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rep {
+    /// F3: REP / REPE / REPZ
+    WhileEqual,
+    /// F2: REPNE / REPNZ
+    WhileNotEqual,
+}
+
+/// Prefixes collected before the opcode.
+#[derive(Debug, Clone, Copy, Default)]
+struct Prefixes {
+    segment: Option<usize>,
+    rep: Option<Rep>,
+}
+
+impl Machine {
+    /// Fetches, decodes and executes one instruction (prefixes included).
+    ///
+    /// On error the instruction pointer is left at the faulting instruction.
+    pub(super) fn execute(&mut self) -> Result<(), EmulatorError> {
+        let start = self.registers.ip;
+        let trap = self.registers.flag(TF);
+
+        let mut prefixes = Prefixes::default();
+        let opcode = loop {
+            match self.fetch_byte() {
+                byte @ (0x26 | 0x2E | 0x36 | 0x3E) => {
+                    prefixes.segment = Some(((byte >> 3) & 3) as usize)
+                }
+                0xF2 => prefixes.rep = Some(Rep::WhileNotEqual),
+                0xF3 => prefixes.rep = Some(Rep::WhileEqual),
+                0xF0 => {} // LOCK: a single CPU has nothing to lock
+                byte => break byte,
+            }
+        };
+
+        let result = self
+            .dispatch(opcode, prefixes)
+            .and_then(|_| if trap { self.interrupt(1) } else { Ok(()) });
+        if let Err(err) = &result {
+            self.registers.ip = start;
+            if let EmulatorError::InvalidOpcode { opcode: op, .. } = err {
+                return Err(EmulatorError::InvalidOpcode {
+                    segment: self.registers.segment[CS],
+                    offset: start,
+                    opcode: *op,
+                });
+            }
+        }
+        result
+    }
+
+    fn dispatch(&mut self, opcode: u8, prefixes: Prefixes) -> Result<(), EmulatorError> {
+        let word = opcode & 1 == 1;
+        let seg = prefixes.segment;
+
+        match opcode {
+            // ADD/OR/ADC/SBB/AND/SUB/XOR/CMP in their six encodings
+            0x00..=0x3F if opcode & 7 < 6 => {
+                let op = opcode >> 3;
+                match opcode & 7 {
+                    0 | 1 => {
+                        let m = self.decode_modrm(seg);
+                        let a = self.read(m.rm, word);
+                        let b = self.read(Location::Register(m.reg), word);
+                        let r = self.alu(op, a, b, word);
+                        if op != 7 {
+                            self.write(m.rm, word, r);
+                        }
+                    }
+                    2 | 3 => {
+                        let m = self.decode_modrm(seg);
+                        let a = self.read(Location::Register(m.reg), word);
+                        let b = self.read(m.rm, word);
+                        let r = self.alu(op, a, b, word);
+                        if op != 7 {
+                            self.write(Location::Register(m.reg), word, r);
+                        }
+                    }
+                    _ => {
+                        let a = self.read(Location::Register(AX as u8), word);
+                        let b = self.fetch(word);
+                        let r = self.alu(op, a, b, word);
+                        if op != 7 {
+                            self.write(Location::Register(AX as u8), word, r);
+                        }
+                    }
+                }
+            }
+            0x06 | 0x0E | 0x16 | 0x1E => {
+                let value = self.registers.segment[(opcode >> 3) as usize];
+                self.push(value);
+            }
+            0x07 | 0x17 | 0x1F => {
+                let value = self.pop();
+                self.registers.segment[(opcode >> 3) as usize] = value;
+            }
+            0x27 => self.daa(),
+            0x2F => self.das(),
+            0x37 => self.aaa(),
+            0x3F => self.aas(),
+            0x40..=0x47 => {
+                let r = (opcode & 7) as usize;
+                let value = self.registers.general[r];
+                self.registers.general[r] = self.inc_dec(value, false, true);
+            }
+            0x48..=0x4F => {
+                let r = (opcode & 7) as usize;
+                let value = self.registers.general[r];
+                self.registers.general[r] = self.inc_dec(value, true, true);
+            }
+            0x50..=0x57 => {
+                // PUSH SP stores the already decremented SP on the 8086
+                let r = (opcode & 7) as usize;
+                self.registers.general[SP] = self.registers.general[SP].wrapping_sub(2);
+                let (ss, sp) = (self.registers.segment[SS], self.registers.general[SP]);
+                let value = self.registers.general[r];
+                self.store_word(ss, sp, value);
+            }
+            0x58..=0x5F => {
+                let value = self.pop();
+                self.registers.general[(opcode & 7) as usize] = value;
+            }
+            0x70..=0x7F => {
+                let disp = self.fetch_byte() as i8;
+                if self.condition(opcode & 0x0F) {
+                    self.jump_relative(disp as i16);
+                }
+            }
+            0x80..=0x83 => {
+                let m = self.decode_modrm(seg);
+                let a = self.read(m.rm, word);
+                let b = match opcode {
+                    0x81 => self.fetch_word(),
+                    0x83 => self.fetch_byte() as i8 as i16 as u16,
+                    _ => self.fetch_byte() as u16,
+                };
+                let r = self.alu(m.reg, a, b, word);
+                if m.reg != 7 {
+                    self.write(m.rm, word, r);
+                }
+            }
+            0x84 | 0x85 => {
+                let m = self.decode_modrm(seg);
+                let a = self.read(m.rm, word);
+                let b = self.read(Location::Register(m.reg), word);
+                self.logic(a & b, word);
+            }
+            0x86 | 0x87 => {
+                let m = self.decode_modrm(seg);
+                let a = self.read(m.rm, word);
+                let b = self.read(Location::Register(m.reg), word);
+                self.write(m.rm, word, b);
+                self.write(Location::Register(m.reg), word, a);
+            }
+            0x88 | 0x89 => {
+                let m = self.decode_modrm(seg);
+                let value = self.read(Location::Register(m.reg), word);
+                self.write(m.rm, word, value);
+            }
+            0x8A | 0x8B => {
+                let m = self.decode_modrm(seg);
+                let value = self.read(m.rm, word);
+                self.write(Location::Register(m.reg), word, value);
+            }
+            0x8C => {
+                let m = self.decode_modrm(seg);
+                let value = self.registers.segment[(m.reg & 3) as usize];
+                self.write(m.rm, true, value);
+            }
+            0x8D => {
+                let m = self.decode_modrm(seg);
+                let Location::Memory(_, offset) = m.rm else {
+                    return Err(invalid(opcode));
+                };
+                self.registers.general[m.reg as usize] = offset;
+            }
+            0x8E => {
+                let m = self.decode_modrm(seg);
+                let value = self.read(m.rm, true);
+                self.registers.segment[(m.reg & 3) as usize] = value;
+            }
+            0x8F => {
+                let m = self.decode_modrm(seg);
+                let value = self.pop();
+                self.write(m.rm, true, value);
+            }
+            0x90..=0x97 => {
+                self.registers.general.swap(AX, (opcode & 7) as usize);
+            }
+            0x98 => {
+                let al = self.registers.byte(0);
+                self.registers.general[AX] = al as i8 as i16 as u16;
+            }
+            0x99 => {
+                let negative = self.registers.general[AX] & 0x8000 != 0;
+                self.registers.general[DX] = if negative { 0xFFFF } else { 0 };
+            }
+            0x9A => {
+                let offset = self.fetch_word();
+                let segment = self.fetch_word();
+                self.push(self.registers.segment[CS]);
+                self.push(self.registers.ip);
+                self.registers.segment[CS] = segment;
+                self.registers.ip = offset;
+            }
+            0x9B => {} // WAIT: there is no coprocessor to wait for
+            0x9C => self.push(self.registers.flags | FLAGS_FIXED),
+            0x9D => {
+                let value = self.pop();
+                self.registers.flags = (value & 0x0FD5) | FLAGS_FIXED;
+            }
+            0x9E => {
+                let ah = self.registers.byte(4) as u16;
+                self.registers.flags = (self.registers.flags & 0xFF00) | (ah & 0xD5) | 0x02;
+            }
+            0x9F => {
+                let flags = self.registers.flags as u8;
+                self.registers.set_byte(4, flags);
+            }
+            0xA0..=0xA3 => {
+                let offset = self.fetch_word();
+                let segment = self.registers.segment[seg.unwrap_or(DS)];
+                let memory = Location::Memory(segment, offset);
+                let acc = Location::Register(AX as u8);
+                if opcode < 0xA2 {
+                    let value = self.read(memory, word);
+                    self.write(acc, word, value);
+                } else {
+                    let value = self.read(acc, word);
+                    self.write(memory, word, value);
+                }
+            }
+            0xA4..=0xA7 | 0xAA..=0xAF => self.string_op(opcode, prefixes),
+            0xA8 | 0xA9 => {
+                let a = self.read(Location::Register(AX as u8), word);
+                let b = self.fetch(word);
+                self.logic(a & b, word);
+            }
+            0xB0..=0xB7 => {
+                let value = self.fetch_byte();
+                self.registers.set_byte(opcode & 7, value);
+            }
+            0xB8..=0xBF => {
+                let value = self.fetch_word();
+                self.registers.general[(opcode & 7) as usize] = value;
+            }
+            0xC2 | 0xC3 => {
+                let release = if opcode == 0xC2 { self.fetch_word() } else { 0 };
+                self.registers.ip = self.pop();
+                self.registers.general[SP] = self.registers.general[SP].wrapping_add(release);
+            }
+            0xC4 | 0xC5 => {
+                let m = self.decode_modrm(seg);
+                let Location::Memory(segment, offset) = m.rm else {
+                    return Err(invalid(opcode));
+                };
+                self.registers.general[m.reg as usize] = self.memory.read_word(segment, offset);
+                let base = self.memory.read_word(segment, offset.wrapping_add(2));
+                self.registers.segment[if opcode == 0xC4 { ES } else { DS }] = base;
+            }
+            0xC6 | 0xC7 => {
+                let m = self.decode_modrm(seg);
+                let value = self.fetch(word);
+                self.write(m.rm, word, value);
+            }
+            0xCA | 0xCB => {
+                let release = if opcode == 0xCA { self.fetch_word() } else { 0 };
+                self.registers.ip = self.pop();
+                self.registers.segment[CS] = self.pop();
+                self.registers.general[SP] = self.registers.general[SP].wrapping_add(release);
+            }
+            0xCC => self.interrupt(3)?,
+            0xCD => {
+                let vector = self.fetch_byte();
+                self.interrupt(vector)?;
+            }
+            0xCE => {
+                if self.registers.flag(OF) {
+                    self.interrupt(4)?;
+                }
+            }
+            0xCF => {
+                self.registers.ip = self.pop();
+                self.registers.segment[CS] = self.pop();
+                let flags = self.pop();
+                self.registers.flags = (flags & 0x0FD5) | FLAGS_FIXED;
+            }
+            0xD0..=0xD3 => {
+                let m = self.decode_modrm(seg);
+                let count = if opcode >= 0xD2 {
+                    self.registers.byte(1)
+                } else {
+                    1
+                };
+                let value = self.read(m.rm, word);
+                let r = self.shift(m.reg, value, count, word);
+                self.write(m.rm, word, r);
+            }
+            0xD4 => {
+                let base = self.fetch_byte();
+                if base == 0 {
+                    return self.interrupt(0);
+                }
+                let al = self.registers.byte(0);
+                self.registers.set_byte(4, al / base);
+                self.registers.set_byte(0, al % base);
+                self.set_szp(self.registers.byte(0) as u16, false);
+            }
+            0xD5 => {
+                let base = self.fetch_byte();
+                let al = self.registers.byte(0);
+                let ah = self.registers.byte(4);
+                let value = al.wrapping_add(ah.wrapping_mul(base));
+                self.registers.general[AX] = value as u16;
+                self.set_szp(value as u16, false);
+            }
+            0xD7 => {
+                let segment = self.registers.segment[seg.unwrap_or(DS)];
+                let offset = self.registers.general[BX].wrapping_add(self.registers.byte(0) as u16);
+                let value = self.memory.read_byte(segment, offset);
+                self.registers.set_byte(0, value);
+            }
+            0xD8..=0xDF => {
+                // ESC: hands an operand to a coprocessor; decode and ignore
+                self.decode_modrm(seg);
+            }
+            0xE0..=0xE2 => {
+                let disp = self.fetch_byte() as i8;
+                let cx = self.registers.general[CX].wrapping_sub(1);
+                self.registers.general[CX] = cx;
+                let zf = self.registers.flag(ZF);
+                let taken = cx != 0
+                    && match opcode {
+                        0xE0 => !zf,
+                        0xE1 => zf,
+                        _ => true,
+                    };
+                if taken {
+                    self.jump_relative(disp as i16);
+                }
+            }
+            0xE3 => {
+                let disp = self.fetch_byte() as i8;
+                if self.registers.general[CX] == 0 {
+                    self.jump_relative(disp as i16);
+                }
+            }
+            0xE4..=0xE7 => {
+                let port = self.fetch_byte() as u16;
+                self.port_io(opcode, port, word);
+            }
+            0xE8 => {
+                let disp = self.fetch_word();
+                self.push(self.registers.ip);
+                self.jump_relative(disp as i16);
+            }
+            0xE9 => {
+                let disp = self.fetch_word();
+                self.jump_relative(disp as i16);
+            }
+            0xEA => {
+                let offset = self.fetch_word();
+                let segment = self.fetch_word();
+                self.registers.segment[CS] = segment;
+                self.registers.ip = offset;
+            }
+            0xEB => {
+                let disp = self.fetch_byte() as i8;
+                self.jump_relative(disp as i16);
+            }
+            0xEC..=0xEF => {
+                let port = self.registers.general[DX];
+                self.port_io(opcode, port, word);
+            }
+            0xF4 => self.halted = true,
+            0xF5 => {
+                let cf = self.registers.flag(CF);
+                self.registers.set_flag(CF, !cf);
+            }
+            0xF6 | 0xF7 => {
+                let m = self.decode_modrm(seg);
+                self.group3(m, word)?;
+            }
+            0xF8 => self.registers.set_flag(CF, false),
+            0xF9 => self.registers.set_flag(CF, true),
+            0xFA => self.registers.set_flag(IF, false),
+            0xFB => self.registers.set_flag(IF, true),
+            0xFC => self.registers.set_flag(DF, false),
+            0xFD => self.registers.set_flag(DF, true),
+            0xFE => {
+                let m = self.decode_modrm(seg);
+                if m.reg > 1 {
+                    return Err(invalid(opcode));
+                }
+                let value = self.read(m.rm, false);
+                let r = self.inc_dec(value, m.reg == 1, false);
+                self.write(m.rm, false, r);
+            }
+            0xFF => {
+                let m = self.decode_modrm(seg);
+                self.group5(m)?;
+            }
+            _ => return Err(invalid(opcode)),
+        }
+        Ok(())
+    }
+
+    // --- Fetch, stack and operand access ---
+
+    fn fetch_byte(&mut self) -> u8 {
+        let value = self
+            .memory
+            .read_byte(self.registers.segment[CS], self.registers.ip);
+        self.registers.ip = self.registers.ip.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let lo = self.fetch_byte() as u16;
+        let hi = self.fetch_byte() as u16;
+        lo | (hi << 8)
+    }
+
+    fn fetch(&mut self, word: bool) -> u16 {
+        if word {
+            self.fetch_word()
+        } else {
+            self.fetch_byte() as u16
+        }
+    }
+
+    pub(crate) fn push(&mut self, value: u16) {
+        self.registers.general[SP] = self.registers.general[SP].wrapping_sub(2);
+        let (ss, sp) = (self.registers.segment[SS], self.registers.general[SP]);
+        self.store_word(ss, sp, value);
+    }
+
+    pub(crate) fn pop(&mut self) -> u16 {
+        let (ss, sp) = (self.registers.segment[SS], self.registers.general[SP]);
+        self.registers.general[SP] = sp.wrapping_add(2);
+        self.memory.read_word(ss, sp)
+    }
+
+    /// Every memory write made by an instruction goes through here.
+    pub(crate) fn store_byte(&mut self, segment: u16, offset: u16, value: u8) {
+        if let Some(trace) = &mut self.trace {
+            let before = self.memory.read_byte(segment, offset);
+            trace.record_write(physical(segment, offset), before, value);
+        }
+        self.memory.write_byte(segment, offset, value);
+    }
+
+    pub(crate) fn store_word(&mut self, segment: u16, offset: u16, value: u16) {
+        self.store_byte(segment, offset, value as u8);
+        self.store_byte(segment, offset.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    fn decode_modrm(&mut self, segment_override: Option<usize>) -> ModRm {
+        let byte = self.fetch_byte();
+        let (mode, reg, rm) = (byte >> 6, (byte >> 3) & 7, byte & 7);
+        if mode == 3 {
+            return ModRm {
+                reg,
+                rm: Location::Register(rm),
+            };
+        }
+
+        let g = self.registers.general;
+        let (base, default_segment) = match rm {
+            0 => (g[BX].wrapping_add(g[SI]), DS),
+            1 => (g[BX].wrapping_add(g[DI]), DS),
+            2 => (g[BP].wrapping_add(g[SI]), SS),
+            3 => (g[BP].wrapping_add(g[DI]), SS),
+            4 => (g[SI], DS),
+            5 => (g[DI], DS),
+            6 if mode == 0 => (0, DS), // direct address
+            6 => (g[BP], SS),
+            _ => (g[BX], DS),
+        };
+        let displacement = match mode {
+            0 if rm == 6 => self.fetch_word(),
+            0 => 0,
+            1 => self.fetch_byte() as i8 as i16 as u16,
+            _ => self.fetch_word(),
+        };
+        let segment = self.registers.segment[segment_override.unwrap_or(default_segment)];
+        ModRm {
+            reg,
+            rm: Location::Memory(segment, base.wrapping_add(displacement)),
+        }
+    }
+
+    fn read(&self, location: Location, word: bool) -> u16 {
+        match (location, word) {
+            (Location::Register(r), true) => self.registers.general[r as usize],
+            (Location::Register(r), false) => self.registers.byte(r) as u16,
+            (Location::Memory(s, o), true) => self.memory.read_word(s, o),
+            (Location::Memory(s, o), false) => self.memory.read_byte(s, o) as u16,
+        }
+    }
+
+    fn write(&mut self, location: Location, word: bool, value: u16) {
+        match (location, word) {
+            (Location::Register(r), true) => self.registers.general[r as usize] = value,
+            (Location::Register(r), false) => self.registers.set_byte(r, value as u8),
+            (Location::Memory(s, o), true) => self.store_word(s, o, value),
+            (Location::Memory(s, o), false) => self.store_byte(s, o, value as u8),
+        }
+    }
+
+    fn jump_relative(&mut self, displacement: i16) {
+        self.registers.ip = self.registers.ip.wrapping_add(displacement as u16);
+    }
+
+    /// Runs an installed service for `vector`, or pushes FLAGS, CS and IP
+    /// and jumps through the interrupt vector table.
+    pub(crate) fn interrupt(&mut self, vector: u8) -> Result<(), EmulatorError> {
+        let mut services = std::mem::take(&mut self.services);
+        let handled = services.iter_mut().try_fold(false, |done, service| {
+            Ok(done || service.handle(vector, self)?)
+        });
+        self.services = services;
+        if handled? {
+            return Ok(());
+        }
+
+        let entry = vector as u16 * 4;
+        let offset = self.memory.read_word(0, entry);
+        let segment = self.memory.read_word(0, entry + 2);
+        if offset == 0 && segment == 0 {
+            return Err(EmulatorError::UnhandledInterrupt(vector));
+        }
+        self.push(self.registers.flags | FLAGS_FIXED);
+        self.registers.set_flag(IF, false);
+        self.registers.set_flag(TF, false);
+        self.push(self.registers.segment[CS]);
+        self.push(self.registers.ip);
+        self.registers.segment[CS] = segment;
+        self.registers.ip = offset;
+        Ok(())
+    }
+
+    /// IN/OUT. No devices are modelled: reads float high and writes vanish.
+    fn port_io(&mut self, opcode: u8, _port: u16, word: bool) {
+        if opcode & 2 == 0 {
+            let value = if word { 0xFFFF } else { 0xFF };
+            self.write(Location::Register(AX as u8), word, value);
+        }
+    }
+
+    // --- Flags and arithmetic ---
+
+    fn set_szp(&mut self, value: u16, word: bool) {
+        let (value, sign) = if word {
+            (value, 0x8000)
+        } else {
+            (value & 0xFF, 0x80)
+        };
+        self.registers.set_flag(ZF, value == 0);
+        self.registers.set_flag(SF, value & sign != 0);
+        self.registers
+            .set_flag(PF, (value as u8).count_ones().is_multiple_of(2));
+    }
+
+    fn condition(&self, code: u8) -> bool {
+        let r = &self.registers;
+        let sign_overflow = r.flag(SF) != r.flag(OF);
+        let holds = match code >> 1 {
+            0 => r.flag(OF),
+            1 => r.flag(CF),
+            2 => r.flag(ZF),
+            3 => r.flag(CF) || r.flag(ZF),
+            4 => r.flag(SF),
+            5 => r.flag(PF),
+            6 => sign_overflow,
+            _ => r.flag(ZF) || sign_overflow,
+        };
+        holds != (code & 1 == 1)
+    }
+
+    /// ADD, OR, ADC, SBB, AND, SUB, XOR, CMP by their /digit.
+    fn alu(&mut self, op: u8, a: u16, b: u16, word: bool) -> u16 {
+        let carry = self.registers.flag(CF);
+        match op {
+            0 => self.add(a, b, false, word),
+            1 => self.logic(a | b, word),
+            2 => self.add(a, b, carry, word),
+            3 => self.sub(a, b, carry, word),
+            4 => self.logic(a & b, word),
+            6 => self.logic(a ^ b, word),
+            _ => self.sub(a, b, false, word),
+        }
+    }
+
+    fn add(&mut self, a: u16, b: u16, carry: bool, word: bool) -> u16 {
+        let (mask, sign) = masks(word);
+        let full = (a as u32 & mask) + (b as u32 & mask) + carry as u32;
+        let r = (full & mask) as u16;
+        self.registers.set_flag(CF, full > mask);
+        self.registers
+            .set_flag(OF, (a ^ r) & (b ^ r) & sign as u16 != 0);
+        self.registers.set_flag(AF, (a ^ b ^ r) & 0x10 != 0);
+        self.set_szp(r, word);
+        r
+    }
+
+    fn sub(&mut self, a: u16, b: u16, borrow: bool, word: bool) -> u16 {
+        let (mask, sign) = masks(word);
+        let (a32, b32) = (a as u32 & mask, b as u32 & mask);
+        let r = (a32.wrapping_sub(b32).wrapping_sub(borrow as u32) & mask) as u16;
+        self.registers.set_flag(CF, a32 < b32 + borrow as u32);
+        self.registers
+            .set_flag(OF, (a ^ b) & (a ^ r) & sign as u16 != 0);
+        self.registers.set_flag(AF, (a ^ b ^ r) & 0x10 != 0);
+        self.set_szp(r, word);
+        r
+    }
+
+    fn logic(&mut self, r: u16, word: bool) -> u16 {
+        self.registers.set_flag(CF, false);
+        self.registers.set_flag(OF, false);
+        self.registers.set_flag(AF, false);
+        self.set_szp(r, word);
+        r
+    }
+
+    /// INC/DEC: like ADD/SUB 1 but CF is preserved.
+    fn inc_dec(&mut self, value: u16, decrement: bool, word: bool) -> u16 {
+        let carry = self.registers.flag(CF);
+        let r = if decrement {
+            self.sub(value, 1, false, word)
+        } else {
+            self.add(value, 1, false, word)
+        };
+        self.registers.set_flag(CF, carry);
+        r
+    }
+
+    /// ROL, ROR, RCL, RCR, SHL, SHR, SAL, SAR by their /digit. The 8086 does
+    /// not mask the count.
+    fn shift(&mut self, op: u8, value: u16, count: u8, word: bool) -> u16 {
+        if count == 0 {
+            return value;
+        }
+        let (mask, sign) = masks(word);
+        let mut v = value as u32 & mask;
+        let mut cf = self.registers.flag(CF);
+        for _ in 0..count {
+            match op {
+                0 => {
+                    cf = v & sign != 0;
+                    v = ((v << 1) | cf as u32) & mask;
+                }
+                1 => {
+                    cf = v & 1 != 0;
+                    v = (v >> 1) | if cf { sign } else { 0 };
+                }
+                2 => {
+                    let out = v & sign != 0;
+                    v = ((v << 1) | cf as u32) & mask;
+                    cf = out;
+                }
+                3 => {
+                    let out = v & 1 != 0;
+                    v = (v >> 1) | if cf { sign } else { 0 };
+                    cf = out;
+                }
+                4 | 6 => {
+                    cf = v & sign != 0;
+                    v = (v << 1) & mask;
+                }
+                5 => {
+                    cf = v & 1 != 0;
+                    v >>= 1;
+                }
+                _ => {
+                    cf = v & 1 != 0;
+                    v = (v >> 1) | (v & sign);
+                }
+            }
+        }
+        let msb = v & sign != 0;
+        let overflow = match op {
+            0 | 2 | 4 | 6 => msb != cf,
+            1 | 3 => msb != (v & (sign >> 1) != 0),
+            5 => value as u32 & sign != 0,
+            _ => false,
+        };
+        self.registers.set_flag(CF, cf);
+        self.registers.set_flag(OF, overflow);
+        if op >= 4 {
+            self.set_szp(v as u16, word);
+        }
+        v as u16
+    }
+
+    /// F6/F7: TEST, NOT, NEG, MUL, IMUL, DIV, IDIV.
+    fn group3(&mut self, m: ModRm, word: bool) -> Result<(), EmulatorError> {
+        let value = self.read(m.rm, word);
+        let g = &mut self.registers.general;
+        match m.reg {
+            0 | 1 => {
+                let imm = self.fetch(word);
+                self.logic(value & imm, word);
+            }
+            2 => self.write(m.rm, word, !value),
+            3 => {
+                let r = self.sub(0, value, false, word);
+                self.write(m.rm, word, r);
+            }
+            4 => {
+                let high = if word {
+                    let r = g[AX] as u32 * value as u32;
+                    g[AX] = r as u16;
+                    g[DX] = (r >> 16) as u16;
+                    g[DX]
+                } else {
+                    g[AX] = (g[AX] & 0xFF) * (value & 0xFF);
+                    g[AX] >> 8
+                };
+                self.registers.set_flag(CF, high != 0);
+                self.registers.set_flag(OF, high != 0);
+            }
+            5 => {
+                let overflow = if word {
+                    let r = g[AX] as i16 as i32 * value as i16 as i32;
+                    g[AX] = r as u16;
+                    g[DX] = (r >> 16) as u16;
+                    r != r as i16 as i32
+                } else {
+                    let r = g[AX] as u8 as i8 as i16 * value as u8 as i8 as i16;
+                    g[AX] = r as u16;
+                    r != r as i8 as i16
+                };
+                self.registers.set_flag(CF, overflow);
+                self.registers.set_flag(OF, overflow);
+            }
+            6 => {
+                if word {
+                    let dividend = ((g[DX] as u32) << 16) | g[AX] as u32;
+                    let divisor = value as u32;
+                    match dividend.checked_div(divisor) {
+                        Some(q) if q <= 0xFFFF => {
+                            g[AX] = q as u16;
+                            g[DX] = (dividend % divisor) as u16;
+                        }
+                        _ => return self.interrupt(0),
+                    }
+                } else {
+                    let dividend = g[AX];
+                    let divisor = value & 0xFF;
+                    match dividend.checked_div(divisor) {
+                        Some(q) if q <= 0xFF => g[AX] = ((dividend % divisor) << 8) | q,
+                        _ => return self.interrupt(0),
+                    }
+                }
+            }
+            _ => {
+                if word {
+                    let dividend = (((g[DX] as u32) << 16) | g[AX] as u32) as i32 as i64;
+                    let divisor = value as i16 as i64;
+                    match dividend.checked_div(divisor) {
+                        Some(q) if i16::try_from(q).is_ok() => {
+                            g[AX] = q as u16;
+                            g[DX] = (dividend % divisor) as u16;
+                        }
+                        _ => return self.interrupt(0),
+                    }
+                } else {
+                    let dividend = g[AX] as i16 as i32;
+                    let divisor = value as u8 as i8 as i32;
+                    match dividend.checked_div(divisor) {
+                        Some(q) if i8::try_from(q).is_ok() => {
+                            let rem = (dividend % divisor) as u8 as u16;
+                            g[AX] = (rem << 8) | q as u8 as u16;
+                        }
+                        _ => return self.interrupt(0),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// FF: INC, DEC, CALL, CALL far, JMP, JMP far, PUSH on a 16-bit r/m.
+    fn group5(&mut self, m: ModRm) -> Result<(), EmulatorError> {
+        match m.reg {
+            0 | 1 => {
+                let value = self.read(m.rm, true);
+                let r = self.inc_dec(value, m.reg == 1, true);
+                self.write(m.rm, true, r);
+            }
+            2 => {
+                let target = self.read(m.rm, true);
+                self.push(self.registers.ip);
+                self.registers.ip = target;
+            }
+            4 => self.registers.ip = self.read(m.rm, true),
+            3 | 5 => {
+                let Location::Memory(segment, offset) = m.rm else {
+                    return Err(invalid(0xFF));
+                };
+                let target_ip = self.memory.read_word(segment, offset);
+                let target_cs = self.memory.read_word(segment, offset.wrapping_add(2));
+                if m.reg == 3 {
+                    self.push(self.registers.segment[CS]);
+                    self.push(self.registers.ip);
+                }
+                self.registers.segment[CS] = target_cs;
+                self.registers.ip = target_ip;
+            }
+            6 => {
+                let value = self.read(m.rm, true);
+                self.push(value);
+            }
+            _ => return Err(invalid(0xFF)),
+        }
+        Ok(())
+    }
+
+    /// MOVS, CMPS, STOS, LODS, SCAS with an optional repeat prefix.
+    fn string_op(&mut self, opcode: u8, prefixes: Prefixes) {
+        let word = opcode & 1 == 1;
+        let size: u16 = if word { 2 } else { 1 };
+        let step = if self.registers.flag(DF) {
+            size.wrapping_neg()
+        } else {
+            size
+        };
+        let source = self.registers.segment[prefixes.segment.unwrap_or(DS)];
+        let compares = matches!(opcode, 0xA6 | 0xA7 | 0xAE | 0xAF);
+
+        loop {
+            if prefixes.rep.is_some() && self.registers.general[CX] == 0 {
+                break;
+            }
+            let es = self.registers.segment[ES];
+            let (si, di) = (self.registers.general[SI], self.registers.general[DI]);
+            let acc = Location::Register(AX as u8);
+            match opcode {
+                0xA4 | 0xA5 => {
+                    let value = self.read(Location::Memory(source, si), word);
+                    self.write(Location::Memory(es, di), word, value);
+                }
+                0xA6 | 0xA7 => {
+                    let a = self.read(Location::Memory(source, si), word);
+                    let b = self.read(Location::Memory(es, di), word);
+                    self.sub(a, b, false, word);
+                }
+                0xAA | 0xAB => {
+                    let value = self.read(acc, word);
+                    self.write(Location::Memory(es, di), word, value);
+                }
+                0xAC | 0xAD => {
+                    let value = self.read(Location::Memory(source, si), word);
+                    self.write(acc, word, value);
+                }
+                _ => {
+                    let a = self.read(acc, word);
+                    let b = self.read(Location::Memory(es, di), word);
+                    self.sub(a, b, false, word);
+                }
+            }
+            if matches!(opcode, 0xA4..=0xA7 | 0xAC | 0xAD) {
+                self.registers.general[SI] = si.wrapping_add(step);
+            }
+            if !matches!(opcode, 0xAC | 0xAD) {
+                self.registers.general[DI] = di.wrapping_add(step);
+            }
+
+            let Some(rep) = prefixes.rep else {
+                break;
+            };
+            self.registers.general[CX] = self.registers.general[CX].wrapping_sub(1);
+            let zf = self.registers.flag(ZF);
+            if compares && (zf != (rep == Rep::WhileEqual)) {
+                break;
+            }
+        }
+    }
+
+    // --- Decimal adjust ---
+
+    fn daa(&mut self) {
+        let (old_al, old_cf) = (self.registers.byte(0), self.registers.flag(CF));
+        let mut al = old_al;
+        if al & 0x0F > 9 || self.registers.flag(AF) {
+            let (sum, carry) = al.overflowing_add(6);
+            al = sum;
+            self.registers.set_flag(CF, old_cf || carry);
+            self.registers.set_flag(AF, true);
+        } else {
+            self.registers.set_flag(AF, false);
+        }
+        if old_al > 0x99 || old_cf {
+            al = al.wrapping_add(0x60);
+            self.registers.set_flag(CF, true);
+        } else {
+            self.registers.set_flag(CF, false);
+        }
+        self.registers.set_byte(0, al);
+        self.set_szp(al as u16, false);
+    }
+
+    fn das(&mut self) {
+        let (old_al, old_cf) = (self.registers.byte(0), self.registers.flag(CF));
+        let mut al = old_al;
+        if al & 0x0F > 9 || self.registers.flag(AF) {
+            let (diff, borrow) = al.overflowing_sub(6);
+            al = diff;
+            self.registers.set_flag(CF, old_cf || borrow);
+            self.registers.set_flag(AF, true);
+        } else {
+            self.registers.set_flag(AF, false);
+        }
+        if old_al > 0x99 || old_cf {
+            al = al.wrapping_sub(0x60);
+            self.registers.set_flag(CF, true);
+        } else {
+            self.registers.set_flag(CF, false);
+        }
+        self.registers.set_byte(0, al);
+        self.set_szp(al as u16, false);
+    }
+
+    fn aaa(&mut self) {
+        self.ascii_adjust(false);
+    }
+
+    fn aas(&mut self) {
+        self.ascii_adjust(true);
+    }
+
+    fn ascii_adjust(&mut self, subtract: bool) {
+        let al = self.registers.byte(0);
+        let adjust = al & 0x0F > 9 || self.registers.flag(AF);
+        if adjust {
+            let ah = self.registers.byte(4);
+            if subtract {
+                self.registers.set_byte(0, al.wrapping_sub(6));
+                self.registers.set_byte(4, ah.wrapping_sub(1));
+            } else {
+                self.registers.set_byte(0, al.wrapping_add(6));
+                self.registers.set_byte(4, ah.wrapping_add(1));
+            }
+        }
+        self.registers.set_flag(AF, adjust);
+        self.registers.set_flag(CF, adjust);
+        let al = self.registers.byte(0) & 0x0F;
+        self.registers.set_byte(0, al);
+    }
+}
+
+fn masks(word: bool) -> (u32, u32) {
+    if word { (0xFFFF, 0x8000) } else { (0xFF, 0x80) }
+}
+
+/// Placeholder location; `execute` fills in where the instruction started.
+fn invalid(opcode: u8) -> EmulatorError {
+    EmulatorError::InvalidOpcode {
+        segment: 0,
+        offset: 0,
+        opcode,
+    }
+}
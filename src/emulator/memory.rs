@@ -0,0 +1,66 @@
+// src/emulator/memory.rs
+
+/// Size of the 8086 physical address space (20 address lines).
+pub const MEMORY_SIZE: usize = 1 << 20;
+
+/// 1 MiB of flat physical memory addressed through `segment:offset` pairs.
+#[derive(Clone)]
+pub struct Memory {
+    bytes: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory {
+            bytes: vec![0; MEMORY_SIZE],
+        }
+    }
+}
+
+impl std::fmt::Debug for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Memory({} bytes)", self.bytes.len())
+    }
+}
+
+/// `segment * 16 + offset`, wrapping at 1 MiB like the 8086 does.
+pub fn physical(segment: u16, offset: u16) -> usize {
+    (((segment as usize) << 4) + offset as usize) & (MEMORY_SIZE - 1)
+}
+
+impl Memory {
+    pub fn read_byte(&self, segment: u16, offset: u16) -> u8 {
+        self.bytes[physical(segment, offset)]
+    }
+
+    pub fn write_byte(&mut self, segment: u16, offset: u16, value: u8) {
+        self.bytes[physical(segment, offset)] = value;
+    }
+
+    /// Little-endian word; the high byte wraps to offset 0 of the same segment.
+    pub fn read_word(&self, segment: u16, offset: u16) -> u16 {
+        let lo = self.read_byte(segment, offset) as u16;
+        let hi = self.read_byte(segment, offset.wrapping_add(1)) as u16;
+        lo | (hi << 8)
+    }
+
+    pub fn write_word(&mut self, segment: u16, offset: u16, value: u16) {
+        self.write_byte(segment, offset, value as u8);
+        self.write_byte(segment, offset.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    /// Copies `data` to physical memory starting at `segment:offset`.
+    pub fn load(&mut self, segment: u16, offset: u16, data: &[u8]) {
+        let start = physical(segment, offset);
+        for (i, byte) in data.iter().enumerate() {
+            self.bytes[(start + i) & (MEMORY_SIZE - 1)] = *byte;
+        }
+    }
+
+    /// `len` bytes starting at a physical address, for memory views.
+    pub fn slice(&self, address: usize, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.bytes[(address + i) & (MEMORY_SIZE - 1)])
+            .collect()
+    }
+}
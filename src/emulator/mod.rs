@@ -0,0 +1,251 @@
+// src/emulator/mod.rs
+//! An 8086 that runs the images built by `output::com` and `output::exe`.
+//!
+//! `Machine` owns the register file and 1 MiB of memory. Programs are loaded
+//! the way DOS would load them (PSP, stack, relocations) and then executed
+//! one instruction per `step`.
+
+pub mod debugger;
+mod execute;
+pub mod memory;
+pub mod registers;
+pub mod services;
+pub mod trace;
+
+use memory::Memory;
+use registers::{CS, DS, ES, Registers, SP, SS};
+use services::{Console, InterruptService};
+use std::fmt;
+use trace::Trace;
+
+/// Segment of the PSP for loaded programs; the image follows it.
+pub const LOAD_SEGMENT: u16 = 0x1000;
+/// Size of the Program Segment Prefix DOS places before a program.
+const PSP_PARAGRAPHS: u16 = 0x10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// The bytes at `segment:offset` are not an 8086 instruction.
+    InvalidOpcode {
+        segment: u16,
+        offset: u16,
+        opcode: u8,
+    },
+    /// `INT n` (or a CPU exception) with no handler installed for `n`.
+    UnhandledInterrupt(u8),
+    /// `step` was called after `HLT` or program termination.
+    Halted,
+    /// The file handed to a loader is not a valid image.
+    InvalidImage(String),
+    /// A service needs keyboard input the console does not have yet. The
+    /// instruction is retried by the next `step`.
+    WaitingForInput,
+    /// A DOS/BIOS interrupt function (AH) the services do not implement.
+    UnsupportedService { vector: u8, function: u8 },
+    /// `rewind_to` a step that is not in the recorded trace.
+    TraceUnavailable(u64),
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::InvalidOpcode {
+                segment,
+                offset,
+                opcode,
+            } => write!(
+                f,
+                "Invalid opcode {:02X}h at {:04X}:{:04X}",
+                opcode, segment, offset
+            ),
+            EmulatorError::UnhandledInterrupt(0) => write!(f, "Divide error (INT 0)"),
+            EmulatorError::UnhandledInterrupt(n) => write!(f, "Unhandled interrupt {:02X}h", n),
+            EmulatorError::Halted => write!(f, "The machine is halted"),
+            EmulatorError::InvalidImage(msg) => write!(f, "Invalid image: {}", msg),
+            EmulatorError::WaitingForInput => write!(f, "Waiting for keyboard input"),
+            EmulatorError::UnsupportedService { vector, function } => write!(
+                f,
+                "INT {:02X}h function {:02X}h is not supported",
+                vector, function
+            ),
+            EmulatorError::TraceUnavailable(step) => {
+                write!(f, "Step {} is not in the recorded trace", step)
+            }
+        }
+    }
+}
+
+/// Why `run` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Halted,
+    /// `max_steps` instructions ran without the program stopping.
+    StepLimit,
+    /// The program is blocked reading the console; push input and run again.
+    WaitingForInput,
+}
+
+#[derive(Debug, Default)]
+pub struct Machine {
+    pub registers: Registers,
+    pub memory: Memory,
+    pub halted: bool,
+    /// Instructions executed so far.
+    pub steps: u64,
+    pub console: Console,
+    /// Consulted in order before the interrupt vector table.
+    pub services: Vec<Box<dyn InterruptService>>,
+    /// Return code passed to INT 21h/4Ch once the program has exited.
+    pub exit_code: Option<u8>,
+    /// History for `rewind_to`; recording is off while `None`.
+    pub trace: Option<Trace>,
+}
+
+impl Machine {
+    /// A machine with the standard DOS and BIOS services installed.
+    /// `Machine::default()` has none: every interrupt goes to the IVT.
+    pub fn new() -> Self {
+        Machine {
+            services: services::standard(),
+            ..Self::default()
+        }
+    }
+
+    /// Ends the program (INT 20h, INT 21h/00h and 4Ch).
+    pub(crate) fn terminate(&mut self, code: u8) {
+        self.halted = true;
+        self.exit_code = Some(code);
+    }
+
+    /// Next key from the console, or `WaitingForInput`.
+    pub(crate) fn read_key(&mut self) -> Result<u8, EmulatorError> {
+        let key = self
+            .console
+            .input
+            .pop_front()
+            .ok_or(EmulatorError::WaitingForInput)?;
+        if let Some(trace) = &mut self.trace {
+            trace.record_input(key);
+        }
+        Ok(key)
+    }
+
+    /// Loads a .COM image at `LOAD_SEGMENT:0100h`, like DOS does.
+    ///
+    /// All segment registers point at the PSP, SP is at the top of the
+    /// segment and a zero word is pushed so a final `RET` reaches the
+    /// `INT 20h` at PSP:0000.
+    pub fn load_com(image: &[u8]) -> Result<Self, EmulatorError> {
+        if image.len() > 0xFF00 {
+            return Err(EmulatorError::InvalidImage(
+                ".COM image is larger than 64K".to_string(),
+            ));
+        }
+        let mut machine = Machine::new();
+        let psp = LOAD_SEGMENT;
+        machine.write_psp(psp);
+        machine.memory.load(psp, 0x100, image);
+
+        let r = &mut machine.registers;
+        r.segment = [psp; 4];
+        r.general[SP] = 0xFFFE;
+        r.ip = 0x100;
+        machine.memory.write_word(psp, 0xFFFE, 0);
+        Ok(machine)
+    }
+
+    /// Loads an MZ executable after the PSP and applies its relocations.
+    pub fn load_exe(file: &[u8]) -> Result<Self, EmulatorError> {
+        let bad = |msg: &str| EmulatorError::InvalidImage(msg.to_string());
+        let word = |at: usize| -> Result<u16, EmulatorError> {
+            file.get(at..at + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .ok_or_else(|| bad("truncated header"))
+        };
+        if file.get(0..2) != Some(b"MZ".as_slice()) {
+            return Err(bad("missing MZ signature"));
+        }
+
+        let last_page = word(0x02)? as usize;
+        let pages = word(0x04)? as usize;
+        let relocation_count = word(0x06)? as usize;
+        let header_size = word(0x08)? as usize * 16;
+        let file_size = match last_page {
+            0 => pages * 512,
+            n => pages.saturating_sub(1) * 512 + n,
+        };
+        let image = file
+            .get(header_size..file_size.min(file.len()))
+            .ok_or_else(|| bad("image outside of the file"))?;
+
+        let mut machine = Machine::new();
+        let psp = LOAD_SEGMENT;
+        let load = psp + PSP_PARAGRAPHS;
+        machine.write_psp(psp);
+        machine.memory.load(load, 0, image);
+
+        let table = word(0x18)? as usize;
+        for i in 0..relocation_count {
+            let offset = word(table + i * 4)?;
+            let segment = word(table + i * 4 + 2)?.wrapping_add(load);
+            let value = machine.memory.read_word(segment, offset);
+            machine
+                .memory
+                .write_word(segment, offset, value.wrapping_add(load));
+        }
+
+        let r = &mut machine.registers;
+        r.segment[SS] = word(0x0E)?.wrapping_add(load);
+        r.general[SP] = word(0x10)?;
+        r.ip = word(0x14)?;
+        r.segment[CS] = word(0x16)?.wrapping_add(load);
+        r.segment[DS] = psp;
+        r.segment[ES] = psp;
+        Ok(machine)
+    }
+
+    /// Minimal PSP: `INT 20h` at offset 0 and the top of memory at offset 2.
+    fn write_psp(&mut self, psp: u16) {
+        self.memory.load(psp, 0, &[0xCD, 0x20]);
+        self.memory.write_word(psp, 2, 0xA000);
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        if self.halted {
+            return Err(EmulatorError::Halted);
+        }
+        if self.trace.is_none() {
+            self.execute()?;
+            self.steps += 1;
+            return Ok(());
+        }
+
+        let before = self.registers.clone();
+        self.begin_delta();
+        let result = self.execute();
+        self.end_delta(&before, result.is_ok());
+        result?;
+        self.steps += 1;
+        Ok(())
+    }
+
+    /// Runs until the program halts, an error occurs or `max_steps`
+    /// instructions have executed.
+    pub fn run(&mut self, max_steps: u64) -> Result<RunOutcome, EmulatorError> {
+        for _ in 0..max_steps {
+            if self.halted {
+                return Ok(RunOutcome::Halted);
+            }
+            match self.step() {
+                Err(EmulatorError::WaitingForInput) => return Ok(RunOutcome::WaitingForInput),
+                result => result?,
+            }
+        }
+        Ok(if self.halted {
+            RunOutcome::Halted
+        } else {
+            RunOutcome::StepLimit
+        })
+    }
+}
@@ -0,0 +1,162 @@
+// src/emulator/registers.rs
+use serde::Serialize;
+
+// FLAGS bits
+pub const CF: u16 = 0x0001;
+pub const PF: u16 = 0x0004;
+pub const AF: u16 = 0x0010;
+pub const ZF: u16 = 0x0040;
+pub const SF: u16 = 0x0080;
+pub const TF: u16 = 0x0100;
+pub const IF: u16 = 0x0200;
+pub const DF: u16 = 0x0400;
+pub const OF: u16 = 0x0800;
+
+/// Bits 12-15 of FLAGS always read as 1 on the 8086, bit 1 is reserved as 1.
+pub const FLAGS_FIXED: u16 = 0xF002;
+
+// General register codes, as used in ModR/M
+pub const AX: usize = 0;
+pub const CX: usize = 1;
+pub const DX: usize = 2;
+pub const BX: usize = 3;
+pub const SP: usize = 4;
+pub const BP: usize = 5;
+pub const SI: usize = 6;
+pub const DI: usize = 7;
+
+// Segment register codes
+pub const ES: usize = 0;
+pub const CS: usize = 1;
+pub const SS: usize = 2;
+pub const DS: usize = 3;
+
+/// The 8086 register file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers {
+    /// AX, CX, DX, BX, SP, BP, SI, DI in encoding order.
+    pub general: [u16; 8],
+    /// ES, CS, SS, DS in encoding order.
+    pub segment: [u16; 4],
+    pub ip: u16,
+    pub flags: u16,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers {
+            general: [0; 8],
+            segment: [0; 4],
+            ip: 0,
+            flags: FLAGS_FIXED,
+        }
+    }
+}
+
+impl Registers {
+    /// 8-bit register by ModR/M code: AL, CL, DL, BL, AH, CH, DH, BH.
+    pub fn byte(&self, code: u8) -> u8 {
+        let word = self.general[(code & 3) as usize];
+        if code < 4 {
+            word as u8
+        } else {
+            (word >> 8) as u8
+        }
+    }
+
+    pub fn set_byte(&mut self, code: u8, value: u8) {
+        let word = &mut self.general[(code & 3) as usize];
+        *word = if code < 4 {
+            (*word & 0xFF00) | value as u16
+        } else {
+            (*word & 0x00FF) | ((value as u16) << 8)
+        };
+    }
+
+    pub fn flag(&self, mask: u16) -> bool {
+        self.flags & mask != 0
+    }
+
+    pub fn set_flag(&mut self, mask: u16, on: bool) {
+        if on {
+            self.flags |= mask;
+        } else {
+            self.flags &= !mask;
+        }
+    }
+
+    pub fn snapshot(&self) -> RegisterSnapshot {
+        let g = &self.general;
+        let s = &self.segment;
+        RegisterSnapshot {
+            ax: g[AX],
+            bx: g[BX],
+            cx: g[CX],
+            dx: g[DX],
+            sp: g[SP],
+            bp: g[BP],
+            si: g[SI],
+            di: g[DI],
+            cs: s[CS],
+            ds: s[DS],
+            es: s[ES],
+            ss: s[SS],
+            ip: self.ip,
+            flags: self.flags,
+        }
+    }
+}
+
+/// Named register values for display and for the web UI.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegisterSnapshot {
+    pub ax: u16,
+    pub bx: u16,
+    pub cx: u16,
+    pub dx: u16,
+    pub sp: u16,
+    pub bp: u16,
+    pub si: u16,
+    pub di: u16,
+    pub cs: u16,
+    pub ds: u16,
+    pub es: u16,
+    pub ss: u16,
+    pub ip: u16,
+    pub flags: u16,
+}
+
+impl std::fmt::Display for RegisterSnapshot {
+    /// DEBUG-style dump: `AX=0000  BX=0000 ...` plus the flag letters.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "AX={:04X}  BX={:04X}  CX={:04X}  DX={:04X}  SP={:04X}  BP={:04X}  SI={:04X}  DI={:04X}",
+            self.ax, self.bx, self.cx, self.dx, self.sp, self.bp, self.si, self.di
+        )?;
+        let names = [
+            (OF, "OV", "NV"),
+            (DF, "DN", "UP"),
+            (IF, "EI", "DI"),
+            (SF, "NG", "PL"),
+            (ZF, "ZR", "NZ"),
+            (AF, "AC", "NA"),
+            (PF, "PE", "PO"),
+            (CF, "CY", "NC"),
+        ];
+        let flags: Vec<&str> = names
+            .iter()
+            .map(|(mask, on, off)| if self.flags & mask != 0 { *on } else { *off })
+            .collect();
+        write!(
+            f,
+            "DS={:04X}  ES={:04X}  SS={:04X}  CS={:04X}  IP={:04X}   {}",
+            self.ds,
+            self.es,
+            self.ss,
+            self.cs,
+            self.ip,
+            flags.join(" ")
+        )
+    }
+}
@@ -0,0 +1,156 @@
+// src/emulator/services.rs
+use super::registers::{AX, DS, DX, ZF};
+use super::{EmulatorError, Machine};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// In-memory keyboard and screen shared by the DOS and BIOS services.
+#[derive(Debug, Clone, Default)]
+pub struct Console {
+    /// Keys not yet read by the program.
+    pub input: VecDeque<u8>,
+    /// Everything the program has printed.
+    pub output: Vec<u8>,
+}
+
+impl Console {
+    /// Queues typed text; `\n` becomes the CR the Enter key produces.
+    pub fn push_input(&mut self, text: &str) {
+        self.input
+            .extend(text.bytes().map(|b| if b == b'\n' { b'\r' } else { b }));
+    }
+
+    /// Output so far as text.
+    pub fn output_text(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+/// Software that answers `INT n` in place of code in the vector table.
+pub trait InterruptService: fmt::Debug {
+    /// Handles `INT vector` and returns `Ok(true)`, or `Ok(false)` to let
+    /// the next service (and finally the vector table) try.
+    fn handle(&mut self, vector: u8, machine: &mut Machine) -> Result<bool, EmulatorError>;
+}
+
+/// The services a freshly loaded DOS program expects.
+pub fn standard() -> Vec<Box<dyn InterruptService>> {
+    vec![Box::new(DosServices), Box::new(BiosServices)]
+}
+
+/// INT 20h and the INT 21h console and exit functions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DosServices;
+
+impl InterruptService for DosServices {
+    fn handle(&mut self, vector: u8, machine: &mut Machine) -> Result<bool, EmulatorError> {
+        match vector {
+            0x20 => machine.terminate(0),
+            0x21 => {
+                let function = machine.registers.byte(4);
+                match function {
+                    0x00 => machine.terminate(0),
+                    // Read character with echo
+                    0x01 => {
+                        let key = machine.read_key()?;
+                        machine.console.output.push(key);
+                        machine.registers.set_byte(0, key);
+                    }
+                    // Write character in DL
+                    0x02 => {
+                        let ch = machine.registers.byte(2);
+                        machine.console.output.push(ch);
+                        machine.registers.set_byte(0, ch);
+                    }
+                    // Write '$'-terminated string at DS:DX
+                    0x09 => {
+                        let segment = machine.registers.segment[DS];
+                        let start = machine.registers.general[DX];
+                        // A missing '$' stops after one whole segment
+                        for i in 0..=u16::MAX {
+                            let ch = machine.memory.read_byte(segment, start.wrapping_add(i));
+                            if ch == b'$' {
+                                break;
+                            }
+                            machine.console.output.push(ch);
+                        }
+                        machine.registers.set_byte(0, b'$');
+                    }
+                    // Buffered input into DS:DX: [max][count][chars... CR]
+                    0x0A => buffered_input(machine)?,
+                    0x4C => {
+                        let code = machine.registers.byte(0);
+                        machine.terminate(code);
+                    }
+                    _ => {
+                        return Err(EmulatorError::UnsupportedService { vector, function });
+                    }
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// INT 10h teletype output and INT 16h keyboard.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiosServices;
+
+impl InterruptService for BiosServices {
+    fn handle(&mut self, vector: u8, machine: &mut Machine) -> Result<bool, EmulatorError> {
+        let function = machine.registers.byte(4);
+        match (vector, function) {
+            // Teletype output of AL
+            (0x10, 0x0E) => {
+                let ch = machine.registers.byte(0);
+                machine.console.output.push(ch);
+            }
+            // Wait for a key: AL = ASCII, AH = scan code (not modelled)
+            (0x16, 0x00) => {
+                let key = machine.read_key()?;
+                machine.registers.general[AX] = key as u16;
+            }
+            // Check for a key: ZF set when none is waiting
+            (0x16, 0x01) => match machine.console.input.front().copied() {
+                Some(key) => {
+                    machine.registers.general[AX] = key as u16;
+                    machine.registers.set_flag(ZF, false);
+                }
+                None => machine.registers.set_flag(ZF, true),
+            },
+            (0x10 | 0x16, _) => {
+                return Err(EmulatorError::UnsupportedService { vector, function });
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// INT 21h/0Ah. Waits until a whole line (up to CR) has been typed.
+fn buffered_input(machine: &mut Machine) -> Result<(), EmulatorError> {
+    let Some(line_end) = machine.console.input.iter().position(|&b| b == b'\r') else {
+        return Err(EmulatorError::WaitingForInput);
+    };
+    let segment = machine.registers.segment[DS];
+    let buffer = machine.registers.general[DX];
+    let max = machine.memory.read_byte(segment, buffer) as usize;
+    if max == 0 {
+        return Ok(());
+    }
+
+    let line = (0..=line_end)
+        .map(|_| machine.read_key())
+        .collect::<Result<Vec<u8>, _>>()?;
+    // Room for max - 1 characters plus the CR; DOS drops the rest
+    let count = (line.len() - 1).min(max - 1);
+    for (i, &ch) in line[..count].iter().enumerate() {
+        machine.store_byte(segment, buffer.wrapping_add(2 + i as u16), ch);
+    }
+    machine.store_byte(segment, buffer.wrapping_add(2 + count as u16), b'\r');
+    machine.store_byte(segment, buffer.wrapping_add(1), count as u8);
+    machine.console.output.extend(&line[..count]);
+    machine.console.output.push(b'\r');
+    Ok(())
+}
@@ -0,0 +1,322 @@
+// src/emulator/trace.rs
+//! Per-instruction change log for stepping backwards.
+//!
+//! While `Machine::trace` is set, every `step` appends a `StepDelta` with the
+//! registers it changed, the bytes it wrote and the console input it read.
+//! Undoing deltas newest-first puts the machine back at any earlier step.
+
+use super::memory::MEMORY_SIZE;
+use super::registers::Registers;
+use super::{EmulatorError, Machine};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Register names in delta order: general, segment, IP, FLAGS.
+const REGISTER_NAMES: [&str; 14] = [
+    "AX", "CX", "DX", "BX", "SP", "BP", "SI", "DI", "ES", "CS", "SS", "DS", "IP", "FLAGS",
+];
+
+/// Steps kept by `Trace::default()`; older ones are dropped.
+pub const DEFAULT_TRACE_LIMIT: usize = 100_000;
+
+fn register_list(registers: &Registers) -> [u16; 14] {
+    let mut values = [0; 14];
+    values[..8].copy_from_slice(&registers.general);
+    values[8..12].copy_from_slice(&registers.segment);
+    values[12] = registers.ip;
+    values[13] = registers.flags;
+    values
+}
+
+fn set_register(registers: &mut Registers, index: usize, value: u16) {
+    match index {
+        0..8 => registers.general[index] = value,
+        8..12 => registers.segment[index - 8] = value,
+        12 => registers.ip = value,
+        _ => registers.flags = value,
+    }
+}
+
+/// What one instruction changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepDelta {
+    /// Value of `Machine::steps` before the instruction ran.
+    pub step: u64,
+    /// Address of the instruction.
+    pub cs: u16,
+    pub ip: u16,
+    /// (register index, before, after) for every register that changed.
+    pub registers: Vec<(u8, u16, u16)>,
+    /// (physical address, before, after) in write order.
+    pub writes: Vec<(u32, u8, u8)>,
+    /// Console keys consumed, in order.
+    pub input: Vec<u8>,
+    /// Console output length before the instruction.
+    pub output_len: usize,
+    /// Whether the instruction ended the program.
+    pub halted: bool,
+    pub exit_code: Option<u8>,
+}
+
+/// Recorded history of a machine.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    steps: VecDeque<StepDelta>,
+    limit: usize,
+    /// Delta of the instruction being executed.
+    pending: Option<StepDelta>,
+}
+
+impl Default for Trace {
+    fn default() -> Self {
+        Trace::with_limit(DEFAULT_TRACE_LIMIT)
+    }
+}
+
+impl Trace {
+    /// A trace that keeps only the last `limit` steps.
+    pub fn with_limit(limit: usize) -> Self {
+        Trace {
+            steps: VecDeque::new(),
+            limit,
+            pending: None,
+        }
+    }
+
+    /// Steps held, for the command line's `--trace` summary.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Earliest step that can still be rewound to.
+    pub fn first_step(&self) -> Option<u64> {
+        self.steps.front().map(|delta| delta.step)
+    }
+
+    pub(crate) fn record_write(&mut self, address: usize, before: u8, after: u8) {
+        if let Some(delta) = &mut self.pending {
+            delta.writes.push((address as u32, before, after));
+        }
+    }
+
+    pub(crate) fn record_input(&mut self, key: u8) {
+        if let Some(delta) = &mut self.pending {
+            delta.input.push(key);
+        }
+    }
+
+    fn push(&mut self, delta: StepDelta) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.steps.len() == self.limit {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(delta);
+    }
+
+    /// Net changes made by the steps from `from` up to (not including) `to`.
+    pub fn diff(&self, from: u64, to: u64) -> Option<StateDiff> {
+        let (from, to) = (from.min(to), from.max(to));
+        let first = self.first_step()?;
+        if from < first || to > first + self.steps.len() as u64 {
+            return None;
+        }
+
+        let mut registers: BTreeMap<u8, (u16, u16)> = BTreeMap::new();
+        let mut memory: BTreeMap<u32, (u8, u8)> = BTreeMap::new();
+        for delta in self
+            .steps
+            .range((from - first) as usize..(to - first) as usize)
+        {
+            for &(index, before, after) in &delta.registers {
+                registers.entry(index).or_insert((before, after)).1 = after;
+            }
+            for &(address, before, after) in &delta.writes {
+                memory.entry(address).or_insert((before, after)).1 = after;
+            }
+        }
+
+        Some(StateDiff {
+            from,
+            to,
+            registers: registers
+                .into_iter()
+                .filter(|(_, (before, after))| before != after)
+                .map(|(index, (before, after))| RegisterChange {
+                    name: REGISTER_NAMES[index as usize],
+                    before,
+                    after,
+                })
+                .collect(),
+            memory: memory
+                .into_iter()
+                .filter(|(_, (before, after))| before != after)
+                .map(|(address, (before, after))| MemoryChange {
+                    address,
+                    before,
+                    after,
+                })
+                .collect(),
+        })
+    }
+
+    /// One JSON object per step: position, source line (via `line_at`),
+    /// changed registers and memory writes.
+    pub fn to_json_lines(&self, line_at: impl Fn(u16, u16) -> Option<usize>) -> String {
+        let mut out = String::new();
+        for delta in &self.steps {
+            let registers: BTreeMap<&str, [u16; 2]> = delta
+                .registers
+                .iter()
+                .map(|&(index, before, after)| (REGISTER_NAMES[index as usize], [before, after]))
+                .collect();
+            let record = TraceRecord {
+                step: delta.step,
+                line: line_at(delta.cs, delta.ip),
+                cs: delta.cs,
+                ip: delta.ip,
+                registers,
+                writes: delta
+                    .writes
+                    .iter()
+                    .map(|&(address, before, after)| MemoryChange {
+                        address,
+                        before,
+                        after,
+                    })
+                    .collect(),
+                input: String::from_utf8_lossy(&delta.input).into_owned(),
+                halted: delta.halted,
+            };
+            out.push_str(&serde_json::to_string(&record).unwrap_or_default());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[derive(Serialize)]
+struct TraceRecord<'a> {
+    step: u64,
+    line: Option<usize>,
+    cs: u16,
+    ip: u16,
+    registers: BTreeMap<&'a str, [u16; 2]>,
+    writes: Vec<MemoryChange>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    input: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    halted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub before: u16,
+    pub after: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryChange {
+    /// Physical address.
+    pub address: u32,
+    pub before: u8,
+    pub after: u8,
+}
+
+/// Differences between the machine state at two steps.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    pub from: u64,
+    pub to: u64,
+    pub registers: Vec<RegisterChange>,
+    pub memory: Vec<MemoryChange>,
+}
+
+impl Machine {
+    /// Starts recording every step (dropping any earlier trace).
+    pub fn start_trace(&mut self, limit: usize) {
+        self.trace = Some(Trace::with_limit(limit));
+    }
+
+    /// Called by `step` before the instruction runs.
+    pub(super) fn begin_delta(&mut self) {
+        let output_len = self.console.output.len();
+        let r = &self.registers;
+        let delta = StepDelta {
+            step: self.steps,
+            cs: r.segment[super::registers::CS],
+            ip: r.ip,
+            registers: Vec::new(),
+            writes: Vec::new(),
+            input: Vec::new(),
+            output_len,
+            halted: false,
+            exit_code: self.exit_code,
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.pending = Some(delta);
+        }
+    }
+
+    /// Called by `step` once the instruction ran; `before` holds the
+    /// registers from `begin_delta` time. A failed instruction is not
+    /// recorded.
+    pub(super) fn end_delta(&mut self, before: &Registers, completed: bool) {
+        let Some(trace) = &mut self.trace else {
+            return;
+        };
+        let Some(mut delta) = trace.pending.take() else {
+            return;
+        };
+        if !completed {
+            return;
+        }
+        let (old, new) = (register_list(before), register_list(&self.registers));
+        delta.registers = (0..old.len())
+            .filter(|&i| old[i] != new[i])
+            .map(|i| (i as u8, old[i], new[i]))
+            .collect();
+        delta.halted = self.halted;
+        trace.push(delta);
+    }
+
+    /// Undoes recorded steps until `Machine::steps == step`.
+    pub fn rewind_to(&mut self, step: u64) -> Result<(), EmulatorError> {
+        let Some(mut trace) = self.trace.take() else {
+            return Err(EmulatorError::TraceUnavailable(step));
+        };
+        let recorded = step == self.steps || trace.first_step().is_some_and(|first| step >= first);
+        if !recorded || step > self.steps {
+            self.trace = Some(trace);
+            return Err(EmulatorError::TraceUnavailable(step));
+        }
+
+        while self.steps > step {
+            let Some(delta) = trace.steps.pop_back() else {
+                break;
+            };
+            for &(index, before, _) in &delta.registers {
+                set_register(&mut self.registers, index as usize, before);
+            }
+            for &(address, before, _) in delta.writes.iter().rev() {
+                let address = address as usize & (MEMORY_SIZE - 1);
+                self.memory
+                    .write_byte((address >> 4) as u16, (address & 0xF) as u16, before);
+            }
+            for &key in delta.input.iter().rev() {
+                self.console.input.push_front(key);
+            }
+            self.console.output.truncate(delta.output_len);
+            if delta.halted {
+                self.halted = false;
+            }
+            self.exit_code = delta.exit_code;
+            self.steps = delta.step;
+        }
+        self.trace = Some(trace);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+// src/isa/flags.rs
+//! Which FLAGS bits each instruction reads and writes.
+//!
+//! Follows the 8086 flag tables (`static/x8086/flags.md` lists the bits).
+//! Masks use the FLAGS register layout, so they can be tested directly
+//! against a pushed or emulated FLAGS word.
+
+use super::table::INSTRUCTIONS;
+use crate::syntax::tokens::InstructionType;
+
+const CF: u16 = 0x0001;
+const PF: u16 = 0x0004;
+const AF: u16 = 0x0010;
+const ZF: u16 = 0x0040;
+const SF: u16 = 0x0080;
+const TF: u16 = 0x0100;
+const IF: u16 = 0x0200;
+const DF: u16 = 0x0400;
+const OF: u16 = 0x0800;
+
+const ARITHMETIC: u16 = OF | SF | ZF | AF | PF | CF;
+/// Every flag the 8086 has.
+pub const ALL: u16 = ARITHMETIC | TF | IF | DF;
+
+/// Flag names in the order debuggers show them.
+const NAMES: [(u16, &str); 9] = [
+    (OF, "OF"),
+    (DF, "DF"),
+    (IF, "IF"),
+    (TF, "TF"),
+    (SF, "SF"),
+    (ZF, "ZF"),
+    (AF, "AF"),
+    (PF, "PF"),
+    (CF, "CF"),
+];
+
+/// Flag usage of one instruction, as FLAGS bit masks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FlagEffects {
+    /// Read by the instruction (branch conditions, carry in, DF for strings).
+    pub tested: u16,
+    /// Set or cleared according to the result.
+    pub modified: u16,
+    /// Always set to 1.
+    pub set: u16,
+    /// Always cleared to 0.
+    pub cleared: u16,
+    /// Left with an unspecified value.
+    pub undefined: u16,
+}
+
+impl FlagEffects {
+    fn new(tested: u16, modified: u16, cleared: u16, undefined: u16) -> Self {
+        FlagEffects {
+            tested,
+            modified,
+            set: 0,
+            cleared,
+            undefined,
+        }
+    }
+
+    /// Flags holding a meaningful value afterwards, given those that did before.
+    pub fn defined_after(&self, defined: u16) -> u16 {
+        (defined | self.modified | self.set | self.cleared) & !self.undefined
+    }
+
+    fn merge(self, other: FlagEffects) -> Self {
+        FlagEffects {
+            tested: self.tested | other.tested,
+            modified: self.modified | other.modified,
+            set: self.set | other.set,
+            cleared: self.cleared | other.cleared,
+            undefined: self.undefined | other.undefined,
+        }
+    }
+}
+
+/// Names of the flags in `mask`, e.g. `["SF", "OF"]`.
+pub fn names(mask: u16) -> Vec<&'static str> {
+    NAMES
+        .iter()
+        .filter(|(bit, _)| mask & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Condition flags read by a Jcc opcode (70h-7Fh); each pair shares them.
+fn condition(opcode: u8) -> u16 {
+    match (opcode >> 1) & 7 {
+        0 => OF,           // JO JNO
+        1 => CF,           // JB JAE
+        2 => ZF,           // JE JNE
+        3 => CF | ZF,      // JBE JA
+        4 => SF,           // JS JNS
+        5 => PF,           // JP JNP
+        6 => SF | OF,      // JL JGE
+        _ => ZF | SF | OF, // JLE JG
+    }
+}
+
+/// Effects of a mnemonic, prefixes included (`REPE CMPSB`), or `None` if
+/// some word is not an 8086 instruction.
+///
+/// Shifts and rotates only define OF for a count of 1; it is listed as
+/// modified either way.
+pub fn effects(mnemonic: &str) -> Option<FlagEffects> {
+    mnemonic
+        .split_whitespace()
+        .map(single)
+        .try_fold(FlagEffects::default(), |acc, e| Some(acc.merge(e?)))
+}
+
+fn single(mnemonic: &str) -> Option<FlagEffects> {
+    let instruction = INSTRUCTIONS
+        .iter()
+        .find(|i| i.mnemonic.eq_ignore_ascii_case(mnemonic))?;
+    if matches!(instruction.category, InstructionType::ConditionalJump) {
+        let opcode = instruction.forms.first()?.opcode[0];
+        return Some(FlagEffects::new(condition(opcode), 0, 0, 0));
+    }
+
+    let effects = match instruction.mnemonic {
+        "ADD" | "SUB" | "CMP" | "NEG" => FlagEffects::new(0, ARITHMETIC, 0, 0),
+        "ADC" | "SBB" => FlagEffects::new(CF, ARITHMETIC, 0, 0),
+        "INC" | "DEC" => FlagEffects::new(0, ARITHMETIC & !CF, 0, 0),
+        "AND" | "OR" | "XOR" | "TEST" => FlagEffects::new(0, SF | ZF | PF, OF | CF, AF),
+        "MUL" | "IMUL" => FlagEffects::new(0, OF | CF, 0, SF | ZF | AF | PF),
+        "DIV" | "IDIV" => FlagEffects::new(0, 0, 0, ARITHMETIC),
+        "AAA" | "AAS" => FlagEffects::new(AF, AF | CF, 0, OF | SF | ZF | PF),
+        "DAA" | "DAS" => FlagEffects::new(AF | CF, SF | ZF | AF | PF | CF, 0, OF),
+        "AAM" | "AAD" => FlagEffects::new(0, SF | ZF | PF, 0, OF | AF | CF),
+        "SHL" | "SAL" | "SHR" | "SAR" => FlagEffects::new(0, OF | SF | ZF | PF | CF, 0, AF),
+        "ROL" | "ROR" => FlagEffects::new(0, OF | CF, 0, 0),
+        "RCL" | "RCR" => FlagEffects::new(CF, OF | CF, 0, 0),
+        "CMPSB" | "CMPSW" | "SCASB" | "SCASW" => FlagEffects::new(DF, ARITHMETIC, 0, 0),
+        "MOVSB" | "MOVSW" | "LODSB" | "LODSW" | "STOSB" | "STOSW" => FlagEffects::new(DF, 0, 0, 0),
+        "REPE" | "REPZ" | "REPNE" | "REPNZ" | "LOOPE" | "LOOPZ" | "LOOPNE" | "LOOPNZ" => {
+            FlagEffects::new(ZF, 0, 0, 0)
+        }
+        "CLC" => FlagEffects::new(0, 0, CF, 0),
+        "CLD" => FlagEffects::new(0, 0, DF, 0),
+        "CLI" => FlagEffects::new(0, 0, IF, 0),
+        "STC" => FlagEffects {
+            set: CF,
+            ..Default::default()
+        },
+        "STD" => FlagEffects {
+            set: DF,
+            ..Default::default()
+        },
+        "STI" => FlagEffects {
+            set: IF,
+            ..Default::default()
+        },
+        "CMC" => FlagEffects::new(CF, CF, 0, 0),
+        "LAHF" => FlagEffects::new(SF | ZF | AF | PF | CF, 0, 0, 0),
+        "SAHF" => FlagEffects::new(0, SF | ZF | AF | PF | CF, 0, 0),
+        "PUSHF" => FlagEffects::new(ALL, 0, 0, 0),
+        "POPF" | "IRET" => FlagEffects::new(0, ALL, 0, 0),
+        // The old FLAGS are pushed, then IF and TF cleared for the handler
+        "INT" | "INTO" => FlagEffects::new(ALL, 0, IF | TF, 0),
+        _ => FlagEffects::default(),
+    };
+    Some(effects)
+}
@@ -0,0 +1,216 @@
+// src/isa/mod.rs
+//! The 8086 instruction set as data.
+//!
+//! Every mnemonic the assembler knows lives in [`table::INSTRUCTIONS`]. The
+//! lexer uses it to classify tokens, the validator to reject unknown
+//! mnemonics and impossible operand combinations, and the encoder to pick
+//! the opcode bytes — so the three can never disagree.
+
+pub mod flags;
+pub mod table;
+pub mod timing;
+
+use crate::syntax::tokens::InstructionType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+/// Operand pattern of an instruction form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Reg,    // general register of the operation size
+    RegMem, // register or memory of the operation size
+    Mem,    // memory of any size (LEA, LDS, LES)
+    Acc,    // AL / AX
+    Moffs,  // direct memory address (MOV accumulator short form)
+    Imm,    // immediate of the operation size
+    Imm8,   // 8-bit immediate regardless of operation size (INT, IN, OUT)
+    Imm16,  // 16-bit immediate (RET n)
+    SImm8,  // immediate that fits in a sign-extended byte (83 group)
+    One,    // the constant 1 (shift/rotate by one)
+    Three,  // the constant 3 (INT 3)
+    Cl,     // CL (shift/rotate count)
+    Dx,     // DX (port number)
+    Sreg,   // segment register
+    Rel8,   // short branch target
+    Rel16,  // near branch target
+    FarPtr, // far procedure: offset then segment (CALL/JMP seg:offset)
+}
+
+/// How the operation size is determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Word,
+    /// Either size; the opcode's low bit (w) is set for 16-bit operations.
+    WidthBit,
+}
+
+/// Contents of the ModR/M reg field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModRm {
+    None,
+    /// `/r`: the register operand.
+    Reg,
+    /// `/digit`: an opcode extension.
+    Digit(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Form {
+    pub operands: &'static [Op],
+    pub opcode: &'static [u8],
+    pub modrm: ModRm,
+    /// `+r`: the register code is added to the last opcode byte.
+    pub reg_in_opcode: bool,
+    pub size: Size,
+}
+
+#[derive(Debug)]
+pub struct Instruction {
+    pub mnemonic: &'static str,
+    pub category: InstructionType,
+    pub forms: &'static [Form],
+}
+
+/// Operand as seen by the form matcher: only the properties that decide
+/// which encoding applies.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperandClass {
+    Register {
+        code: u8,
+        width: Width,
+    },
+    Segment(u8),
+    Memory {
+        direct: bool,
+        width: Option<Width>,
+    },
+    Immediate(i64),
+    /// Segment base fixed up by the loader: always a full word immediate.
+    SegmentBase,
+    Target,
+    /// Entry of a FAR procedure, reached with a segment:offset pointer.
+    FarTarget,
+}
+
+pub fn lookup(mnemonic: &str) -> Option<&'static Instruction> {
+    let upper = mnemonic.to_uppercase();
+    table::INSTRUCTIONS.iter().find(|i| i.mnemonic == upper)
+}
+
+pub fn is_mnemonic(mnemonic: &str) -> bool {
+    lookup(mnemonic).is_some()
+}
+
+/// REP/REPE/REPNE/LOCK may precede another instruction on the same line.
+pub fn is_prefix(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic.to_uppercase().as_str(),
+        "REP" | "REPE" | "REPZ" | "REPNE" | "REPNZ" | "LOCK"
+    )
+}
+
+/// Instructions whose operand is a code address (relative or indirect).
+pub fn is_branch(mnemonic: &str) -> bool {
+    lookup(mnemonic).is_some_and(|i| {
+        i.forms
+            .iter()
+            .any(|f| f.operands.iter().any(|o| matches!(o, Op::Rel8 | Op::Rel16)))
+    })
+}
+
+impl Form {
+    /// Returns the operation size if `operands` fit this form.
+    pub fn matches(&self, operands: &[OperandClass]) -> Option<Width> {
+        if self.operands.len() != operands.len() {
+            return None;
+        }
+        let width = match self.size {
+            Size::Byte => Width::Byte,
+            Size::Word => Width::Word,
+            // The first sized register/memory operand decides; unsized forms are ambiguous
+            Size::WidthBit => self
+                .operands
+                .iter()
+                .zip(operands)
+                .filter(|(op, _)| matches!(op, Op::Reg | Op::RegMem | Op::Acc | Op::Moffs))
+                .find_map(|(_, class)| class.width())?,
+        };
+        self.operands
+            .iter()
+            .zip(operands)
+            .all(|(op, class)| op.accepts(class, width))
+            .then_some(width)
+    }
+}
+
+impl OperandClass {
+    fn width(&self) -> Option<Width> {
+        match self {
+            OperandClass::Register { width, .. } => Some(*width),
+            OperandClass::Memory { width, .. } => *width,
+            _ => None,
+        }
+    }
+}
+
+impl Op {
+    fn accepts(&self, class: &OperandClass, width: Width) -> bool {
+        use OperandClass as C;
+        let sized = |w: &Option<Width>| w.is_none_or(|w| w == width);
+        match (self, class) {
+            (Op::Reg, C::Register { width: w, .. }) => *w == width,
+            (Op::RegMem, C::Register { width: w, .. }) => *w == width,
+            (Op::RegMem, C::Memory { width: w, .. }) => sized(w),
+            (Op::Mem, C::Memory { .. }) => true,
+            (Op::Acc, C::Register { code: 0, width: w }) => *w == width,
+            (
+                Op::Moffs,
+                C::Memory {
+                    direct: true,
+                    width: w,
+                },
+            ) => sized(w),
+            (Op::Imm, C::Immediate(v)) => fits(*v, width),
+            (Op::Imm8, C::Immediate(v)) => fits(*v, Width::Byte),
+            (Op::Imm16, C::Immediate(v)) => fits(*v, Width::Word),
+            (Op::Imm, C::SegmentBase) => width == Width::Word,
+            (Op::Imm16, C::SegmentBase) => true,
+            (Op::SImm8, C::Immediate(v)) => {
+                (-128..=127).contains(v) || (width == Width::Word && (0xFF80..=0xFFFF).contains(v))
+            }
+            (Op::One, C::Immediate(1)) => true,
+            (Op::Three, C::Immediate(3)) => true,
+            (
+                Op::Cl,
+                C::Register {
+                    code: 1,
+                    width: Width::Byte,
+                },
+            ) => true,
+            (
+                Op::Dx,
+                C::Register {
+                    code: 2,
+                    width: Width::Word,
+                },
+            ) => true,
+            (Op::Sreg, C::Segment(_)) => true,
+            (Op::Rel8 | Op::Rel16, C::Target) => true,
+            (Op::FarPtr, C::FarTarget) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Accepts both signed and unsigned spellings (`-1` and `0FFFFh` are the same word).
+pub fn fits(value: i64, width: Width) -> bool {
+    match width {
+        Width::Byte => (-128..=0xFF).contains(&value),
+        Width::Word => (-32768..=0xFFFF).contains(&value),
+    }
+}
@@ -0,0 +1,343 @@
+// src/isa/table.rs
+//! Encoding table for the complete 8086 instruction set.
+//!
+//! Forms are listed in order of preference: when several encode to the same
+//! length the first one wins (e.g. `ADD AX, 5` uses 83 /0 before 05 iw).
+
+use super::ModRm::{Digit, None as NoModRm, Reg as ModReg};
+use super::Op::*;
+use super::Size::{Byte, WidthBit, Word};
+use super::{Form, Instruction, ModRm, Op, Size};
+use crate::syntax::tokens::InstructionType::{self, *};
+
+const fn form(
+    operands: &'static [Op],
+    opcode: &'static [u8],
+    modrm: ModRm,
+    reg_in_opcode: bool,
+    size: Size,
+) -> Form {
+    Form {
+        operands,
+        opcode,
+        modrm,
+        reg_in_opcode,
+        size,
+    }
+}
+
+const fn implied(opcode: &'static [u8]) -> Form {
+    form(&[], opcode, NoModRm, false, Byte)
+}
+
+const fn ins(
+    mnemonic: &'static str,
+    category: InstructionType,
+    forms: &'static [Form],
+) -> Instruction {
+    Instruction {
+        mnemonic,
+        category,
+        forms,
+    }
+}
+
+/// ADD/OR/ADC/SBB/AND/SUB/XOR/CMP share one layout: `base` is 00h, 08h ... 38h.
+macro_rules! alu {
+    ($base:literal, $digit:literal) => {
+        &[
+            form(&[RegMem, Reg], &[$base], ModReg, false, WidthBit),
+            form(&[Reg, RegMem], &[$base | 0x02], ModReg, false, WidthBit),
+            form(&[RegMem, SImm8], &[0x83], Digit($digit), false, Word),
+            form(&[Acc, Imm], &[$base | 0x04], NoModRm, false, WidthBit),
+            form(&[RegMem, Imm], &[0x80], Digit($digit), false, WidthBit),
+        ]
+    };
+}
+
+/// ROL/ROR/RCL/RCR/SHL/SHR/SAR: by one (D0/D1) or by CL (D2/D3).
+macro_rules! shift {
+    ($digit:literal) => {
+        &[
+            form(&[RegMem, One], &[0xD0], Digit($digit), false, WidthBit),
+            form(&[RegMem, Cl], &[0xD2], Digit($digit), false, WidthBit),
+        ]
+    };
+}
+
+/// NOT/NEG/MUL/IMUL/DIV/IDIV: the F6/F7 group with a single r/m operand.
+macro_rules! unary {
+    ($digit:literal) => {
+        &[form(&[RegMem], &[0xF6], Digit($digit), false, WidthBit)]
+    };
+}
+
+macro_rules! jcc {
+    ($opcode:literal) => {
+        &[form(&[Rel8], &[$opcode], NoModRm, false, Byte)]
+    };
+}
+
+pub static INSTRUCTIONS: &[Instruction] = &[
+    // --- DATA TRANSFER ---
+    ins(
+        "MOV",
+        DataTransfer,
+        &[
+            form(&[RegMem, Reg], &[0x88], ModReg, false, WidthBit),
+            form(&[Reg, RegMem], &[0x8A], ModReg, false, WidthBit),
+            form(&[Acc, Moffs], &[0xA0], NoModRm, false, WidthBit),
+            form(&[Moffs, Acc], &[0xA2], NoModRm, false, WidthBit),
+            form(&[Reg, Imm], &[0xB0], NoModRm, true, Byte),
+            form(&[Reg, Imm], &[0xB8], NoModRm, true, Word),
+            form(&[RegMem, Imm], &[0xC6], Digit(0), false, WidthBit),
+            form(&[RegMem, Sreg], &[0x8C], ModReg, false, Word),
+            form(&[Sreg, RegMem], &[0x8E], ModReg, false, Word),
+        ],
+    ),
+    ins(
+        "PUSH",
+        DataTransfer,
+        &[
+            form(&[Reg], &[0x50], NoModRm, true, Word),
+            form(&[Sreg], &[0x06], NoModRm, true, Word),
+            form(&[RegMem], &[0xFF], Digit(6), false, Word),
+        ],
+    ),
+    ins(
+        "POP",
+        DataTransfer,
+        &[
+            form(&[Reg], &[0x58], NoModRm, true, Word),
+            form(&[Sreg], &[0x07], NoModRm, true, Word),
+            form(&[RegMem], &[0x8F], Digit(0), false, Word),
+        ],
+    ),
+    ins(
+        "XCHG",
+        DataTransfer,
+        &[
+            form(&[Acc, Reg], &[0x90], NoModRm, true, Word),
+            form(&[Reg, Acc], &[0x90], NoModRm, true, Word),
+            form(&[RegMem, Reg], &[0x86], ModReg, false, WidthBit),
+            form(&[Reg, RegMem], &[0x86], ModReg, false, WidthBit),
+        ],
+    ),
+    ins(
+        "IN",
+        DataTransfer,
+        &[
+            form(&[Acc, Imm8], &[0xE4], NoModRm, false, WidthBit),
+            form(&[Acc, Dx], &[0xEC], NoModRm, false, WidthBit),
+        ],
+    ),
+    ins(
+        "OUT",
+        DataTransfer,
+        &[
+            form(&[Imm8, Acc], &[0xE6], NoModRm, false, WidthBit),
+            form(&[Dx, Acc], &[0xEE], NoModRm, false, WidthBit),
+        ],
+    ),
+    ins("XLAT", DataTransfer, &[implied(&[0xD7])]),
+    ins("XLATB", DataTransfer, &[implied(&[0xD7])]),
+    ins(
+        "LEA",
+        DataTransfer,
+        &[form(&[Reg, Mem], &[0x8D], ModReg, false, Word)],
+    ),
+    ins(
+        "LDS",
+        DataTransfer,
+        &[form(&[Reg, Mem], &[0xC5], ModReg, false, Word)],
+    ),
+    ins(
+        "LES",
+        DataTransfer,
+        &[form(&[Reg, Mem], &[0xC4], ModReg, false, Word)],
+    ),
+    ins("LAHF", DataTransfer, &[implied(&[0x9F])]),
+    ins("SAHF", DataTransfer, &[implied(&[0x9E])]),
+    ins("PUSHF", DataTransfer, &[implied(&[0x9C])]),
+    ins("POPF", DataTransfer, &[implied(&[0x9D])]),
+    // --- ARITHMETIC ---
+    ins("ADD", Arithmetic, alu!(0x00, 0)),
+    ins("ADC", Arithmetic, alu!(0x10, 2)),
+    ins("SUB", Arithmetic, alu!(0x28, 5)),
+    ins("SBB", Arithmetic, alu!(0x18, 3)),
+    ins("CMP", Arithmetic, alu!(0x38, 7)),
+    ins(
+        "INC",
+        Arithmetic,
+        &[
+            form(&[Reg], &[0x40], NoModRm, true, Word),
+            form(&[RegMem], &[0xFE], Digit(0), false, WidthBit),
+        ],
+    ),
+    ins(
+        "DEC",
+        Arithmetic,
+        &[
+            form(&[Reg], &[0x48], NoModRm, true, Word),
+            form(&[RegMem], &[0xFE], Digit(1), false, WidthBit),
+        ],
+    ),
+    ins("NEG", Arithmetic, unary!(3)),
+    ins("MUL", Arithmetic, unary!(4)),
+    ins("IMUL", Arithmetic, unary!(5)),
+    ins("DIV", Arithmetic, unary!(6)),
+    ins("IDIV", Arithmetic, unary!(7)),
+    ins("AAA", Arithmetic, &[implied(&[0x37])]),
+    ins("AAS", Arithmetic, &[implied(&[0x3F])]),
+    ins("AAM", Arithmetic, &[implied(&[0xD4, 0x0A])]),
+    ins("AAD", Arithmetic, &[implied(&[0xD5, 0x0A])]),
+    ins("DAA", Arithmetic, &[implied(&[0x27])]),
+    ins("DAS", Arithmetic, &[implied(&[0x2F])]),
+    ins("CBW", Arithmetic, &[implied(&[0x98])]),
+    ins("CWD", Arithmetic, &[implied(&[0x99])]),
+    // --- LOGIC ---
+    ins("AND", Logic, alu!(0x20, 4)),
+    ins("OR", Logic, alu!(0x08, 1)),
+    ins("XOR", Logic, alu!(0x30, 6)),
+    ins("NOT", Logic, unary!(2)),
+    ins(
+        "TEST",
+        Logic,
+        &[
+            form(&[RegMem, Reg], &[0x84], ModReg, false, WidthBit),
+            form(&[Reg, RegMem], &[0x84], ModReg, false, WidthBit),
+            form(&[Acc, Imm], &[0xA8], NoModRm, false, WidthBit),
+            form(&[RegMem, Imm], &[0xF6], Digit(0), false, WidthBit),
+        ],
+    ),
+    ins("ROL", Logic, shift!(0)),
+    ins("ROR", Logic, shift!(1)),
+    ins("RCL", Logic, shift!(2)),
+    ins("RCR", Logic, shift!(3)),
+    ins("SHL", Logic, shift!(4)),
+    ins("SAL", Logic, shift!(4)),
+    ins("SHR", Logic, shift!(5)),
+    ins("SAR", Logic, shift!(7)),
+    // --- STRING MANIPULATION ---
+    ins("MOVSB", StringManipulation, &[implied(&[0xA4])]),
+    ins("MOVSW", StringManipulation, &[implied(&[0xA5])]),
+    ins("CMPSB", StringManipulation, &[implied(&[0xA6])]),
+    ins("CMPSW", StringManipulation, &[implied(&[0xA7])]),
+    ins("STOSB", StringManipulation, &[implied(&[0xAA])]),
+    ins("STOSW", StringManipulation, &[implied(&[0xAB])]),
+    ins("LODSB", StringManipulation, &[implied(&[0xAC])]),
+    ins("LODSW", StringManipulation, &[implied(&[0xAD])]),
+    ins("SCASB", StringManipulation, &[implied(&[0xAE])]),
+    ins("SCASW", StringManipulation, &[implied(&[0xAF])]),
+    ins("REP", StringManipulation, &[implied(&[0xF3])]),
+    ins("REPE", StringManipulation, &[implied(&[0xF3])]),
+    ins("REPZ", StringManipulation, &[implied(&[0xF3])]),
+    ins("REPNE", StringManipulation, &[implied(&[0xF2])]),
+    ins("REPNZ", StringManipulation, &[implied(&[0xF2])]),
+    // --- CONTROL TRANSFER ---
+    ins(
+        "JMP",
+        ControlTransfer,
+        &[
+            form(&[Rel8], &[0xEB], NoModRm, false, Byte),
+            form(&[Rel16], &[0xE9], NoModRm, false, Word),
+            form(&[FarPtr], &[0xEA], NoModRm, false, Word),
+            form(&[RegMem], &[0xFF], Digit(4), false, Word),
+        ],
+    ),
+    ins(
+        "CALL",
+        ControlTransfer,
+        &[
+            form(&[Rel16], &[0xE8], NoModRm, false, Word),
+            form(&[FarPtr], &[0x9A], NoModRm, false, Word),
+            form(&[RegMem], &[0xFF], Digit(2), false, Word),
+        ],
+    ),
+    ins(
+        "RET",
+        ControlTransfer,
+        &[
+            implied(&[0xC3]),
+            form(&[Imm16], &[0xC2], NoModRm, false, Word),
+        ],
+    ),
+    // Always near, even inside a FAR procedure
+    ins(
+        "RETN",
+        ControlTransfer,
+        &[
+            implied(&[0xC3]),
+            form(&[Imm16], &[0xC2], NoModRm, false, Word),
+        ],
+    ),
+    ins(
+        "RETF",
+        ControlTransfer,
+        &[
+            implied(&[0xCB]),
+            form(&[Imm16], &[0xCA], NoModRm, false, Word),
+        ],
+    ),
+    ins("LOOP", ControlTransfer, jcc!(0xE2)),
+    ins("LOOPE", ControlTransfer, jcc!(0xE1)),
+    ins("LOOPZ", ControlTransfer, jcc!(0xE1)),
+    ins("LOOPNE", ControlTransfer, jcc!(0xE0)),
+    ins("LOOPNZ", ControlTransfer, jcc!(0xE0)),
+    ins("JCXZ", ControlTransfer, jcc!(0xE3)),
+    // --- CONDITIONAL JUMPS ---
+    ins("JO", ConditionalJump, jcc!(0x70)),
+    ins("JNO", ConditionalJump, jcc!(0x71)),
+    ins("JB", ConditionalJump, jcc!(0x72)),
+    ins("JC", ConditionalJump, jcc!(0x72)),
+    ins("JNAE", ConditionalJump, jcc!(0x72)),
+    ins("JAE", ConditionalJump, jcc!(0x73)),
+    ins("JNB", ConditionalJump, jcc!(0x73)),
+    ins("JNC", ConditionalJump, jcc!(0x73)),
+    ins("JE", ConditionalJump, jcc!(0x74)),
+    ins("JZ", ConditionalJump, jcc!(0x74)),
+    ins("JNE", ConditionalJump, jcc!(0x75)),
+    ins("JNZ", ConditionalJump, jcc!(0x75)),
+    ins("JBE", ConditionalJump, jcc!(0x76)),
+    ins("JNA", ConditionalJump, jcc!(0x76)),
+    ins("JA", ConditionalJump, jcc!(0x77)),
+    ins("JNBE", ConditionalJump, jcc!(0x77)),
+    ins("JS", ConditionalJump, jcc!(0x78)),
+    ins("JNS", ConditionalJump, jcc!(0x79)),
+    ins("JP", ConditionalJump, jcc!(0x7A)),
+    ins("JPE", ConditionalJump, jcc!(0x7A)),
+    ins("JNP", ConditionalJump, jcc!(0x7B)),
+    ins("JPO", ConditionalJump, jcc!(0x7B)),
+    ins("JL", ConditionalJump, jcc!(0x7C)),
+    ins("JNGE", ConditionalJump, jcc!(0x7C)),
+    ins("JGE", ConditionalJump, jcc!(0x7D)),
+    ins("JNL", ConditionalJump, jcc!(0x7D)),
+    ins("JLE", ConditionalJump, jcc!(0x7E)),
+    ins("JNG", ConditionalJump, jcc!(0x7E)),
+    ins("JG", ConditionalJump, jcc!(0x7F)),
+    ins("JNLE", ConditionalJump, jcc!(0x7F)),
+    // --- INTERRUPTS ---
+    ins(
+        "INT",
+        Interrupt,
+        &[
+            form(&[Three], &[0xCC], NoModRm, false, Byte),
+            form(&[Imm8], &[0xCD], NoModRm, false, Byte),
+        ],
+    ),
+    ins("INTO", Interrupt, &[implied(&[0xCE])]),
+    ins("IRET", Interrupt, &[implied(&[0xCF])]),
+    // --- FLAG CONTROL ---
+    ins("CLC", FlagControl, &[implied(&[0xF8])]),
+    ins("STC", FlagControl, &[implied(&[0xF9])]),
+    ins("CMC", FlagControl, &[implied(&[0xF5])]),
+    ins("CLD", FlagControl, &[implied(&[0xFC])]),
+    ins("STD", FlagControl, &[implied(&[0xFD])]),
+    ins("CLI", FlagControl, &[implied(&[0xFA])]),
+    ins("STI", FlagControl, &[implied(&[0xFB])]),
+    // --- PROCESSOR CONTROL ---
+    ins("HLT", ProcessorControl, &[implied(&[0xF4])]),
+    ins("NOP", ProcessorControl, &[implied(&[0x90])]),
+    ins("WAIT", ProcessorControl, &[implied(&[0x9B])]),
+    ins("LOCK", ProcessorControl, &[implied(&[0xF0])]),
+];
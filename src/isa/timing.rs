@@ -0,0 +1,277 @@
+// src/isa/timing.rs
+//! Documented 8086 clock counts.
+//!
+//! Figures are those of the Intel 8086 user's manual timing tables. They are
+//! worked out from the encoded bytes, so what gets timed is the form the
+//! encoder actually picked (`INC r16`, accumulator short forms, ...). Word
+//! transfers are assumed to hit even addresses; each odd one costs 4 more
+//! clocks on the real chip.
+
+use std::fmt;
+
+/// Clock count of one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timing {
+    /// Base clocks, excluding EA. Not-taken case for conditional branches,
+    /// lowest value for MUL/DIV.
+    pub clocks: u32,
+    /// Highest base value for instructions whose time depends on the
+    /// operands (MUL, IMUL, DIV, IDIV); equal to `clocks` otherwise.
+    pub max_clocks: u32,
+    /// Effective-address calculation, including 2 for a segment override.
+    pub ea: u32,
+    /// Base clocks when a conditional branch, LOOP or INTO is taken.
+    pub taken: Option<u32>,
+    /// Added for every repetition of a REP string instruction.
+    pub per_repetition: Option<u32>,
+    /// Added for every bit of a shift or rotate by CL.
+    pub per_bit: Option<u32>,
+}
+
+impl Timing {
+    fn fixed(clocks: u32) -> Self {
+        Self::range(clocks, clocks)
+    }
+
+    fn range(clocks: u32, max_clocks: u32) -> Self {
+        Timing {
+            clocks,
+            max_clocks,
+            ..Default::default()
+        }
+    }
+
+    fn branch(taken: u32, not_taken: u32) -> Self {
+        Timing {
+            taken: Some(taken),
+            ..Self::fixed(not_taken)
+        }
+    }
+
+    fn repeated(self, clocks: u32) -> Self {
+        Timing {
+            per_repetition: Some(clocks),
+            ..self
+        }
+    }
+
+    /// Fewest clocks the instruction can take (branch not taken, no
+    /// repetitions, zero shift count).
+    pub fn min_total(&self) -> u32 {
+        self.clocks + self.ea
+    }
+
+    /// Most clocks for a single execution, ignoring repetitions and shift
+    /// counts.
+    pub fn max_total(&self) -> u32 {
+        self.max_clocks.max(self.taken.unwrap_or(0)) + self.ea
+    }
+}
+
+/// Manual notation: `9+EA(6)=15`, `16/4` (taken/not taken), `70-77`,
+/// `9+17/rep`, `8+4/bit`.
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = |clocks: u32, max: u32| {
+            if clocks == max {
+                clocks.to_string()
+            } else {
+                format!("{}-{}", clocks, max)
+            }
+        };
+        match self.taken {
+            Some(taken) => write!(f, "{}/{}", taken, self.clocks)?,
+            None => write!(f, "{}", base(self.clocks, self.max_clocks))?,
+        }
+        if self.ea > 0 {
+            let total = match self.taken {
+                Some(taken) => format!("{}/{}", taken + self.ea, self.min_total()),
+                None => base(self.min_total(), self.max_clocks + self.ea),
+            };
+            write!(f, "+EA({})={}", self.ea, total)?;
+        }
+        if let Some(clocks) = self.per_repetition {
+            write!(f, "+{}/rep", clocks)?;
+        }
+        if let Some(clocks) = self.per_bit {
+            write!(f, "+{}/bit", clocks)?;
+        }
+        Ok(())
+    }
+}
+
+/// EA calculation time for a memory ModR/M byte.
+fn ea_clocks(modrm: u8) -> u32 {
+    let (mode, rm) = (modrm >> 6, modrm & 7);
+    match (mode, rm) {
+        (0, 0b110) => 6,          // [disp16]
+        (0, 0b100..=0b111) => 5,  // [SI] [DI] [BX]
+        (0, 0b000 | 0b011) => 7,  // [BX+SI] [BP+DI]
+        (0, _) => 8,              // [BX+DI] [BP+SI]
+        (_, 0b100..=0b111) => 9,  // [SI+disp] ... [BX+disp]
+        (_, 0b000 | 0b011) => 11, // [BX+SI+disp] [BP+DI+disp]
+        _ => 12,                  // [BX+DI+disp] [BP+SI+disp]
+    }
+}
+
+fn has_modrm(opcode: u8) -> bool {
+    match opcode {
+        0x00..=0x3F => opcode & 7 < 4,
+        0x80..=0x8F | 0xC4..=0xC7 | 0xD0..=0xD3 | 0xF6 | 0xF7 | 0xFE | 0xFF => true,
+        _ => false,
+    }
+}
+
+/// Clocks for one encoded instruction, or `None` for bytes that are not a
+/// single 8086 instruction.
+///
+/// The `J!cc $+5` / `JMP` pair synthesized for out-of-range conditional
+/// jumps is timed as one branch: taken means falling through to the JMP.
+pub fn timing(bytes: &[u8]) -> Option<Timing> {
+    let mut rest = bytes;
+    let (mut segment_override, mut rep, mut lock) = (false, false, false);
+    loop {
+        match rest.first()? {
+            0x26 | 0x2E | 0x36 | 0x3E => segment_override = true,
+            0xF2 | 0xF3 => rep = true,
+            0xF0 => lock = true,
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+
+    let opcode = rest[0];
+    let modrm = rest.get(1).copied().filter(|_| has_modrm(opcode));
+    let memory = modrm.filter(|m| m >> 6 != 3);
+    let digit = modrm.map_or(0, |m| (m >> 3) & 7);
+    let word = opcode & 1 == 1;
+    // Register form / memory form of a ModR/M instruction
+    let by_mode = |register: u32, mem: u32| {
+        if memory.is_some() {
+            Timing::fixed(mem)
+        } else {
+            Timing::fixed(register)
+        }
+    };
+
+    let mut timing = match opcode {
+        0x06 | 0x0E | 0x16 | 0x1E => Timing::fixed(10), // PUSH sreg
+        0x07 | 0x17 | 0x1F => Timing::fixed(8),         // POP sreg
+        0x27 | 0x2F | 0x37 | 0x3F => Timing::fixed(4),  // DAA DAS AAA AAS
+        // ALU: r/m,reg / reg,r/m / acc,imm
+        0x00..=0x3F => match opcode & 7 {
+            0 | 1 if opcode & 0x38 == 0x38 => by_mode(3, 9),
+            0 | 1 => by_mode(3, 16),
+            2 | 3 => by_mode(3, 9),
+            4 | 5 => Timing::fixed(4),
+            _ => return None,
+        },
+        0x40..=0x4F => Timing::fixed(2), // INC/DEC r16
+        0x50..=0x57 => Timing::fixed(11),
+        0x58..=0x5F => Timing::fixed(8),
+        0x70..=0x7F if rest.len() == 5 && rest[1] == 3 && rest[2] == 0xE9 => {
+            Timing::branch(4 + 15, 16)
+        }
+        0x70..=0x7F => Timing::branch(16, 4),
+        0x80..=0x83 if digit == 7 => by_mode(4, 10), // CMP r/m,imm
+        0x80..=0x83 => by_mode(4, 17),
+        0x84 | 0x85 => by_mode(3, 9),  // TEST r/m,reg
+        0x86 | 0x87 => by_mode(4, 17), // XCHG r/m,reg
+        0x88 | 0x89 | 0x8C => by_mode(2, 9),
+        0x8A | 0x8B | 0x8E => by_mode(2, 8),
+        0x8D => Timing::fixed(2), // LEA
+        0x8F => by_mode(8, 17),
+        0x90..=0x97 => Timing::fixed(3), // NOP, XCHG AX,r16
+        0x98 => Timing::fixed(2),
+        0x99 => Timing::fixed(5),
+        0x9A => Timing::fixed(28), // CALL far
+        0x9B => Timing::fixed(3),
+        0x9C => Timing::fixed(10),
+        0x9D => Timing::fixed(8),
+        0x9E | 0x9F => Timing::fixed(4),
+        0xA0..=0xA3 => Timing::fixed(10), // MOV acc,moffs
+        0xA4 | 0xA5 if rep => Timing::fixed(9).repeated(17),
+        0xA4 | 0xA5 => Timing::fixed(18),
+        0xA6 | 0xA7 if rep => Timing::fixed(9).repeated(22),
+        0xA6 | 0xA7 => Timing::fixed(22),
+        0xA8 | 0xA9 => Timing::fixed(4),
+        0xAA | 0xAB if rep => Timing::fixed(9).repeated(10),
+        0xAA | 0xAB => Timing::fixed(11),
+        0xAC | 0xAD if rep => Timing::fixed(9).repeated(13),
+        0xAC | 0xAD => Timing::fixed(12),
+        0xAE | 0xAF if rep => Timing::fixed(9).repeated(15),
+        0xAE | 0xAF => Timing::fixed(15),
+        0xB0..=0xBF => Timing::fixed(4),
+        0xC2 => Timing::fixed(12),
+        0xC3 => Timing::fixed(8),
+        0xC4 | 0xC5 => Timing::fixed(16), // LES LDS
+        0xC6 | 0xC7 => by_mode(4, 10),
+        0xCA => Timing::fixed(17),
+        0xCB => Timing::fixed(18),
+        0xCC => Timing::fixed(52),
+        0xCD => Timing::fixed(51),
+        0xCE => Timing::branch(53, 4), // INTO
+        0xCF => Timing::fixed(24),
+        0xD0 | 0xD1 => by_mode(2, 15),
+        0xD2 | 0xD3 => Timing {
+            per_bit: Some(4),
+            ..by_mode(8, 20)
+        },
+        0xD4 => Timing::fixed(83),
+        0xD5 => Timing::fixed(60),
+        0xD7 => Timing::fixed(11),
+        0xE0 => Timing::branch(19, 5), // LOOPNE
+        0xE1 => Timing::branch(18, 6), // LOOPE
+        0xE2 => Timing::branch(17, 5), // LOOP
+        0xE3 => Timing::branch(18, 6), // JCXZ
+        0xE4..=0xE7 => Timing::fixed(10),
+        0xE8 => Timing::fixed(19),
+        0xE9..=0xEB => Timing::fixed(15),
+        0xEC..=0xEF => Timing::fixed(8),
+        0xF4 | 0xF5 | 0xF8..=0xFD => Timing::fixed(2),
+        0xF6 | 0xF7 => match (digit, word, memory.is_some()) {
+            (0, _, _) => by_mode(5, 11), // TEST r/m,imm
+            (2 | 3, _, _) => by_mode(3, 16),
+            (4, false, false) => Timing::range(70, 77),
+            (4, false, true) => Timing::range(76, 83),
+            (4, true, false) => Timing::range(118, 133),
+            (4, true, true) => Timing::range(124, 139),
+            (5, false, false) => Timing::range(80, 98),
+            (5, false, true) => Timing::range(86, 104),
+            (5, true, false) => Timing::range(128, 154),
+            (5, true, true) => Timing::range(134, 160),
+            (6, false, false) => Timing::range(80, 90),
+            (6, false, true) => Timing::range(86, 96),
+            (6, true, false) => Timing::range(144, 162),
+            (6, true, true) => Timing::range(150, 168),
+            (7, false, false) => Timing::range(101, 112),
+            (7, false, true) => Timing::range(107, 118),
+            (7, true, false) => Timing::range(165, 184),
+            (7, true, true) => Timing::range(171, 190),
+            _ => return None,
+        },
+        0xFE | 0xFF => match digit {
+            0 | 1 if word => by_mode(2, 15),
+            0 | 1 => by_mode(3, 15),
+            2 if word => by_mode(16, 21),
+            3 if word => Timing::fixed(37),
+            4 if word => by_mode(11, 18),
+            5 if word => Timing::fixed(24),
+            6 if word => by_mode(11, 16),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    if let Some(modrm) = memory {
+        timing.ea = ea_clocks(modrm);
+    }
+    if segment_override {
+        timing.ea += 2;
+    }
+    if lock {
+        timing.clocks += 2;
+        timing.max_clocks += 2;
+    }
+    Some(timing)
+}
@@ -1,22 +1,50 @@
 // src/lib.rs
-#![allow(unused)]
-#![allow(dead_code)]
 
 use chumsky::prelude::*;
 use serde::Serialize;
-use std::cmp::Ordering;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
 mod ast;
+mod disassembler;
+mod emulator;
+mod isa;
+mod output;
 mod semantics;
 mod syntax;
 
-use ast::{LineNode, Statement};
-use semantics::encoder::{pass_one, pass_two};
-use semantics::validator::validate;
+use ast::{LineNode, MemoryModel, Statement};
+use semantics::encoder::{AssemblerOptions, pass_one, pass_two, to_hex};
+use semantics::validator::{SymbolInfo, validate_with_options};
+use syntax::include::{Includes, MemoryFiles};
+use syntax::preprocessor::{self, Expansion};
 use syntax::{lexer::lexer, parser::parser, tokens::Token};
 
+thread_local! {
+    /// Files `INCLUDE` can read, handed over by the browser.
+    static INCLUDE_FILES: RefCell<MemoryFiles> = RefCell::new(MemoryFiles::default());
+}
+
+/// Makes `text` available to `INCLUDE path` / `%include "path"`.
+#[wasm_bindgen]
+pub fn register_include_file(path: &str, text: &str) {
+    INCLUDE_FILES.with(|files| files.borrow_mut().insert(path, text));
+}
+
+#[wasm_bindgen]
+pub fn clear_include_files() {
+    INCLUDE_FILES.with(|files| files.borrow_mut().clear());
+}
+
+/// Expands macros and includes, reading the registered include files.
+fn preprocess(source: &str, tokens: Vec<(Token, SimpleSpan)>) -> Expansion {
+    INCLUDE_FILES.with(|files| {
+        let files = files.borrow();
+        preprocessor::expand(source, tokens, &Includes::new(&*files, ""))
+    })
+}
+
 #[derive(Serialize)]
 pub struct JsSymbolRecord {
     pub name: String,
@@ -35,6 +63,91 @@ pub struct JsLineAnalysis {
     pub instruction: String,
     pub address: Option<String>,
     pub machine_code: Option<String>,
+    pub timing: Option<JsTiming>,
+    pub flags: Option<JsFlagEffects>,
+    /// Linter warning; the line still assembles.
+    pub warning_message: Option<String>,
+    /// Left out by a false IF; not assembled nor checked.
+    pub inactive: bool,
+}
+
+/// Flags an instruction reads and writes, by name (`"ZF"`, `"CF"`...).
+#[derive(Serialize, Clone)]
+pub struct JsFlagEffects {
+    pub tested: Vec<&'static str>,
+    pub modified: Vec<&'static str>,
+    pub set: Vec<&'static str>,
+    pub cleared: Vec<&'static str>,
+    pub undefined: Vec<&'static str>,
+}
+
+impl From<isa::flags::FlagEffects> for JsFlagEffects {
+    fn from(effects: isa::flags::FlagEffects) -> Self {
+        use isa::flags::names;
+        JsFlagEffects {
+            tested: names(effects.tested),
+            modified: names(effects.modified),
+            set: names(effects.set),
+            cleared: names(effects.cleared),
+            undefined: names(effects.undefined),
+        }
+    }
+}
+
+/// 8086 clock count of a line's instructions; totals include EA.
+#[derive(Serialize, Clone)]
+pub struct JsTiming {
+    /// Timing-table notation, e.g. `9+EA(6)=15` or `16/4`.
+    pub text: String,
+    pub clocks: u32,
+    pub max_clocks: u32,
+    pub ea: u32,
+    pub taken: Option<u32>,
+    pub per_repetition: Option<u32>,
+    pub per_bit: Option<u32>,
+}
+
+impl JsTiming {
+    /// A line that expands to several instructions (a macro call,
+    /// `.STARTUP`, `.EXIT`) takes the time of all of them.
+    fn followed_by(self, next: JsTiming) -> JsTiming {
+        let taken = (self.taken.is_some() || next.taken.is_some())
+            .then(|| self.taken.unwrap_or(self.clocks) + next.taken.unwrap_or(next.clocks));
+        let add = |a: Option<u32>, b: Option<u32>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        JsTiming {
+            text: format!("{} + {}", self.text, next.text),
+            clocks: self.clocks + next.clocks,
+            max_clocks: self.max_clocks + next.max_clocks,
+            ea: self.ea + next.ea,
+            taken,
+            per_repetition: add(self.per_repetition, next.per_repetition),
+            per_bit: add(self.per_bit, next.per_bit),
+        }
+    }
+}
+
+/// Straight-line run of instructions: starts at a label or after a jump,
+/// call, return or interrupt and ends with the next one.
+#[derive(Serialize)]
+pub struct JsBlockTiming {
+    pub label: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Every conditional branch falls through, no REP repetitions.
+    pub clocks: u32,
+    /// Every branch taken, slowest MUL/DIV.
+    pub max_clocks: u32,
+}
+
+#[derive(Serialize, Default)]
+pub struct JsProgramTiming {
+    pub blocks: Vec<JsBlockTiming>,
+    /// Sum over all instructions, each executed once.
+    pub clocks: u32,
+    pub max_clocks: u32,
 }
 
 #[derive(Serialize)]
@@ -45,6 +158,8 @@ pub struct JsToken {
     pub line: usize,
     pub start: usize,
     pub end: usize,
+    /// Flag effects of instruction tokens.
+    pub flags: Option<JsFlagEffects>,
 }
 
 #[derive(Serialize)]
@@ -52,9 +167,20 @@ pub struct JsCompilerResult {
     pub success: bool,
     pub tokens: Option<Vec<JsToken>>,
     pub errors: Vec<String>,
+    pub warnings: Vec<String>,
     pub program: Option<ast::Program>,
     pub symbol_table: Vec<JsSymbolRecord>,
     pub line_analysis: Vec<JsLineAnalysis>,
+    pub timing: JsProgramTiming,
+}
+
+/// Per-line extras shown next to the machine code.
+#[derive(Default)]
+struct LineNotes {
+    timing: Option<JsTiming>,
+    flags: Option<JsFlagEffects>,
+    warning: Option<String>,
+    inactive: bool,
 }
 
 fn calculate_line(source: &str, offset: usize) -> usize {
@@ -77,6 +203,7 @@ fn generate_line_analysis(
     error_spans: &[(usize, usize)],
     semantic_errors: &HashMap<usize, String>,
     stmt_info: &HashMap<usize, (String, String)>,
+    notes: &HashMap<usize, LineNotes>,
 ) -> Vec<JsLineAnalysis> {
     let mut lines = Vec::new();
     for (i, raw_line) in source.lines().enumerate() {
@@ -100,7 +227,10 @@ fn generate_line_analysis(
             error_msg = Some(msg.clone());
         }
 
-        let (addr, code) = if let Some((a, c)) = stmt_info.get(&line_num) {
+        let inactive = notes.get(&line_num).is_some_and(|n| n.inactive);
+        let (addr, code) = if inactive {
+            (None, None)
+        } else if let Some((a, c)) = stmt_info.get(&line_num) {
             if is_correct {
                 (Some(a.clone()), Some(c.clone()))
             } else {
@@ -110,6 +240,7 @@ fn generate_line_analysis(
             (None, None)
         };
 
+        let note = notes.get(&line_num);
         lines.push(JsLineAnalysis {
             line_number: line_num,
             is_correct,
@@ -117,20 +248,15 @@ fn generate_line_analysis(
             instruction: raw_line.to_string(),
             address: addr,
             machine_code: code,
+            timing: note.and_then(|n| n.timing.clone()).filter(|_| is_correct),
+            flags: note.and_then(|n| n.flags.clone()).filter(|_| is_correct),
+            warning_message: note.and_then(|n| n.warning.clone()),
+            inactive,
         });
     }
     lines
 }
 
-fn get_line_content(source: &str, offset: usize) -> &str {
-    let start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
-    let end = source[offset..]
-        .find('\n')
-        .map(|i| offset + i)
-        .unwrap_or(source.len());
-    &source[start..end]
-}
-
 #[wasm_bindgen]
 pub fn analyze_full_program(source: &str) -> JsValue {
     let result = analyze_full_program_struct(source);
@@ -150,27 +276,29 @@ pub fn analyze_full_program_struct(source: &str) -> JsCompilerResult {
         all_error_spans.push((err.span().start, err.span().end));
     }
 
-    let js_tokens = if let Some(tokens) = &tokens_result {
-        Some(
-            tokens
-                .iter()
-                .map(|(token, span)| {
-                    let line = calculate_line(source, span.start);
-                    let raw_element = &source[span.start..span.end];
-                    JsToken {
-                        element: raw_element.to_string(),
-                        category: token.category(),
-                        detail: token.description(),
-                        line,
-                        start: span.start,
-                        end: span.end,
-                    }
-                })
-                .collect(),
-        )
-    } else {
-        None
-    };
+    let js_tokens = tokens_result.as_ref().map(|tokens| {
+        tokens
+            .iter()
+            .map(|(token, span)| {
+                let line = calculate_line(source, span.start);
+                let raw_element = &source[span.start..span.end];
+                JsToken {
+                    element: raw_element.to_string(),
+                    category: token.category(),
+                    detail: token.description(),
+                    line,
+                    start: span.start,
+                    end: span.end,
+                    flags: match token {
+                        Token::Instruction(_, mnemonic) => {
+                            isa::flags::effects(mnemonic).map(JsFlagEffects::from)
+                        }
+                        _ => None,
+                    },
+                }
+            })
+            .collect()
+    });
 
     if tokens_result.is_none() {
         let lines = generate_line_analysis(
@@ -179,36 +307,46 @@ pub fn analyze_full_program_struct(source: &str) -> JsCompilerResult {
             &all_error_spans,
             &HashMap::new(),
             &HashMap::new(),
+            &HashMap::new(),
         );
         return JsCompilerResult {
             success: false,
             tokens: None,
             errors: all_errors_msg,
+            warnings: vec![],
             program: None,
             symbol_table: vec![],
             line_analysis: lines,
+            timing: JsProgramTiming::default(),
         };
     }
 
-    let tokens = tokens_result.unwrap();
-    let token_stream = chumsky::input::Stream::from_iter(tokens.into_iter())
+    let expansion = preprocess(source, tokens_result.unwrap());
+    for err in &expansion.errors {
+        let (msg, span) = expansion.locate(err.span, err.message.clone());
+        all_errors_msg.push(format!("[MAC] {}", msg));
+        all_error_spans.push(span);
+    }
+
+    let token_stream = chumsky::input::Stream::from_iter(expansion.tokens.clone())
         .map(SimpleSpan::from(len..len), |(t, s)| (t, s));
 
     let (ast, parse_errs) = parser().parse(token_stream).into_output_errors();
 
     for err in parse_errs {
         // Forensic analysis on the full line
-        let line_content = get_line_content(source, err.span().start);
+        let line_content = expansion.files.line_text(err.span().start);
         let diag = semantics::diagnostics::diagnose_syntax_error(line_content);
 
-        let msg = if diag != "Sintaxis inválida o token faltante" {
-            format!("[PAR] {}", diag)
+        let diag = if diag != "Sintaxis inválida o token faltante" {
+            diag
         } else {
-            format!("[PAR] Invalid syntax or missing token")
+            "Invalid syntax or missing token".to_string()
         };
 
-        all_errors_msg.push(msg);
-        all_error_spans.push((err.span().start, err.span().end));
+        let (msg, span) = expansion.locate(*err.span(), diag);
+        all_errors_msg.push(format!("[PAR] {}", msg));
+        all_error_spans.push(span);
     }
 
     let program = ast.clone();
@@ -217,52 +355,79 @@ pub fn analyze_full_program_struct(source: &str) -> JsCompilerResult {
         for spanned in prog {
             if let LineNode::Error(_) = &spanned.node {
                 // Forensic analysis on the full line (using span start is safe)
-                let line_content = get_line_content(source, spanned.span.0);
+                let line_content = expansion.files.line_text(spanned.span.0);
                 let specific_msg = semantics::diagnostics::diagnose_syntax_error(line_content);
-                all_errors_msg.push(format!("[PAR] {}", specific_msg));
-                all_error_spans.push(spanned.span);
+                let span = SimpleSpan::from(spanned.span.0..spanned.span.1);
+                let (msg, span) = expansion.locate(span, specific_msg);
+                all_errors_msg.push(format!("[PAR] {}", msg));
+                all_error_spans.push(span);
             }
         }
     }
     let mut js_symbol_table = Vec::new();
-    let mut stmt_info_map = HashMap::new();
+    let mut stmt_info_map: HashMap<usize, (String, String)> = HashMap::new();
     let mut semantic_error_map = HashMap::new();
+    let mut notes: HashMap<usize, LineNotes> = HashMap::new();
+    for &line in &expansion.inactive {
+        let origin = expansion.origin(line);
+        if origin.file == syntax::include::MAIN_FILE && origin.macro_line.is_none() {
+            notes.entry(origin.line).or_default().inactive = true;
+        }
+    }
+    let mut all_warnings_msg = Vec::new();
+    let mut program_timing = JsProgramTiming::default();
 
     if let Some(prog) = &program {
-        let (semantic_errs, mut symbol_info_map) = validate(prog);
-
-        for err in semantic_errs {
-            let msg = format!("[SEM] {}", err.message);
+        // A TINY program is laid out as the .COM file it becomes
+        let options = AssemblerOptions {
+            single_segment: expansion.model == Some(MemoryModel::Tiny),
+            ..Default::default()
+        };
+        let (semantic_errs, mut symbol_info_map) = validate_with_options(prog, &options);
+
+        let (address_map, size_map) = pass_one(prog, &mut symbol_info_map, &options);
+        let (machine_code_map, encoding_errs) =
+            pass_two(prog, &address_map, &size_map, &symbol_info_map, &options);
+
+        for err in semantic_errs.into_iter().chain(encoding_errs) {
+            // Statement numbers differ from source lines once macros and
+            // includes expand
+            let line = expansion.main_line(err.line);
+            let msg = match expansion.note(err.line) {
+                Some(note) => format!("[SEM] {} ({})", err.message, note),
+                None => format!("[SEM] {}", err.message),
+            };
             // We store it in the map for line attribution
             // Note: If multiple errors on one line, last one wins or we append?
             // Let's overwrite for now or join.
-            if let Some(existing) = semantic_error_map.get_mut(&err.line) {
+            if let Some(existing) = semantic_error_map.get_mut(&line) {
                 *existing = format!("{}; {}", existing, msg);
             } else {
-                semantic_error_map.insert(err.line, msg.clone());
+                semantic_error_map.insert(line, msg.clone());
             }
 
             // Also add to main errors list for global status
-            all_errors_msg.push(format!("Line {}: {}", err.line, err.message));
+            all_errors_msg.push(format!(
+                "Line {}: {}",
+                expansion.describe(err.line),
+                err.message
+            ));
         }
 
-        let address_map = pass_one(prog, &mut symbol_info_map);
-        let machine_code_map = pass_two(prog, &address_map);
-
         for (name, info) in symbol_info_map {
             js_symbol_table.push(JsSymbolRecord {
-                name: name,
+                name,
                 type_: format!("{:?}", info.type_),
                 data_type: format!("{:?}", info.data_type),
                 value: info.offset.unwrap_or(0),
                 segment: info.segment,
-                line: info.line_defined,
+                line: expansion.main_line(info.line_defined),
             });
         }
         js_symbol_table.sort_by(|a, b| a.name.cmp(&b.name));
 
         for (idx, spanned) in prog.iter().enumerate() {
-            let line = calculate_line(source, spanned.span.0);
+            let line = expansion.main_line(idx + 1);
 
             let addr_str = if let Some(addr) = address_map.get(&idx) {
                 format!("{:04X}", addr)
@@ -270,12 +435,52 @@ pub fn analyze_full_program_struct(source: &str) -> JsCompilerResult {
                 String::new()
             };
 
-            let code_str = machine_code_map.get(&idx).cloned().unwrap_or_default();
+            let code_str = machine_code_map
+                .get(&idx)
+                .map(|bytes| to_hex(bytes))
+                .unwrap_or_default();
+
+            if !code_str.is_empty()
+                && let LineNode::Statement(Statement::Instruction { mnemonic, .. }) = &spanned.node
+                && let Some(effects) = isa::flags::effects(mnemonic)
+            {
+                notes.entry(line).or_default().flags = Some(JsFlagEffects::from(effects));
+            }
 
-            if !addr_str.is_empty() || !code_str.is_empty() {
-                stmt_info_map.insert(line, (addr_str, code_str));
+            // A macro invocation or INCLUDE line shows the code of all it
+            // brings in
+            match stmt_info_map.get_mut(&line) {
+                Some((addr, code)) => {
+                    if addr.is_empty() {
+                        *addr = addr_str;
+                    }
+                    if !code_str.is_empty() {
+                        if !code.is_empty() {
+                            code.push(' ');
+                        }
+                        code.push_str(&code_str);
+                    }
+                }
+                None if !addr_str.is_empty() || !code_str.is_empty() => {
+                    stmt_info_map.insert(line, (addr_str, code_str));
+                }
+                None => {}
             }
         }
+
+        program_timing = analyze_timing(&expansion, prog, &machine_code_map, &mut notes);
+
+        for warning in semantics::analyzer::lint(prog) {
+            notes
+                .entry(expansion.main_line(warning.line))
+                .or_default()
+                .warning = Some(format!("[LINT] {}", warning.message));
+            all_warnings_msg.push(format!(
+                "Line {}: {}",
+                expansion.describe(warning.line),
+                warning.message
+            ));
+        }
     }
 
     let line_analysis = generate_line_analysis(
@@ -284,14 +489,519 @@ pub fn analyze_full_program_struct(source: &str) -> JsCompilerResult {
         &all_error_spans,
         &semantic_error_map,
         &stmt_info_map,
+        &notes,
     );
 
     JsCompilerResult {
         success: all_errors_msg.is_empty(),
         tokens: js_tokens,
         errors: all_errors_msg,
+        warnings: all_warnings_msg,
         program,
         symbol_table: js_symbol_table,
-        line_analysis: line_analysis,
+        line_analysis,
+        timing: program_timing,
+    }
+}
+
+/// Notes the clock count of every instruction line and returns the
+/// per-block and program totals.
+fn analyze_timing(
+    expansion: &Expansion,
+    prog: &ast::Program,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    notes: &mut HashMap<usize, LineNotes>,
+) -> JsProgramTiming {
+    let mut program = JsProgramTiming::default();
+    let mut open: Option<JsBlockTiming> = None;
+    let mut label = None;
+
+    for (idx, spanned) in prog.iter().enumerate() {
+        let line = expansion.main_line(idx + 1);
+        let mnemonic = match &spanned.node {
+            LineNode::Statement(Statement::Instruction { mnemonic, .. }) => mnemonic,
+            LineNode::Statement(Statement::Label(name) | Statement::Procedure { name, .. }) => {
+                program.blocks.extend(open.take());
+                label = Some(name.clone());
+                continue;
+            }
+            LineNode::Statement(
+                Statement::Segment { .. } | Statement::SegmentEnd | Statement::ProcedureEnd { .. },
+            ) => {
+                program.blocks.extend(open.take());
+                label = None;
+                continue;
+            }
+            _ => continue,
+        };
+        let Some(timing) = machine_code_map
+            .get(&idx)
+            .and_then(|bytes| isa::timing::timing(bytes))
+        else {
+            continue;
+        };
+
+        let block = open.get_or_insert_with(|| JsBlockTiming {
+            label: label.take(),
+            start_line: line,
+            end_line: line,
+            clocks: 0,
+            max_clocks: 0,
+        });
+        block.end_line = line;
+        block.clocks += timing.min_total();
+        block.max_clocks += timing.max_total();
+        program.clocks += timing.min_total();
+        program.max_clocks += timing.max_total();
+
+        let this = JsTiming {
+            text: timing.to_string(),
+            clocks: timing.min_total(),
+            max_clocks: timing.max_total(),
+            ea: timing.ea,
+            taken: timing.taken.map(|clocks| clocks + timing.ea),
+            per_repetition: timing.per_repetition,
+            per_bit: timing.per_bit,
+        };
+        let noted = &mut notes.entry(line).or_default().timing;
+        *noted = Some(match noted.take() {
+            Some(earlier) => earlier.followed_by(this),
+            None => this,
+        });
+
+        let ends_block = mnemonic
+            .split_whitespace()
+            .last()
+            .and_then(isa::lookup)
+            .is_some_and(|i| {
+                matches!(
+                    i.category,
+                    syntax::tokens::InstructionType::ControlTransfer
+                        | syntax::tokens::InstructionType::ConditionalJump
+                        | syntax::tokens::InstructionType::Interrupt
+                )
+            });
+        if ends_block {
+            program.blocks.extend(open.take());
+        }
+    }
+    program.blocks.extend(open);
+    program
+}
+
+/// Everything the output writers need from an error-free assembly.
+struct Assembly {
+    program: ast::Program,
+    /// Main-file line of each statement.
+    lines: Vec<usize>,
+    symbol_table: HashMap<String, SymbolInfo>,
+    address_map: HashMap<usize, u64>,
+    machine_code_map: HashMap<usize, Vec<u8>>,
+}
+
+/// Runs the whole pipeline, stopping at the first stage that reports errors.
+fn assemble(source: &str, options: &AssemblerOptions) -> Result<Assembly, Vec<String>> {
+    let len = source.len();
+    let (tokens, lex_errs) = lexer().parse(source).into_output_errors();
+    if !lex_errs.is_empty() {
+        return Err(lex_errs
+            .iter()
+            .map(|e| format!("Line {}: {}", calculate_line(source, e.span().start), e))
+            .collect());
+    }
+
+    let expansion = preprocess(source, tokens.unwrap_or_default());
+    let token_stream = chumsky::input::Stream::from_iter(expansion.tokens.clone())
+        .map(SimpleSpan::from(len..len), |(t, s)| (t, s));
+    let (ast, parse_errs) = parser().parse(token_stream).into_output_errors();
+    let mut errors: Vec<String> = expansion
+        .errors
+        .iter()
+        .map(|e| format!("Line {}: {}", expansion.describe_span(e.span), e.message))
+        .collect();
+    errors.extend(
+        parse_errs
+            .iter()
+            .map(|e| format!("Line {}: {}", expansion.describe_span(*e.span()), e)),
+    );
+    let program = ast.unwrap_or_default();
+    for spanned in &program {
+        if let LineNode::Error(msg) = &spanned.node {
+            let span = SimpleSpan::from(spanned.span.0..spanned.span.1);
+            errors.push(format!("Line {}: {}", expansion.describe_span(span), msg));
+        }
     }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let (semantic_errs, mut symbol_table) = validate_with_options(&program, options);
+    let (address_map, size_map) = pass_one(&program, &mut symbol_table, options);
+    let (machine_code_map, encoding_errs) =
+        pass_two(&program, &address_map, &size_map, &symbol_table, options);
+
+    let errors: Vec<String> = semantic_errs
+        .iter()
+        .chain(&encoding_errs)
+        .map(|err| format!("Line {}: {}", expansion.describe(err.line), err.message))
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(Assembly {
+        lines: (1..=program.len())
+            .map(|l| expansion.main_line(l))
+            .collect(),
+        program,
+        symbol_table,
+        address_map,
+        machine_code_map,
+    })
+}
+
+/// Assembles `source` into a flat DOS .COM image loaded at 100h.
+pub fn assemble_com(source: &str) -> Result<Vec<u8>, Vec<String>> {
+    let options = AssemblerOptions {
+        single_segment: true,
+        ..Default::default()
+    };
+    let assembly = assemble(source, &options)?;
+    output::com::build_com(
+        &assembly.program,
+        &assembly.address_map,
+        &assembly.machine_code_map,
+    )
+    .map_err(|e| vec![e])
+}
+
+#[wasm_bindgen]
+pub fn assemble_com_binary(source: &str) -> Result<Vec<u8>, JsValue> {
+    assemble_com(source).map_err(|errors| JsValue::from_str(&errors.join("\n")))
+}
+
+/// Assembles `source` into a DOS MZ executable with one segment per
+/// `SEGMENT` block and relocations for every segment reference.
+pub fn assemble_exe(source: &str) -> Result<Vec<u8>, Vec<String>> {
+    let assembly = assemble(source, &AssemblerOptions::default())?;
+    output::exe::build_exe(
+        &assembly.program,
+        &assembly.address_map,
+        &assembly.machine_code_map,
+        &assembly.symbol_table,
+    )
+    .map_err(|e| vec![e])
+}
+
+#[wasm_bindgen]
+pub fn assemble_exe_binary(source: &str) -> Result<Vec<u8>, JsValue> {
+    assemble_exe(source).map_err(|errors| JsValue::from_str(&errors.join("\n")))
+}
+
+/// Intel HEX image of `source`, with segments at their paragraphs.
+pub fn assemble_intel_hex(source: &str) -> Result<String, Vec<String>> {
+    let assembly = assemble(source, &AssemblerOptions::default())?;
+    Ok(output::hex::to_intel_hex(
+        &assembly.program,
+        &assembly.address_map,
+        &assembly.machine_code_map,
+        &assembly.symbol_table,
+    ))
+}
+
+/// Motorola S-record image of `source`, with segments at their paragraphs.
+pub fn assemble_srecord(source: &str) -> Result<String, Vec<String>> {
+    let assembly = assemble(source, &AssemblerOptions::default())?;
+    Ok(output::hex::to_srecord(
+        &assembly.program,
+        &assembly.address_map,
+        &assembly.machine_code_map,
+        &assembly.symbol_table,
+    ))
+}
+
+#[wasm_bindgen]
+pub fn export_intel_hex(source: &str) -> Result<String, JsValue> {
+    assemble_intel_hex(source).map_err(|errors| JsValue::from_str(&errors.join("\n")))
+}
+
+#[wasm_bindgen]
+pub fn export_srecord(source: &str) -> Result<String, JsValue> {
+    assemble_srecord(source).map_err(|errors| JsValue::from_str(&errors.join("\n")))
+}
+
+/// Glyph source for a .COM image; assembling it with `assemble_com` gives
+/// back the same bytes.
+pub fn disassemble_com(image: &[u8]) -> String {
+    disassembler::disassemble(image, semantics::encoder::COM_ORIGIN).to_source()
+}
+
+/// Glyph source for raw 8086 code loaded at `origin` (100h for a .COM file).
+#[wasm_bindgen]
+pub fn disassemble_binary(bytes: &[u8], origin: u32) -> String {
+    disassembler::disassemble(bytes, origin as u64).to_source()
+}
+
+/// An emulated 8086 running an assembled program, for the browser.
+#[wasm_bindgen]
+pub struct Emulator {
+    machine: emulator::Machine,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Assembles `source` as a .COM (`com`) or .EXE program and loads it.
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str, com: bool) -> Result<Emulator, JsValue> {
+        let errors = |errors: Vec<String>| JsValue::from_str(&errors.join("\n"));
+        let machine = if com {
+            emulator::Machine::load_com(&assemble_com(source).map_err(errors)?)
+        } else {
+            emulator::Machine::load_exe(&assemble_exe(source).map_err(errors)?)
+        };
+        machine
+            .map(|machine| Emulator { machine })
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Executes one instruction.
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        self.machine
+            .step()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Runs up to `max_steps` instructions. Returns `"halted"`,
+    /// `"step_limit"` or `"waiting_for_input"` (call `provide_input` and
+    /// run again).
+    pub fn run(&mut self, max_steps: u32) -> Result<String, JsValue> {
+        let outcome = self
+            .machine
+            .run(max_steps as u64)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(match outcome {
+            emulator::RunOutcome::Halted => "halted",
+            emulator::RunOutcome::StepLimit => "step_limit",
+            emulator::RunOutcome::WaitingForInput => "waiting_for_input",
+        }
+        .to_string())
+    }
+
+    /// Queues keyboard input for INT 21h and INT 16h; `\n` is Enter.
+    pub fn provide_input(&mut self, text: &str) {
+        self.machine.console.push_input(text);
+    }
+
+    /// Everything the program has printed so far.
+    pub fn output(&self) -> String {
+        self.machine.console.output_text()
+    }
+
+    /// The INT 21h/4Ch return code once the program has exited.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.machine.exit_code
+    }
+
+    pub fn registers(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.machine.registers.snapshot()).unwrap()
+    }
+
+    /// `len` bytes starting at `segment:offset`.
+    pub fn read_memory(&self, segment: u16, offset: u16, len: usize) -> Vec<u8> {
+        let start = emulator::memory::physical(segment, offset);
+        self.machine.memory.slice(start, len)
+    }
+
+    pub fn halted(&self) -> bool {
+        self.machine.halted
+    }
+
+    /// Instructions executed so far.
+    pub fn steps(&self) -> f64 {
+        self.machine.steps as f64
+    }
+}
+
+/// Assembles `source` and loads it under the debugger.
+fn debug_program(source: &str, com: bool) -> Result<emulator::debugger::Debugger, Vec<String>> {
+    let options = AssemblerOptions {
+        single_segment: com,
+        ..Default::default()
+    };
+    let assembly = assemble(source, &options)?;
+    let machine = if com {
+        output::com::build_com(
+            &assembly.program,
+            &assembly.address_map,
+            &assembly.machine_code_map,
+        )
+        .and_then(|image| emulator::Machine::load_com(&image).map_err(|e| e.to_string()))
+    } else {
+        output::exe::build_exe(
+            &assembly.program,
+            &assembly.address_map,
+            &assembly.machine_code_map,
+            &assembly.symbol_table,
+        )
+        .and_then(|image| emulator::Machine::load_exe(&image).map_err(|e| e.to_string()))
+    }
+    .map_err(|e| vec![e])?;
+    let map = emulator::debugger::SourceMap::new(
+        &assembly.lines,
+        &assembly.program,
+        &assembly.address_map,
+        &assembly.machine_code_map,
+        &assembly.symbol_table,
+        com,
+    );
+    Ok(emulator::debugger::Debugger::new(machine, map))
+}
+
+/// A step-through debugging session for the browser. Every stop is returned
+/// as `{ reason, line, cs, ip, watches: [{ name, value, changed }] }`.
+#[wasm_bindgen]
+pub struct DebugSession {
+    debugger: emulator::debugger::Debugger,
+}
+
+#[wasm_bindgen]
+impl DebugSession {
+    /// Assembles `source` as a .COM (`com`) or .EXE program and loads it,
+    /// stopped before its first instruction.
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str, com: bool) -> Result<DebugSession, JsValue> {
+        debug_program(source, com)
+            .map(|debugger| DebugSession { debugger })
+            .map_err(|errors| JsValue::from_str(&errors.join("\n")))
+    }
+
+    /// Breaks on `line` (or the next line with code); returns the line used.
+    pub fn add_breakpoint(&mut self, line: usize) -> Option<usize> {
+        self.debugger.add_line_breakpoint(line)
+    }
+
+    /// Breaks on the code following `label`; returns its line.
+    pub fn add_label_breakpoint(&mut self, label: &str) -> Option<usize> {
+        self.debugger.add_label_breakpoint(label)
+    }
+
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.debugger.remove_line_breakpoint(line)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear_breakpoints();
+    }
+
+    pub fn breakpoints(&self) -> Vec<usize> {
+        self.debugger.breakpoint_lines()
+    }
+
+    pub fn watch_register(&mut self, name: &str) -> bool {
+        self.debugger.watch_register(name)
+    }
+
+    pub fn watch_variable(&mut self, name: &str) -> bool {
+        self.debugger.watch_variable(name)
+    }
+
+    pub fn watch_memory(&mut self, segment: u16, offset: u16, word: bool) {
+        self.debugger.watch_memory(segment, offset, word);
+    }
+
+    pub fn remove_watch(&mut self, name: &str) -> bool {
+        self.debugger.remove_watch(name)
+    }
+
+    pub fn watches(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.debugger.watch_values()).unwrap()
+    }
+
+    pub fn step(&mut self) -> Result<JsValue, JsValue> {
+        stop_to_js(self.debugger.step())
+    }
+
+    pub fn step_over(&mut self, max_steps: u32) -> Result<JsValue, JsValue> {
+        stop_to_js(self.debugger.step_over(max_steps as u64))
+    }
+
+    /// Runs until a breakpoint, a watch change, the end of the program or
+    /// `max_steps` instructions.
+    pub fn run(&mut self, max_steps: u32) -> Result<JsValue, JsValue> {
+        stop_to_js(self.debugger.run(max_steps as u64))
+    }
+
+    /// Runs to the cursor; `undefined` when no code follows `line`.
+    pub fn run_to_line(&mut self, line: usize, max_steps: u32) -> Result<JsValue, JsValue> {
+        match self.debugger.run_to_line(line, max_steps as u64) {
+            Ok(Some(stop)) => stop_to_js(Ok(stop)),
+            Ok(None) => Ok(JsValue::UNDEFINED),
+            Err(e) => Err(JsValue::from_str(&e.to_string())),
+        }
+    }
+
+    pub fn current_line(&self) -> Option<usize> {
+        self.debugger.current_line()
+    }
+
+    pub fn registers(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.debugger.machine.registers.snapshot()).unwrap()
+    }
+
+    /// `len` bytes starting at `segment:offset`.
+    pub fn read_memory(&self, segment: u16, offset: u16, len: usize) -> Vec<u8> {
+        let start = emulator::memory::physical(segment, offset);
+        self.debugger.machine.memory.slice(start, len)
+    }
+
+    pub fn provide_input(&mut self, text: &str) {
+        self.debugger.machine.console.push_input(text);
+    }
+
+    pub fn output(&self) -> String {
+        self.debugger.machine.console.output_text()
+    }
+
+    /// Instructions executed so far.
+    pub fn steps(&self) -> f64 {
+        self.debugger.machine.steps as f64
+    }
+
+    /// Starts recording the last `limit` steps so they can be undone.
+    pub fn record_trace(&mut self, limit: usize) {
+        self.debugger.machine.start_trace(limit);
+    }
+
+    /// Goes back to the state before instruction number `step`.
+    pub fn rewind_to(&mut self, step: f64) -> Result<JsValue, JsValue> {
+        stop_to_js(self.debugger.rewind_to(step as u64))
+    }
+
+    pub fn step_back(&mut self, count: u32) -> Result<JsValue, JsValue> {
+        stop_to_js(self.debugger.step_back(count as u64))
+    }
+
+    /// Registers and memory that differ between two recorded steps, as
+    /// `{ from, to, registers: [{ name, before, after }], memory: [...] }`.
+    pub fn diff(&self, from: f64, to: f64) -> JsValue {
+        let diff = self
+            .debugger
+            .machine
+            .trace
+            .as_ref()
+            .and_then(|trace| trace.diff(from as u64, to as u64));
+        serde_wasm_bindgen::to_value(&diff).unwrap()
+    }
+
+    /// The recorded trace, one JSON object per line.
+    pub fn trace_json_lines(&self) -> Option<String> {
+        self.debugger.trace_json_lines()
+    }
+}
+
+fn stop_to_js(
+    stop: Result<emulator::debugger::Stop, emulator::EmulatorError>,
+) -> Result<JsValue, JsValue> {
+    stop.map(|stop| serde_wasm_bindgen::to_value(&stop).unwrap())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
 }
@@ -7,6 +7,16 @@ export interface WasmToken {
   line: number;
   start: number;
   end: number;
+  flags: FlagEffects | null; // Only for instruction tokens
+}
+
+// Flags an instruction reads and writes, by name ("ZF", "CF"...)
+export interface FlagEffects {
+  tested: string[];
+  modified: string[];
+  set: string[];
+  cleared: string[];
+  undefined: string[];
 }
 
 // Optional: You can refine this union type if you want strict checking in TS components
@@ -36,6 +46,21 @@ export interface LineAnalysis {
   instruction: string;
   address: string | null;
   machine_code: string | null;
+  timing: InstructionTiming | null;
+  flags: FlagEffects | null;
+  warning_message: string | null; // Linter warning; the line still assembles
+  inactive: boolean; // Left out by a false IF; not assembled nor checked
+}
+
+// 8086 clock count of one instruction (totals include EA)
+export interface InstructionTiming {
+  text: string; // Timing-table notation, e.g. "9+EA(6)=15" or "16/4"
+  clocks: number;
+  max_clocks: number;
+  ea: number;
+  taken: number | null;
+  per_repetition: number | null;
+  per_bit: number | null;
 }
 
 export interface AnalysisResult {
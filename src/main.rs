@@ -1,7 +1,13 @@
 // src/main.rs
-#![allow(unused)]
+// The binary compiles the library's modules again and leaves the browser-only
+// parts (debugger, clock tables, in-memory includes) unused.
+#![allow(dead_code)]
 
 mod ast;
+mod disassembler;
+mod emulator;
+mod isa;
+mod output;
 mod semantics;
 mod syntax;
 
@@ -9,18 +15,47 @@ use chumsky::prelude::*;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::path::Path;
 
 // Import your modules
-use semantics::encoder::{pass_one, pass_two};
-use semantics::validator::validate;
-use syntax::{lexer::lexer, parser::parser, tokens::Token};
+use ast::MemoryModel;
+use disassembler::disassemble;
+use emulator::debugger::SourceMap;
+use emulator::trace::DEFAULT_TRACE_LIMIT;
+use emulator::{Machine, RunOutcome};
+use output::{
+    com::build_com,
+    exe::build_exe,
+    hex::{to_intel_hex, to_srecord},
+    listing::{BYTES_PER_ROW, build_listing},
+    map::build_map,
+};
+use semantics::analyzer::lint;
+use semantics::encoder::{
+    AssemblerOptions, COM_ORIGIN, DEFAULT_START_OFFSET, pass_one, pass_two, to_hex,
+};
+use semantics::validator::validate_with_options;
+use syntax::include::{DiskFiles, Includes};
+use syntax::{lexer::lexer, parser::parser, preprocessor};
+
+/// Instructions `--run` executes before giving up on a program.
+const MAX_EMULATION_STEPS: u64 = 1_000_000;
 
 fn main() {
     // 1. Read File
     let args: Vec<String> = env::args().collect();
-    let filename = args
-        .get(1)
-        .expect("Please provide a filename: cargo run test.asm");
+    let filename =
+        args.iter().skip(1).find(|a| !a.starts_with("--")).expect(
+            "Please provide a filename: cargo run test.asm [--relax-jumps] [--start=HEX] [--include=DIR] [--com | --exe] [--hex] [--srec] [--lst] [--map] [--run [--input=TEXT] [--trace]] | cargo run prog.com --disasm",
+        );
+
+    // A .COM image to turn back into source instead of a program to assemble
+    if args.iter().any(|a| a == "--disasm") {
+        let image = fs::read(filename).expect("Could not read file");
+        print!("{}", disassemble(&image, COM_ORIGIN).to_source());
+        return;
+    }
+
     let source = fs::read_to_string(filename).expect("Could not read file");
 
     println!("\n=== ASSEMBLING: {} ===\n", filename);
@@ -39,8 +74,28 @@ fn main() {
 
     let tokens = tokens_result.unwrap();
 
+    // 2b. MACRO EXPANSION
+    let mut includes = Includes::new(&DiskFiles, filename);
+    includes.search_paths = args
+        .iter()
+        .filter_map(|a| a.strip_prefix("--include="))
+        .map(str::to_string)
+        .collect();
+    let expansion = preprocessor::expand(&source, tokens, &includes);
+    if !expansion.errors.is_empty() {
+        println!("❌ PREPROCESSOR ERRORS:");
+        for err in &expansion.errors {
+            println!(
+                "  Line {}: {}",
+                expansion.describe_span(err.span),
+                err.message
+            );
+        }
+        return;
+    }
+
     // 3. PARSER
-    let token_stream = chumsky::input::Stream::from_iter(tokens.into_iter())
+    let token_stream = chumsky::input::Stream::from_iter(expansion.tokens.clone())
         .map(SimpleSpan::from(len..len), |(t, s)| (t, s));
 
     let (ast_opt, parse_errs) = parser().parse(token_stream).into_output_errors();
@@ -57,12 +112,28 @@ fn main() {
 
     // 4. VALIDATOR (Populate Symbol Table)
     // Note: This returns the symbol table with 'offset: None' initially
-    let (semantic_errs, mut symbol_table) = validate(&program);
+    let write_com = args.iter().any(|a| a == "--com");
+    let write_exe = args.iter().any(|a| a == "--exe");
+    let options = AssemblerOptions {
+        relax_jumps: args.iter().any(|a| a == "--relax-jumps"),
+        // A TINY program is a .COM program unless an .EXE is asked for
+        single_segment: write_com || (expansion.model == Some(MemoryModel::Tiny) && !write_exe),
+        start_offset: args
+            .iter()
+            .find_map(|a| a.strip_prefix("--start="))
+            .map(|hex| {
+                u64::from_str_radix(hex.trim_end_matches(['h', 'H']), 16)
+                    .expect("--start takes a hex offset, e.g. --start=0250h")
+            })
+            .unwrap_or(DEFAULT_START_OFFSET),
+    };
+    let (semantic_errs, mut symbol_table) = validate_with_options(&program, &options);
 
+    let mut has_errors = !semantic_errs.is_empty();
     if !semantic_errs.is_empty() {
         println!("⚠️ SEMANTIC ERRORS:");
-        for err in semantic_errs {
-            println!("  Line {}: {}", err.line, err.message);
+        for err in &semantic_errs {
+            println!("  Line {}: {}", expansion.describe(err.line), err.message);
         }
         // We continue even with semantic errors to test addressing,
         // unless you want to stop here.
@@ -70,63 +141,226 @@ fn main() {
 
     // 5. PASS 1: ADDRESS DETERMINATION
     // This calculates addresses and updates the symbol_table offsets
-    let address_map = pass_one(&program, &mut symbol_table);
+    let (address_map, size_map) = pass_one(&program, &mut symbol_table, &options);
 
     // 6. PASS 2: MACHINE CODE ENCODING
-    let machine_code_map = pass_two(&program, &address_map);
+    let (machine_code_map, encoding_errs) =
+        pass_two(&program, &address_map, &size_map, &symbol_table, &options);
+
+    has_errors |= !encoding_errs.is_empty();
+    if !encoding_errs.is_empty() {
+        println!("⚠️ ENCODING ERRORS:");
+        for err in &encoding_errs {
+            println!("  Line {}: {}", expansion.describe(err.line), err.message);
+        }
+    }
+
+    let warnings = lint(&program);
+    if !warnings.is_empty() {
+        println!("⚠️ WARNINGS:");
+        for warning in &warnings {
+            println!(
+                "  Line {}: {}",
+                expansion.describe(warning.line),
+                warning.message
+            );
+        }
+    }
+
+    // 7. OUTPUT FILES (.COM / .EXE / .HEX / .S19)
+    let mut outputs: Vec<(&str, Result<Vec<u8>, String>)> = Vec::new();
+    if write_com {
+        outputs.push(("com", build_com(&program, &address_map, &machine_code_map)));
+    }
+    if write_exe {
+        let exe = build_exe(&program, &address_map, &machine_code_map, &symbol_table);
+        outputs.push(("exe", exe));
+    }
+    if args.iter().any(|a| a == "--hex") {
+        let hex = to_intel_hex(&program, &address_map, &machine_code_map, &symbol_table);
+        outputs.push(("hex", Ok(hex.into_bytes())));
+    }
+    if args.iter().any(|a| a == "--srec") {
+        let srec = to_srecord(&program, &address_map, &machine_code_map, &symbol_table);
+        outputs.push(("s19", Ok(srec.into_bytes())));
+    }
+    for (extension, result) in outputs {
+        match result {
+            _ if has_errors => println!(
+                "⚠️ .{} OUTPUT SKIPPED: fix the errors above first\n",
+                extension.to_uppercase()
+            ),
+            Ok(bytes) => {
+                let path = Path::new(filename).with_extension(extension);
+                fs::write(&path, &bytes).expect("Could not write output file");
+                println!("Wrote {} ({} bytes)\n", path.display(), bytes.len());
+            }
+            Err(msg) => println!("⚠️ .{} OUTPUT ERROR: {}\n", extension.to_uppercase(), msg),
+        }
+    }
+
+    // 8. LISTING FILE (.LST), written even with errors so they can be read in place
+    if args.iter().any(|a| a == "--lst") {
+        let errors: Vec<_> = semantic_errs
+            .iter()
+            .chain(&encoding_errs)
+            .cloned()
+            .collect();
+        let listing = build_listing(
+            filename,
+            &source,
+            &program,
+            &expansion,
+            &address_map,
+            &machine_code_map,
+            &symbol_table,
+            &errors,
+        );
+        let path = Path::new(filename).with_extension("lst");
+        fs::write(&path, &listing).expect("Could not write listing file");
+        println!("Wrote {}\n", path.display());
+    }
+
+    // 9. MAP FILE (.MAP): segment map and cross-reference
+    if args.iter().any(|a| a == "--map") {
+        let map = build_map(
+            &program,
+            &expansion,
+            &address_map,
+            &machine_code_map,
+            &symbol_table,
+        );
+        let path = Path::new(filename).with_extension("map");
+        fs::write(&path, &map).expect("Could not write map file");
+        println!("Wrote {}\n", path.display());
+    }
+
+    // 10. EMULATION: run the .COM (with --com or .MODEL TINY) or .EXE image
+    if args.iter().any(|a| a == "--run") && !has_errors {
+        let machine = if options.single_segment {
+            build_com(&program, &address_map, &machine_code_map)
+                .map_err(|e| e.to_string())
+                .and_then(|image| Machine::load_com(&image).map_err(|e| e.to_string()))
+        } else {
+            build_exe(&program, &address_map, &machine_code_map, &symbol_table)
+                .and_then(|image| Machine::load_exe(&image).map_err(|e| e.to_string()))
+        };
+        match machine {
+            Ok(mut machine) => {
+                if let Some(input) = args.iter().find_map(|a| a.strip_prefix("--input=")) {
+                    machine.console.push_input(&input.replace("\\n", "\n"));
+                }
+                let trace = args.iter().any(|a| a == "--trace");
+                if trace {
+                    machine.start_trace(DEFAULT_TRACE_LIMIT);
+                }
+                println!("=== EMULATION ===");
+                let outcome = machine.run(MAX_EMULATION_STEPS);
+                let output = machine.console.output_text();
+                if !output.is_empty() {
+                    println!("{}", output.replace("\r\n", "\n").replace('\r', "\n"));
+                    println!("{}", "-".repeat(40));
+                }
+                match outcome {
+                    Ok(RunOutcome::Halted) => println!("Halted after {} steps", machine.steps),
+                    Ok(RunOutcome::StepLimit) => {
+                        println!("Stopped after {} steps (step limit)", machine.steps)
+                    }
+                    Ok(RunOutcome::WaitingForInput) => println!(
+                        "Waiting for keyboard input after {} steps (use --input=TEXT)",
+                        machine.steps
+                    ),
+                    Err(err) => println!("⚠️ {} (after {} steps)", err, machine.steps),
+                }
+                if let Some(code) = machine.exit_code {
+                    println!("Exit code {}", code);
+                }
+                println!("{}\n", machine.registers.snapshot());
+
+                if let Some(trace) = machine.trace.as_ref().filter(|_| trace) {
+                    let lines: Vec<usize> = (1..=program.len())
+                        .map(|line| expansion.main_line(line))
+                        .collect();
+                    let map = SourceMap::new(
+                        &lines,
+                        &program,
+                        &address_map,
+                        &machine_code_map,
+                        &symbol_table,
+                        options.single_segment,
+                    );
+                    let lines = trace.to_json_lines(|cs, ip| map.line_at(cs, ip));
+                    let path = Path::new(filename).with_extension("trace.jsonl");
+                    fs::write(&path, lines).expect("Could not write trace file");
+                    println!("Wrote {} ({} steps)\n", path.display(), trace.len());
+                }
+            }
+            Err(msg) => println!("⚠️ EMULATION ERROR: {}\n", msg),
+        }
+    }
 
     // ==========================================
     // OUTPUT: LISTING FILE VISUALIZATION
     // ==========================================
     println!("=== LISTING OUTPUT ===");
     println!(
-        "{:<6} | {:<8} | {:<16} | {}",
-        "Line", "Address", "Machine Code", "Source"
+        "{:<6} | {:<8} | {:<17} | Source",
+        "Line", "Address", "Machine Code"
     );
     println!("{}", "-".repeat(80));
 
+    // Statements of each source line: its own, plus those a macro call,
+    // INCLUDE or .STARTUP expands to, which show their bytes on it
+    let mut statements: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..program.len() {
+        statements
+            .entry(expansion.main_line(index + 1))
+            .or_default()
+            .push(index);
+    }
+
     // Split source into lines to print side-by-side
     for (line_idx, source_line) in source.lines().enumerate() {
-        // We need to map source lines to AST nodes.
-        // Since 'address_map' is indexed by AST Node Index, not Line Number,
-        // we have to be careful.
-        //
-        // Ideally, we loop through the AST (program) and find which line it belongs to.
-        // But for a simple visualization, let's try to lookup if a statement exists on this line.
-
-        // Find which AST node starts on this line
-        let node_idx = program.iter().position(|spanned| {
-            let start_byte = spanned.span.0;
-            get_line_number(&source, start_byte) == line_idx + 1
-        });
-
-        let addr_str = if let Some(idx) = node_idx {
-            if let Some(addr) = address_map.get(&idx) {
-                format!("{:04X}", addr)
-            } else {
-                String::new()
-            }
-        } else {
-            String::new()
-        };
+        let nodes = statements
+            .get(&(line_idx + 1))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
 
-        let code_str = if let Some(idx) = node_idx {
-            if let Some(code) = machine_code_map.get(&idx) {
-                code.clone()
-            } else {
-                String::new()
-            }
-        } else {
-            String::new()
-        };
+        // Where the code starts; a line without code shows its first statement's
+        let address = nodes
+            .iter()
+            .find(|idx| machine_code_map.contains_key(idx))
+            .or(nodes.first())
+            .and_then(|idx| address_map.get(idx));
+        let addr_str = address
+            .map(|addr| format!("{:04X}", addr))
+            .unwrap_or_default();
+
+        let code: Vec<u8> = nodes
+            .iter()
+            .filter_map(|idx| machine_code_map.get(idx))
+            .flatten()
+            .copied()
+            .collect();
 
+        // Long code (a DUP, a .STACK) continues on rows of its own, as in the .LST
+        let mut rows = code.chunks(BYTES_PER_ROW);
         println!(
-            "{:<6} | \x1b[34m{:<8}\x1b[0m | \x1b[32m{:<16}\x1b[0m | {}",
+            "{:<6} | \x1b[34m{:<8}\x1b[0m | \x1b[32m{:<17}\x1b[0m | {}",
             line_idx + 1,
             addr_str,
-            code_str,
+            rows.next().map(to_hex).unwrap_or_default(),
             source_line
         );
+        for (row, chunk) in rows.enumerate() {
+            let offset = address.map_or(0, |a| a + ((row + 1) * BYTES_PER_ROW) as u64);
+            println!(
+                "{:<6} | \x1b[34m{:<8}\x1b[0m | \x1b[32m{}\x1b[0m",
+                "",
+                format!("{:04X}", offset),
+                to_hex(chunk)
+            );
+        }
     }
 
     // ==========================================
@@ -156,15 +390,3 @@ fn main() {
         );
     }
 }
-
-// Helper to convert byte offset to line number
-fn get_line_number(source: &str, offset: usize) -> usize {
-    let slice = &source[..offset.min(source.len())];
-    let count = slice.lines().count();
-    if slice.ends_with('\n') {
-        count + 1
-    } else {
-        count
-    }
-    .max(1)
-}
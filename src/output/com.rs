@@ -0,0 +1,60 @@
+// src/output/com.rs
+use crate::ast::Program;
+use crate::semantics::encoder::COM_ORIGIN;
+use std::collections::HashMap;
+
+/// Largest .COM image: one 64K segment minus the PSP and a word of stack.
+const MAX_COM_SIZE: u64 = 0xFF00;
+
+/// Builds a flat DOS .COM image.
+///
+/// The program must have been laid out with `AssemblerOptions::single_segment`
+/// (or start with `ORG 100h`) so that every statement address is an offset in
+/// the one segment DOS loads at 100h. Byte `n` of the file is loaded at
+/// offset `100h + n`; gaps left by `ORG` are zero-filled.
+pub fn build_com(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let mut image: Vec<u8> = Vec::new();
+    let mut written: Vec<bool> = Vec::new();
+
+    for index in 0..program.len() {
+        let Some(bytes) = machine_code_map.get(&index) else {
+            continue;
+        };
+        let address = address_map.get(&index).copied().unwrap_or(0);
+        if address < COM_ORIGIN {
+            return Err(format!(
+                "Line {}: código en {:04X}h por debajo de ORG 100h",
+                index + 1,
+                address
+            ));
+        }
+
+        let start = (address - COM_ORIGIN) as usize;
+        let end = start + bytes.len();
+        if end as u64 > MAX_COM_SIZE {
+            return Err(format!(
+                "Line {}: el programa no cabe en un archivo .COM (64K)",
+                index + 1
+            ));
+        }
+        if end > image.len() {
+            image.resize(end, 0);
+            written.resize(end, false);
+        }
+        if written[start..end].iter().any(|w| *w) {
+            return Err(format!(
+                "Line {}: se superpone al código en {:04X}h",
+                index + 1,
+                address
+            ));
+        }
+        image[start..end].copy_from_slice(bytes);
+        written[start..end].fill(true);
+    }
+
+    Ok(image)
+}
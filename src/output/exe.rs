@@ -0,0 +1,154 @@
+// src/output/exe.rs
+use crate::ast::{LineNode, Program, Statement};
+use crate::semantics::encoder::segment_fixups;
+use crate::semantics::validator::{SymbolInfo, SymbolType, segment_kind};
+use std::collections::HashMap;
+
+/// Fixed part of the MZ header; the relocation table follows it.
+const HEADER_SIZE: usize = 0x1C;
+const PAGE_SIZE: usize = 512;
+/// Paragraphs of stack DOS must provide when the program declares none.
+const DEFAULT_STACK_PARAGRAPHS: u16 = 0x1000;
+
+/// Builds a DOS MZ executable.
+///
+/// The program must have been laid out without `single_segment`, so that
+/// every statement address is an offset inside its own segment and every
+/// segment symbol holds the paragraph it starts at in the load image.
+///
+/// - Initial CS:IP comes from the `END start` label (start of the code
+///   segment when `END` names none).
+/// - Initial SS:SP points at the top of the stack segment.
+/// - Every segment base used as an immediate or a `DW` value gets a
+///   relocation entry so DOS can add the load segment to it.
+pub fn build_exe(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Result<Vec<u8>, String> {
+    let frame_of = |kind: &str| {
+        symbols
+            .get(kind)
+            .filter(|s| matches!(s.type_, SymbolType::Segment))
+            .and_then(|s| s.offset)
+    };
+
+    let mut image: Vec<u8> = Vec::new();
+    let mut written: Vec<bool> = Vec::new();
+    let mut relocations: Vec<(u16, u16)> = Vec::new();
+    let mut kind: Option<&str> = None;
+    let mut frame: Option<u64> = None;
+    let mut stack_top: u64 = 0;
+    let mut entry_label: Option<&String> = None;
+
+    for (index, spanned) in program.iter().enumerate() {
+        let LineNode::Statement(stmt) = &spanned.node else {
+            continue;
+        };
+        match stmt {
+            Statement::Segment { name } => {
+                kind = segment_kind(name);
+                frame = kind.and_then(frame_of);
+            }
+            Statement::SegmentEnd => {
+                kind = None;
+                frame = None;
+            }
+            Statement::End { label } => entry_label = label.as_ref(),
+            _ => {}
+        }
+
+        let Some(bytes) = machine_code_map.get(&index) else {
+            continue;
+        };
+        let Some(frame) = frame else {
+            return Err(format!("Line {}: código fuera de un segmento", index + 1));
+        };
+        let address = address_map.get(&index).copied().unwrap_or(0);
+
+        let start = (frame * 16 + address) as usize;
+        let end = start + bytes.len();
+        if end > image.len() {
+            image.resize(end, 0);
+            written.resize(end, false);
+        }
+        if written[start..end].iter().any(|w| *w) {
+            return Err(format!(
+                "Line {}: se superpone al código en {:04X}:{:04X}",
+                index + 1,
+                frame,
+                address
+            ));
+        }
+        image[start..end].copy_from_slice(bytes);
+        written[start..end].fill(true);
+        if kind == Some("STACK") {
+            stack_top = stack_top.max(address + bytes.len() as u64);
+        }
+
+        for fixup in segment_fixups(stmt, bytes.len(), symbols) {
+            relocations.push((frame as u16, (address as usize + fixup) as u16));
+        }
+    }
+
+    // CS:IP
+    let (cs, ip) = match entry_label {
+        Some(name) => {
+            let sym = symbols
+                .get(name)
+                .filter(|s| matches!(s.type_, SymbolType::Label | SymbolType::Procedure(_)))
+                .ok_or_else(|| {
+                    format!(
+                        "El punto de entrada '{}' no es una etiqueta de código",
+                        name
+                    )
+                })?;
+            (frame_of(&sym.segment).unwrap_or(0), sym.offset.unwrap_or(0))
+        }
+        None => (
+            frame_of("CODE").ok_or("El programa no tiene segmento de código")?,
+            0,
+        ),
+    };
+
+    // SS:SP, and the extra memory DOS must reserve past the image
+    let (ss, sp, min_alloc) = match frame_of("STACK") {
+        Some(ss) => (ss, stack_top, 0),
+        None => (image.len().div_ceil(16) as u64, 0, DEFAULT_STACK_PARAGRAPHS),
+    };
+    if sp > 0x10000 {
+        return Err("El segmento de pila supera los 64K".to_string());
+    }
+    // A full 64K stack starts with SP wrapped to 0
+    let sp = sp as u16;
+
+    let header_paragraphs = (HEADER_SIZE + relocations.len() * 4).div_ceil(16);
+    let header_size = header_paragraphs * 16;
+    let file_size = header_size + image.len();
+
+    let mut exe = Vec::with_capacity(file_size);
+    let mut word = |value: u16| exe.extend_from_slice(&value.to_le_bytes());
+    word(u16::from_le_bytes(*b"MZ"));
+    word((file_size % PAGE_SIZE) as u16); // bytes used in the last page
+    word(file_size.div_ceil(PAGE_SIZE) as u16); // pages in the file
+    word(relocations.len() as u16);
+    word(header_paragraphs as u16);
+    word(min_alloc);
+    word(0xFFFF); // max extra paragraphs
+    word(ss as u16);
+    word(sp);
+    word(0); // checksum
+    word(ip as u16);
+    word(cs as u16);
+    word(HEADER_SIZE as u16); // relocation table offset
+    word(0); // overlay number
+    for (segment, offset) in &relocations {
+        word(*offset);
+        word(*segment);
+    }
+    exe.resize(header_size, 0);
+    exe.extend_from_slice(&image);
+
+    Ok(exe)
+}
@@ -0,0 +1,176 @@
+// src/output/hex.rs
+use crate::ast::{LineNode, Program, Statement};
+use crate::semantics::validator::{SymbolInfo, SymbolType, segment_kind};
+use std::collections::HashMap;
+
+/// Data bytes per record, the usual line length for both formats.
+const RECORD_SIZE: usize = 16;
+
+/// A contiguous run of bytes at `segment:offset`.
+struct Chunk {
+    segment: u16,
+    offset: u16,
+    bytes: Vec<u8>,
+}
+
+impl Chunk {
+    fn linear(&self) -> u32 {
+        ((self.segment as u32) << 4) + self.offset as u32
+    }
+}
+
+/// Intel HEX image of the program.
+///
+/// Addresses are `segment:offset` pairs: a type 02 (extended segment address)
+/// record is emitted whenever the segment changes, and the `END` label, if
+/// any, becomes a type 03 (start segment address) record.
+pub fn to_intel_hex(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> String {
+    let mut out = String::new();
+    let mut current_segment = 0;
+
+    for chunk in chunks(program, address_map, machine_code_map, symbols) {
+        if chunk.segment != current_segment {
+            current_segment = chunk.segment;
+            out += &intel_record(0x02, 0, &current_segment.to_be_bytes());
+        }
+        for (i, data) in chunk.bytes.chunks(RECORD_SIZE).enumerate() {
+            let offset = chunk.offset.wrapping_add((i * RECORD_SIZE) as u16);
+            out += &intel_record(0x00, offset, data);
+        }
+    }
+
+    if let Some((cs, ip)) = entry_point(program, symbols) {
+        let mut start = cs.to_be_bytes().to_vec();
+        start.extend(ip.to_be_bytes());
+        out += &intel_record(0x03, 0, &start);
+    }
+    out += &intel_record(0x01, 0, &[]);
+    out
+}
+
+/// Motorola S-record image of the program.
+///
+/// Data records carry linear (20-bit) addresses: S1/S9 when everything fits
+/// in 16 bits, S2/S8 otherwise. The terminator holds the `END` entry point.
+pub fn to_srecord(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> String {
+    let chunks = chunks(program, address_map, machine_code_map, symbols);
+    let wide = chunks
+        .iter()
+        .any(|c| c.linear() + c.bytes.len() as u32 > 0x10000);
+    let (data_type, end_type, address_len) = if wide { (2, 8, 3) } else { (1, 9, 2) };
+
+    let mut out = s_record(0, 0, 2, b"glyph");
+    let mut count: u32 = 0;
+    for chunk in &chunks {
+        for (i, data) in chunk.bytes.chunks(RECORD_SIZE).enumerate() {
+            let address = chunk.linear() + (i * RECORD_SIZE) as u32;
+            out += &s_record(data_type, address, address_len, data);
+            count += 1;
+        }
+    }
+    if count <= 0xFFFF {
+        out += &s_record(5, count, 2, &[]);
+    }
+
+    let entry = entry_point(program, symbols).map_or(0, |(cs, ip)| ((cs as u32) << 4) + ip as u32);
+    out += &s_record(end_type, entry, address_len, &[]);
+    out
+}
+
+/// `:LLAAAATT<data>CC` with a two's complement checksum.
+fn intel_record(record_type: u8, address: u16, data: &[u8]) -> String {
+    let mut fields = vec![data.len() as u8];
+    fields.extend(address.to_be_bytes());
+    fields.push(record_type);
+    fields.extend(data);
+    let sum = fields.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+    fields.push(sum.wrapping_neg());
+    format!(":{}\n", hex_digits(&fields))
+}
+
+/// `S<type><count><address><data><checksum>` with a one's complement checksum.
+fn s_record(record_type: u8, address: u32, address_len: usize, data: &[u8]) -> String {
+    let mut fields = vec![(address_len + data.len() + 1) as u8];
+    fields.extend(&address.to_be_bytes()[4 - address_len..]);
+    fields.extend(data);
+    let sum = fields.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+    fields.push(!sum);
+    format!("S{}{}\n", record_type, hex_digits(&fields))
+}
+
+fn hex_digits(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn frame_of(kind: &str, symbols: &HashMap<String, SymbolInfo>) -> u16 {
+    symbols
+        .get(kind)
+        .filter(|s| matches!(s.type_, SymbolType::Segment))
+        .and_then(|s| s.offset)
+        .unwrap_or(0) as u16
+}
+
+/// Statement bytes placed in their segments, merged into contiguous runs.
+///
+/// Segment frames come from the symbol table, so a flat (.COM) layout simply
+/// puts everything in segment 0.
+fn chunks(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut segment = 0;
+
+    for (index, spanned) in program.iter().enumerate() {
+        match &spanned.node {
+            LineNode::Statement(Statement::Segment { name }) => {
+                segment = segment_kind(name).map_or(0, |k| frame_of(k, symbols));
+            }
+            LineNode::Statement(Statement::SegmentEnd) => segment = 0,
+            _ => {}
+        }
+        let Some(bytes) = machine_code_map.get(&index) else {
+            continue;
+        };
+        let offset = address_map.get(&index).copied().unwrap_or(0) as u16;
+
+        match chunks.last_mut() {
+            Some(last)
+                if last.segment == segment
+                    && last.offset as usize + last.bytes.len() == offset as usize =>
+            {
+                last.bytes.extend(bytes);
+            }
+            _ => chunks.push(Chunk {
+                segment,
+                offset,
+                bytes: bytes.clone(),
+            }),
+        }
+    }
+    chunks
+}
+
+/// CS:IP of the `END` label, if the program names one.
+fn entry_point(program: &Program, symbols: &HashMap<String, SymbolInfo>) -> Option<(u16, u16)> {
+    let label = program.iter().find_map(|spanned| match &spanned.node {
+        LineNode::Statement(Statement::End { label }) => label.as_ref(),
+        _ => None,
+    })?;
+    let sym = symbols
+        .get(label)
+        .filter(|s| matches!(s.type_, SymbolType::Label | SymbolType::Procedure(_)))?;
+    Some((frame_of(&sym.segment, symbols), sym.offset? as u16))
+}
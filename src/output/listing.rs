@@ -0,0 +1,259 @@
+// src/output/listing.rs
+use crate::ast::{Distance, LineNode, Program, Statement};
+use crate::disassembler::statement_text;
+use crate::output::segment_extents;
+use crate::semantics::encoder::to_hex;
+use crate::semantics::validator::{
+    CompilerError, ConstantValue, DataType, SymbolInfo, SymbolType, segment_kind,
+};
+use crate::syntax::include::MAIN_FILE;
+use crate::syntax::preprocessor::Expansion;
+use crate::syntax::tokens::pseudoinstruction;
+use std::collections::HashMap;
+
+/// Lines per page, header included (66-line forms minus margins).
+const PAGE_LENGTH: usize = 60;
+/// Machine code bytes shown per listing row; the rest go on continuation rows.
+pub const BYTES_PER_ROW: usize = 6;
+const BYTES_WIDTH: usize = BYTES_PER_ROW * 3;
+
+/// Builds a paginated MASM-style listing.
+///
+/// Every source line is listed with its line number, `segment:offset` and
+/// machine code, followed by its errors. Lines expanded from a macro or
+/// repeat block come right after the invocation, marked with a `1`, and
+/// the lines of an included file after the `INCLUDE`, marked with a `C`.
+/// The segment and symbol tables come after the source. `errors` use the
+/// same line numbering as the validator (statement index + 1); `expansion`
+/// maps those numbers back to the source.
+#[allow(clippy::too_many_arguments)]
+pub fn build_listing(
+    title: &str,
+    source: &str,
+    program: &Program,
+    expansion: &Expansion,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+    errors: &[CompilerError],
+) -> String {
+    let frame_of = |kind: &str| {
+        symbols
+            .get(kind)
+            .filter(|s| matches!(s.type_, SymbolType::Segment))
+            .and_then(|s| s.offset)
+            .unwrap_or(0)
+    };
+
+    // Source line (1-based) -> its own statement, and the expanded and
+    // included ones listed after it: those that follow it in the program
+    let mut own = HashMap::new();
+    let mut expanded: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut previous = 0;
+    for index in 0..program.len() {
+        let origin = expansion.origin(index + 1);
+        if origin.file == MAIN_FILE && origin.macro_line.is_none() {
+            own.entry(origin.line).or_insert(index);
+            previous = origin.line;
+        } else {
+            expanded.entry(previous).or_default().push(index);
+        }
+    }
+
+    // One row per statement, continuation rows for long code, then errors.
+    // Expanded rows (`number` 0) show no line number.
+    let list_statement =
+        |pager: &mut Pager, frame: &mut u64, number: usize, text: &str, index: usize| {
+            let node = &program[index].node;
+            if let LineNode::Statement(Statement::Segment { name }) = node
+                && let Some(kind) = segment_kind(name)
+            {
+                *frame = frame_of(kind);
+            }
+
+            let address = match node {
+                LineNode::Statement(stmt) if shows_address(stmt) => address_map.get(&index),
+                _ => None,
+            };
+            let bytes = machine_code_map
+                .get(&index)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            let mut rows = bytes.chunks(BYTES_PER_ROW);
+            pager.push(format!(
+                "{:>5}  {:<9}  {:<BYTES_WIDTH$}  {}",
+                if number == 0 {
+                    String::new()
+                } else {
+                    number.to_string()
+                },
+                address.map_or(String::new(), |a| format!("{:04X}:{:04X}", frame, a)),
+                rows.next().map(to_hex).unwrap_or_default(),
+                text
+            ));
+            for (row, chunk) in rows.enumerate() {
+                let offset = address.map_or(0, |a| a + ((row + 1) * BYTES_PER_ROW) as u64);
+                pager.push(format!(
+                    "{:>5}  {:04X}:{:04X}  {}",
+                    "",
+                    frame,
+                    offset,
+                    to_hex(chunk)
+                ));
+            }
+
+            for err in errors.iter().filter(|e| e.line == index + 1) {
+                pager.push(format!("***** Error: {}", err.message));
+            }
+        };
+
+    let mut pager = Pager::new(title);
+    let mut frame = 0;
+
+    for (line_idx, text) in source.lines().enumerate() {
+        let line = line_idx + 1;
+        if let Some(&index) = own.get(&line) {
+            list_statement(&mut pager, &mut frame, line, text, index);
+        } else {
+            pager.push(format!(
+                "{:>5}  {:<9}  {:<BYTES_WIDTH$}  {}",
+                line, "", "", text
+            ));
+        }
+        for &index in expanded.get(&line).into_iter().flatten() {
+            let origin = expansion.origin(index + 1);
+            let text = match (&program[index].node, origin.macro_line) {
+                (LineNode::Statement(stmt), Some(_)) => format!("1   {}", statement_text(stmt)),
+                (_, Some(_)) => continue,
+                (_, None) => {
+                    let file = expansion.files.get(origin.file);
+                    let text = file.and_then(|f| f.text.lines().nth(origin.line - 1));
+                    format!("C   {}", text.unwrap_or_default())
+                }
+            };
+            list_statement(&mut pager, &mut frame, 0, &text, index);
+        }
+    }
+
+    // Segment table
+    pager.new_page();
+    pager.push("Segments:".to_string());
+    pager.push(String::new());
+    pager.push(format!("{:<20} {:<6} {:<6}", "Name", "Frame", "Size"));
+    for segment in segment_extents(program, address_map, machine_code_map, symbols) {
+        pager.push(format!(
+            "{:<20} {:04X}   {:04X}",
+            segment.kind, segment.frame, segment.size
+        ));
+    }
+
+    // Symbol table
+    pager.push(String::new());
+    pager.push("Symbols:".to_string());
+    pager.push(String::new());
+    pager.push(format!(
+        "{:<20} {:<8} {:<6} {}",
+        "Name", "Type", "Value", "Attr"
+    ));
+    let mut names: Vec<&String> = symbols
+        .iter()
+        .filter(|(_, s)| !matches!(s.type_, SymbolType::Segment))
+        .map(|(name, _)| name)
+        .collect();
+    names.sort_by_key(|name| name.to_uppercase());
+    for name in names {
+        let sym = &symbols[name];
+        let kind = match (&sym.type_, &sym.data_type) {
+            (SymbolType::Variable, DataType::Byte) => "BYTE",
+            (SymbolType::Variable, _) => "WORD",
+            (SymbolType::Label, _) => "L NEAR",
+            (SymbolType::Procedure(Distance::Near), _) => "P NEAR",
+            (SymbolType::Procedure(Distance::Far), _) => "P FAR",
+            (SymbolType::Constant, _) if matches!(sym.value, Some(ConstantValue::Text(_))) => {
+                "TEXT"
+            }
+            _ => "NUMBER",
+        };
+        pager.push(format!(
+            "{:<20} {:<8} {:<6} {}",
+            name,
+            kind,
+            sym.offset
+                .map_or("????".to_string(), |o| format!("{:04X}", o)),
+            sym.segment
+        ));
+    }
+
+    pager.push(String::new());
+    pager.push(format!("{:>5} Errors", errors.len()));
+    pager.finish()
+}
+
+/// Statements whose location counter is worth printing.
+fn shows_address(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Directive { name, .. } => {
+            name != ".MODEL" && !pseudoinstruction::is_conditional(name)
+        }
+        _ => matches!(
+            stmt,
+            Statement::Instruction { .. }
+                | Statement::Label(_)
+                | Statement::Procedure { .. }
+                | Statement::Variable { .. }
+                | Statement::Data { .. }
+        ),
+    }
+}
+
+/// Splits the listing into pages, each starting with a form feed and header.
+struct Pager {
+    title: String,
+    out: String,
+    page: usize,
+    line: usize,
+}
+
+impl Pager {
+    fn new(title: &str) -> Self {
+        let mut pager = Pager {
+            title: title.to_string(),
+            out: String::new(),
+            page: 0,
+            line: 0,
+        };
+        pager.header();
+        pager
+    }
+
+    fn header(&mut self) {
+        self.page += 1;
+        if self.page > 1 {
+            self.out.push('\x0c');
+        }
+        self.out += &format!(
+            "Glyph 8086 Assembler{:>50}\n{}\n\n",
+            format!("Page {}", self.page),
+            self.title
+        );
+        self.line = 3;
+    }
+
+    fn push(&mut self, line: String) {
+        if self.line >= PAGE_LENGTH {
+            self.header();
+        }
+        self.out += line.trim_end();
+        self.out.push('\n');
+        self.line += 1;
+    }
+
+    fn new_page(&mut self) {
+        self.header();
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
@@ -0,0 +1,99 @@
+// src/output/map.rs
+use crate::ast::{Distance, Program};
+use crate::output::segment_extents;
+use crate::semantics::validator::{Access, ConstantValue, DataType, SymbolInfo, SymbolType};
+use crate::syntax::preprocessor::Expansion;
+use std::collections::HashMap;
+
+/// Builds the map file: a LINK-style segment map followed by a CREF-style
+/// cross-reference of every symbol.
+///
+/// In the reference lists `#` marks the defining line and `+` a line that
+/// writes the symbol. Variables and labels nobody references are flagged
+/// so dead code and data stand out. Line numbers are source lines, as
+/// `expansion` maps them back; a line of an included file is `file:line`.
+pub fn build_map(
+    program: &Program,
+    expansion: &Expansion,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> String {
+    let extents = segment_extents(program, address_map, machine_code_map, symbols);
+    let mut out = String::new();
+
+    // Segment map (linear addresses)
+    out += " Start  Stop   Length Name                 Class\n";
+    for segment in &extents {
+        let start = segment.frame * 16;
+        let stop = start + segment.size.saturating_sub(1);
+        out += &format!(
+            " {:05X}H {:05X}H {:05X}H {:<20} {}\n",
+            start, stop, segment.size, segment.kind, segment.kind
+        );
+    }
+
+    // Cross-reference
+    out += "\nSymbol Cross-Reference        (# definition, + modification)\n\n";
+    out += &format!(
+        "{:<20} {:<8} {:>5}  {:<8} {:<6}  References\n",
+        "Name", "Type", "Size", "Segment", "Offset"
+    );
+
+    let mut names: Vec<&String> = symbols.keys().collect();
+    names.sort_by_key(|name| name.to_uppercase());
+    for name in names {
+        let sym = &symbols[name];
+        let (kind, size) = match (&sym.type_, &sym.data_type) {
+            (SymbolType::Variable, data_type) => (
+                if *data_type == DataType::Byte {
+                    "BYTE"
+                } else {
+                    "WORD"
+                },
+                machine_code_map
+                    .get(&(sym.line_defined - 1))
+                    .map_or(0, |bytes| bytes.len() as u64),
+            ),
+            (SymbolType::Label, _) => ("L NEAR", 0),
+            (SymbolType::Procedure(Distance::Near), _) => ("P NEAR", 0),
+            (SymbolType::Procedure(Distance::Far), _) => ("P FAR", 0),
+            (SymbolType::Constant, _) if matches!(sym.value, Some(ConstantValue::Text(_))) => {
+                ("TEXT", 0)
+            }
+            (SymbolType::Constant, _) => ("NUMBER", 0),
+            (SymbolType::Segment, _) => (
+                "SEGMENT",
+                extents
+                    .iter()
+                    .filter(|s| s.kind == name.as_str())
+                    .map(|s| s.size)
+                    .sum(),
+            ),
+        };
+
+        let mut refs = vec![format!("{}#", expansion.place(sym.line_defined))];
+        refs.extend(sym.references.iter().map(|r| match r.access {
+            Access::Read => expansion.place(r.line),
+            Access::Write => format!("{}+", expansion.place(r.line)),
+        }));
+        let unused = sym.references.is_empty()
+            && matches!(
+                sym.type_,
+                SymbolType::Variable | SymbolType::Label | SymbolType::Procedure(_)
+            );
+
+        out += &format!(
+            "{:<20} {:<8} {:>5}  {:<8} {:<6}  {}{}\n",
+            name,
+            kind,
+            size,
+            sym.segment,
+            sym.offset
+                .map_or("????".to_string(), |o| format!("{:04X}", o)),
+            refs.join(" "),
+            if unused { "  (unused)" } else { "" }
+        );
+    }
+    out
+}
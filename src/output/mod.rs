@@ -0,0 +1,72 @@
+// src/output/mod.rs
+//! Writers that turn the per-statement machine code from `pass_two` into
+//! files other tools can load.
+
+pub mod com;
+pub mod exe;
+pub mod hex;
+// Only the command line writes listings and maps
+#[allow(dead_code)]
+pub mod listing;
+#[allow(dead_code)]
+pub mod map;
+
+use crate::ast::{LineNode, Program, Statement};
+use crate::semantics::validator::{SymbolInfo, SymbolType, segment_kind};
+use std::collections::HashMap;
+
+/// A segment as laid out by `pass_one`.
+pub struct SegmentExtent {
+    pub kind: &'static str,
+    /// Paragraph the segment starts at in the image.
+    pub frame: u64,
+    /// Bytes from the segment start to the end of its last statement.
+    pub size: u64,
+}
+
+/// Segments in order of first appearance with the space their statements
+/// occupy. A segment opened again adds to the same extent.
+pub fn segment_extents(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    machine_code_map: &HashMap<usize, Vec<u8>>,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Vec<SegmentExtent> {
+    let mut extents: Vec<SegmentExtent> = Vec::new();
+    // Index in `extents` of the open segment
+    let mut open = None;
+
+    for (index, spanned) in program.iter().enumerate() {
+        match &spanned.node {
+            LineNode::Statement(Statement::Segment { name }) => {
+                open = segment_kind(name).map(|kind| {
+                    extents
+                        .iter()
+                        .position(|e| e.kind == kind)
+                        .unwrap_or_else(|| {
+                            let frame = symbols
+                                .get(kind)
+                                .filter(|s| matches!(s.type_, SymbolType::Segment))
+                                .and_then(|s| s.offset)
+                                .unwrap_or(0);
+                            extents.push(SegmentExtent {
+                                kind,
+                                frame,
+                                size: 0,
+                            });
+                            extents.len() - 1
+                        })
+                });
+            }
+            LineNode::Statement(Statement::SegmentEnd) => open = None,
+            _ => {}
+        }
+        if let (Some(open), Some(bytes), Some(address)) =
+            (open, machine_code_map.get(&index), address_map.get(&index))
+        {
+            let extent = &mut extents[open];
+            extent.size = extent.size.max(address + bytes.len() as u64);
+        }
+    }
+    extents
+}
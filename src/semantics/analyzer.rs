@@ -1 +1,123 @@
+// src/semantics/analyzer.rs
+//! Warnings about programs that assemble but probably do not do what was
+//! meant. They never stop assembly: every warning has `is_correct: true`.
 
+use crate::ast::{LineNode, Operand, Program, Statement};
+use crate::isa::{self, flags};
+use crate::semantics::validator::CompilerError;
+use std::collections::HashMap;
+
+/// An instruction as a node of the control-flow graph.
+struct Node<'a> {
+    /// Validator line number (statement index + 1).
+    line: usize,
+    mnemonic: String,
+    operands: &'a [Operand],
+}
+
+/// Runs every check.
+pub fn lint(ast: &Program) -> Vec<CompilerError> {
+    unset_flag_reads(ast)
+}
+
+/// Conditional branches that test a flag no instruction on any path to
+/// them sets (or that was last left undefined, e.g. ZF after MUL).
+///
+/// Flags are tracked along the control flow from the entry point: jumps
+/// follow their label, CALL enters the procedure with the caller's flags
+/// and, since the callee may set anything, returns with all of them
+/// defined, as does INT. Code only reachable through indirect jumps is not
+/// checked.
+fn unset_flag_reads(ast: &Program) -> Vec<CompilerError> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut entry_label = None;
+
+    for (idx, spanned) in ast.iter().enumerate() {
+        match &spanned.node {
+            LineNode::Statement(Statement::Instruction { mnemonic, operands }) => {
+                nodes.push(Node {
+                    line: idx + 1,
+                    mnemonic: mnemonic.to_uppercase(),
+                    operands,
+                })
+            }
+            LineNode::Statement(Statement::Label(name) | Statement::Procedure { name, .. }) => {
+                labels.insert(name.to_uppercase(), nodes.len());
+            }
+            LineNode::Statement(Statement::End { label }) => entry_label = label.clone(),
+            _ => {}
+        }
+    }
+    if nodes.is_empty() {
+        return Vec::new();
+    }
+
+    let target = |node: &Node| match node.operands {
+        [Operand::Label(name)] => labels.get(&name.to_uppercase()).copied(),
+        _ => None,
+    };
+    let entry = entry_label
+        .and_then(|name| labels.get(&name.to_uppercase()).copied())
+        .unwrap_or(0);
+
+    // Flags defined on entry to each node; None until reached
+    let mut defined: Vec<Option<u16>> = vec![None; nodes.len() + 1];
+    defined[entry] = Some(0);
+    let mut work = vec![entry];
+    while let Some(at) = work.pop() {
+        let (Some(node), Some(before)) = (nodes.get(at), defined[at]) else {
+            continue;
+        };
+        let main = node.mnemonic.split_whitespace().last().unwrap_or_default();
+        let after = flags::effects(&node.mnemonic).map_or(before, |e| e.defined_after(before));
+
+        let mut successors = Vec::new();
+        match main {
+            "CALL" => {
+                successors.extend(target(node).map(|t| (t, after)));
+                successors.push((at + 1, flags::ALL));
+            }
+            // The handler may return anything: DOS reports errors in CF,
+            // INT 16h a waiting key in ZF
+            "INT" | "INTO" => successors.push((at + 1, flags::ALL)),
+            "JMP" => successors.extend(target(node).map(|t| (t, after))),
+            "RET" | "RETN" | "RETF" | "IRET" | "HLT" => {}
+            _ => {
+                if isa::is_branch(main) {
+                    successors.extend(target(node).map(|t| (t, after)));
+                }
+                successors.push((at + 1, after));
+            }
+        }
+
+        for (next, out) in successors {
+            let merged = defined[next].unwrap_or(0) | out;
+            if defined[next] != Some(merged) {
+                defined[next] = Some(merged);
+                work.push(next);
+            }
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (node, before) in nodes.iter().zip(&defined) {
+        let main = node.mnemonic.split_whitespace().last().unwrap_or_default();
+        let (Some(before), Some(effects)) = (before, flags::effects(main)) else {
+            continue;
+        };
+        let missing = effects.tested & !before;
+        if isa::is_branch(main) && missing != 0 {
+            warnings.push(CompilerError {
+                message: format!(
+                    "El salto '{}' lee {}, que ninguna instrucción anterior deja definido",
+                    main,
+                    flags::names(missing).join(", ")
+                ),
+                line: node.line,
+                is_correct: true,
+            });
+        }
+    }
+    warnings
+}
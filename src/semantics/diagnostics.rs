@@ -12,19 +12,12 @@ pub fn diagnose_syntax_error(line: &str) -> String {
     for word in trimmed.split_whitespace() {
         // Strip common delimiters if any attached (like comma)
         let clean_word = word.trim_matches(|c| c == ',' || c == '[' || c == ']');
-        if clean_word.to_lowercase().ends_with('h') {
-            if clean_word.len() > 1 {
-                let val = &clean_word[..clean_word.len() - 1];
-                // Check if it's hex digits
-                if val.chars().all(|c| c.is_ascii_hexdigit()) {
-                    // STRICT CHECK: Must start with 0
-                    if !val.starts_with('0') {
-                        return format!(
-                            "Constante Hex inválida '{}' (falta 0 inicial)",
-                            clean_word
-                        );
-                    }
-                }
+        if clean_word.to_lowercase().ends_with('h') && clean_word.len() > 1 {
+            let val = &clean_word[..clean_word.len() - 1];
+            // Check if it's hex digits
+            // STRICT CHECK: Must start with 0
+            if val.chars().all(|c| c.is_ascii_hexdigit()) && !val.starts_with('0') {
+                return format!("Constante Hex inválida '{}' (falta 0 inicial)", clean_word);
             }
         }
     }
@@ -33,7 +26,8 @@ pub fn diagnose_syntax_error(line: &str) -> String {
         return "Formato DUP inválido. Use: count DUP(val)".to_string();
     }
 
-    if (line.matches('"').count() % 2 != 0) || (line.matches('‘').count() % 2 != 0) {
+    if !line.matches('"').count().is_multiple_of(2) || !line.matches('‘').count().is_multiple_of(2)
+    {
         return "Faltan comillas de cierre".to_string();
     }
 
@@ -1,285 +1,1065 @@
-use crate::ast::{LineNode, Operand, Program, Statement};
-use crate::semantics::validator::SymbolInfo;
-use std::collections::HashMap;
+use crate::ast::{Distance, Expr, LineNode, Operand, Program, Statement};
+use crate::isa::{self, Form, Instruction, ModRm, Op, OperandClass, Size, Width};
+use crate::semantics::expression::{
+    Address, Value, assign_constant, evaluate, evaluate_absolute, operand_value,
+};
+use crate::semantics::validator::{
+    CompilerError, ConstantValue, DataType, SymbolInfo, SymbolType, lookup_symbol, segment_kind,
+};
+use crate::syntax::tokens::InstructionType;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+/// Knobs that change how the program is assembled.
+#[derive(Debug, Clone)]
+pub struct AssemblerOptions {
+    /// Rewrite conditional jumps whose target is out of rel8 range as an
+    /// inverted jump over a `JMP near` instead of reporting an error.
+    /// Only the command line turns it on (`--relax-jumps`); the web editor
+    /// and the wasm entry points always report those jumps.
+    pub relax_jumps: bool,
+    /// Lay every segment out in one address space starting at 100h instead
+    /// of restarting each segment at offset 0, as a .COM program requires.
+    pub single_segment: bool,
+    /// Location counter before the first segment. Single-segment layouts
+    /// always start at [`COM_ORIGIN`] instead.
+    pub start_offset: u64,
+}
+
+impl Default for AssemblerOptions {
+    fn default() -> Self {
+        AssemblerOptions {
+            relax_jumps: false,
+            single_segment: false,
+            start_offset: DEFAULT_START_OFFSET,
+        }
+    }
+}
+
+/// Location counter before the first segment unless configured otherwise.
+pub const DEFAULT_START_OFFSET: u64 = 0x0250;
+
+/// Load offset of a .COM program inside its segment (after the PSP).
+pub const COM_ORIGIN: u64 = 0x100;
+
+/// What the encoder knows about the statement being encoded.
+pub struct EncodeContext<'a> {
+    pub symbols: &'a HashMap<String, SymbolInfo>,
+    /// Offset of the first byte of the instruction.
+    pub address: u64,
+    /// Kind of the segment being assembled (`CODE`, `DATA`, ...).
+    pub segment: &'a str,
+    /// Pass one found the branch target out of rel8 range: skip short forms.
+    pub long_branch: bool,
+    /// Inside a FAR procedure, where a plain RET returns far.
+    pub far_return: bool,
+    pub options: &'a AssemblerOptions,
+}
+
+impl EncodeContext<'_> {
+    /// Value of `$`.
+    fn here(&self) -> Address {
+        Address::here(self.address, self.segment)
+    }
+}
 
 // PHASE 3: Determine Sizes & Addresses
 pub fn pass_one(
     program: &Program,
     symbol_table: &mut HashMap<String, SymbolInfo>,
-) -> HashMap<usize, u64> {
-    // Returns a map of Statement Index -> Address
+    options: &AssemblerOptions,
+) -> (HashMap<usize, u64>, HashMap<usize, u64>) {
+    // Returns maps of Statement Index -> Address and Statement Index -> Reserved Size
+
+    // Branches start optimistic (short) and are promoted to near once their
+    // target is out of range. Promotion is sticky, so sizes only ever grow and
+    // the layout converges; the iteration cap only guards against bugs.
+    let mut long_branches = HashSet::new();
+    let mut previous = None;
+    for _ in 0..MAX_LAYOUT_PASSES {
+        let (address_map, size_map) = layout(program, symbol_table, options, &long_branches);
+
+        let mut promoted = false;
+        let mut segment = "NONE";
+        for (index, spanned) in program.iter().enumerate() {
+            if let LineNode::Statement(Statement::Segment { name }) = &spanned.node {
+                segment = segment_kind(name).unwrap_or("NONE");
+            } else if let LineNode::Statement(Statement::Instruction { mnemonic, operands }) =
+                &spanned.node
+                && isa::is_branch(mnemonic)
+                && !long_branches.contains(&index)
+                && resolve_target(operands, symbol_table).is_some()
+            {
+                let ctx = EncodeContext {
+                    symbols: symbol_table,
+                    address: address_map[&index],
+                    segment,
+                    long_branch: false,
+                    far_return: false,
+                    options,
+                };
+                if encode_instruction(mnemonic, operands, &ctx).len() != SHORT_BRANCH_SIZE {
+                    long_branches.insert(index);
+                    promoted = true;
+                }
+            }
+        }
+
+        let converged = previous
+            .as_ref()
+            .is_some_and(|(prev, _)| *prev == address_map);
+        if !promoted && converged {
+            return (address_map, size_map);
+        }
+        previous = Some((address_map, size_map));
+    }
+    previous.unwrap_or_default()
+}
+
+const MAX_LAYOUT_PASSES: usize = 64;
+const SHORT_BRANCH_SIZE: usize = 2; // opcode + rel8
 
+/// One sizing pass over the program with the current branch decisions.
+///
+/// Every statement is sized by the same encoder pass two uses. Forward
+/// references are harmless: symbolic displacements are always encoded as
+/// 16 bits and branches are settled by the relaxation loop in `pass_one`.
+/// Statements that cannot be encoded reserve no space.
+fn layout(
+    program: &Program,
+    symbol_table: &mut HashMap<String, SymbolInfo>,
+    options: &AssemblerOptions,
+    long_branches: &HashSet<usize>,
+) -> (HashMap<usize, u64>, HashMap<usize, u64>) {
     let mut address_map = HashMap::new();
-    let mut location_counter: u64 = 0x0250; // Requirement: Start at 0250h
+    let mut size_map = HashMap::new();
+    let mut location_counter: u64 = if options.single_segment {
+        COM_ORIGIN
+    } else {
+        options.start_offset
+    };
+    // Segments in order of first appearance; the first holds what comes
+    // before any segment
+    let mut regions = vec![Region {
+        kind: None,
+        resume: 0,
+        extent: 0,
+    }];
+    let mut current = 0;
+    let mut segment = "NONE";
+    let mut far_return = false;
 
     for (index, spanned) in program.iter().enumerate() {
-        // Store current address for this statement
-        // Note: For Empty/Error lines, we might still store an address (the current LC)
-        // or skip it. If we skip it, the frontend map lookup might fail if it expects 1-to-1.
-        // But the frontend iterates lines and checks if address_map has key.
-
         // We will store address for ALL lines, so the frontend can show the address even for errors/comments
         address_map.insert(index, location_counter);
+        let here = Address::here(location_counter, segment);
 
         if let LineNode::Statement(stmt) = &spanned.node {
-            match stmt {
-                Statement::Segment { name: _ } => {
-                    location_counter = 0x0000; // Reset for new segment (or align)
-                    // If we want a specific org for code segment, we might check name
+            let size = match stmt {
+                Statement::Segment { name } => {
+                    let kind = segment_kind(name);
+                    segment = kind.unwrap_or("NONE");
+                    if !options.single_segment {
+                        regions[current].resume = location_counter;
+                        // A segment opened again carries on where it stopped
+                        let open =
+                            kind.and_then(|k| regions.iter().position(|r| r.kind == Some(k)));
+                        current = open.unwrap_or_else(|| {
+                            regions.push(Region {
+                                kind,
+                                resume: 0,
+                                extent: 0,
+                            });
+                            regions.len() - 1
+                        });
+                        location_counter = regions[current].resume;
+                    }
+                    continue;
+                }
+                Statement::Directive { name, args } if name == "ORG" => {
+                    if let [arg] = args.as_slice() {
+                        match operand_value(arg, symbol_table, &here) {
+                            Ok(Value::Absolute(value)) => location_counter = value as u16 as u64,
+                            Ok(Value::Relocatable(address)) => {
+                                location_counter = address.offset as u16 as u64
+                            }
+                            _ => {}
+                        }
+                    }
+                    continue;
+                }
+                Statement::Directive { name, args } => {
+                    match alignment_padding(name, args, symbol_table, &here) {
+                        Some(padding) => padding.len(),
+                        None => continue,
+                    }
+                }
+                // Values built on `$` or on labels change as the layout
+                // settles, and `=` values as the source goes on
+                Statement::Constant {
+                    name,
+                    value,
+                    redefinable,
+                } => {
+                    if *redefinable
+                        || symbol_table
+                            .get(name)
+                            .is_some_and(|sym| sym.line_defined == index + 1)
+                    {
+                        let _ = assign_constant(name, value, symbol_table, &here);
+                    }
+                    continue;
                 }
                 Statement::Label(name) => {
                     // Update Symbol Table with the calculated address
                     if let Some(sym) = symbol_table.get_mut(name) {
                         sym.offset = Some(location_counter);
                     }
+                    continue;
+                }
+                Statement::Procedure { name, distance } => {
+                    far_return = *distance == Distance::Far;
+                    if let Some(sym) = symbol_table.get_mut(name) {
+                        sym.offset = Some(location_counter);
+                    }
+                    continue;
+                }
+                Statement::ProcedureEnd { .. } => {
+                    far_return = false;
+                    continue;
                 }
-                // Inside the match stmt loop:
                 Statement::Variable {
                     name,
                     directive,
                     value,
                 } => {
-                    // START FIX
                     if let Some(sym) = symbol_table.get_mut(name) {
                         sym.offset = Some(location_counter);
                     }
-                    // END FIX
-                    let size = get_variable_size(directive, value);
-                    location_counter += size;
+                    encode_data(directive, value, symbol_table, &here).len()
                 }
                 Statement::Data { directive, value } => {
-                    // Note: Data statements usually don't have names in your AST unless
-                    // wrapped in Variable, but if they do, update here too.
-                    let size = get_variable_size(directive, value);
-                    location_counter += size;
+                    encode_data(directive, value, symbol_table, &here).len()
                 }
                 Statement::Instruction { mnemonic, operands } => {
-                    // ESTIMATE instruction size.
-                    let size = estimate_instruction_size(mnemonic, operands);
-                    location_counter += size;
+                    let ctx = EncodeContext {
+                        symbols: symbol_table,
+                        address: location_counter,
+                        segment,
+                        long_branch: long_branches.contains(&index),
+                        far_return,
+                        options,
+                    };
+                    encode_instruction(mnemonic, operands, &ctx).len()
+                }
+                _ => continue,
+            } as u64;
+
+            size_map.insert(index, size);
+            location_counter += size;
+            regions[current].extent = regions[current].extent.max(location_counter);
+        }
+    }
+
+    // Each segment starts on the next paragraph of the image
+    let mut image_end: u64 = 0;
+    for region in &regions {
+        let base = image_end.next_multiple_of(16);
+        image_end = base + region.extent;
+        if let Some(sym) = region.kind.and_then(|k| symbol_table.get_mut(k)) {
+            sym.offset = Some(base / 16);
+        }
+    }
+    (address_map, size_map)
+}
+
+/// A segment as `layout` fills it.
+struct Region {
+    kind: Option<&'static str>,
+    /// Location counter when another segment was opened.
+    resume: u64,
+    /// Bytes from the segment start to the end of its last statement.
+    extent: u64,
+}
+
+// PHASE 4: Generate Machine Code
+pub fn pass_two(
+    program: &Program,
+    address_map: &HashMap<usize, u64>,
+    size_map: &HashMap<usize, u64>,
+    symbol_table: &HashMap<String, SymbolInfo>,
+    options: &AssemblerOptions,
+) -> (HashMap<usize, Vec<u8>>, Vec<CompilerError>) {
+    // Returns map of Statement Index -> Machine Code
+
+    let mut encoding_map = HashMap::new();
+    let mut errors = Vec::new();
+    // Copied only if a `=` symbol is assigned again
+    let mut symbols = Cow::Borrowed(symbol_table);
+    let mut segment = "NONE";
+    let mut far_return = false;
+
+    for (index, spanned) in program.iter().enumerate() {
+        let LineNode::Statement(stmt) = &spanned.node else {
+            continue;
+        };
+        let address = address_map.get(&index).copied().unwrap_or(0);
+        let here = Address::here(address, segment);
+        let symbol_table = symbols.as_ref();
+        let bytes = match stmt {
+            Statement::Segment { name } => {
+                segment = segment_kind(name).unwrap_or("NONE");
+                continue;
+            }
+            Statement::Procedure { distance, .. } => {
+                far_return = *distance == Distance::Far;
+                continue;
+            }
+            Statement::ProcedureEnd { .. } => {
+                far_return = false;
+                continue;
+            }
+            Statement::Constant {
+                name,
+                value,
+                redefinable: true,
+            } => {
+                let _ = assign_constant(name, value, symbols.to_mut(), &here);
+                continue;
+            }
+            Statement::Instruction { mnemonic, operands } => {
+                // Promotion is sticky: a branch pass one made near stays near
+                // even if the layout later brought its target back in range
+                let long_branch = isa::is_branch(mnemonic)
+                    && size_map
+                        .get(&index)
+                        .is_some_and(|size| *size != SHORT_BRANCH_SIZE as u64);
+                let ctx = EncodeContext {
+                    symbols: symbol_table,
+                    address,
+                    segment,
+                    long_branch,
+                    far_return,
+                    options,
+                };
+                let bytes = encode_instruction(mnemonic, operands, &ctx);
+                if bytes.is_empty()
+                    && isa::is_branch(mnemonic)
+                    && let Some(target) = resolve_target(operands, symbol_table)
+                {
+                    errors.push(CompilerError {
+                        message: format!(
+                            "Salto fuera de rango: '{}' está a más de 128 bytes",
+                            target
+                        ),
+                        line: index + 1,
+                        is_correct: false,
+                    });
                 }
-                _ => {}
+                bytes
+            }
+            Statement::Variable {
+                value, directive, ..
+            }
+            | Statement::Data {
+                value, directive, ..
+            } => encode_data(directive, value, symbol_table, &here),
+            Statement::Directive { name, args } => {
+                match alignment_padding(name, args, symbol_table, &here) {
+                    Some(padding) => padding,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        // Any drift here would shift every later address away from the bytes emitted
+        let reserved = size_map.get(&index).copied().unwrap_or(0);
+        if bytes.len() as u64 != reserved {
+            errors.push(CompilerError {
+                message: format!(
+                    "Tamaño inconsistente: {} bytes generados, {} reservados en la primera pasada",
+                    bytes.len(),
+                    reserved
+                ),
+                line: index + 1,
+                is_correct: false,
+            });
+        }
+
+        if !bytes.is_empty() {
+            encoding_map.insert(index, bytes);
+        }
+    }
+    (encoding_map, errors)
+}
+
+/// Encodes one instruction placed at `address` the way pass two would.
+/// Empty when it cannot be encoded.
+pub fn encode_at(
+    mnemonic: &str,
+    operands: &[Operand],
+    symbols: &HashMap<String, SymbolInfo>,
+    address: u64,
+) -> Vec<u8> {
+    let options = AssemblerOptions::default();
+    let ctx = EncodeContext {
+        symbols,
+        address,
+        segment: "CODE",
+        long_branch: false,
+        far_return: false,
+        options: &options,
+    };
+    encode_instruction(mnemonic, operands, &ctx)
+}
+
+/// Offsets inside a statement's `len` encoded bytes that hold a segment base
+/// and need a load-time relocation.
+///
+/// Immediates are always the last field of an 8086 instruction, so a segment
+/// operand occupies the final word.
+pub fn segment_fixups(
+    stmt: &Statement,
+    len: usize,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Vec<usize> {
+    let is_segment = |op: &Operand| match op {
+        Operand::Label(name) => {
+            lookup_symbol(name, symbols).is_some_and(|s| matches!(s.type_, SymbolType::Segment))
+        }
+        // SEG var: `$` plays no part in whether the result is a segment
+        Operand::Expression(expr) => matches!(
+            evaluate(expr, symbols, &Address::here(0, "NONE")),
+            Ok(Value::Frame(_))
+        ),
+        _ => false,
+    };
+    // CALL far_proc: the pointer's segment word, also the last one
+    let is_far = |op: &Operand| match op {
+        Operand::Label(name) => symbols
+            .get(name)
+            .is_some_and(|s| matches!(s.type_, SymbolType::Procedure(Distance::Far))),
+        _ => false,
+    };
+    match stmt {
+        Statement::Instruction { operands, .. }
+            if len >= 2 && operands.iter().any(|op| is_segment(op) || is_far(op)) =>
+        {
+            vec![len - 2]
+        }
+        Statement::Variable {
+            directive, value, ..
+        }
+        | Statement::Data { directive, value }
+            if directive.eq_ignore_ascii_case("DW") =>
+        {
+            let unit = match value {
+                Operand::Dup { value, .. } => value.as_ref(),
+                _ => value,
+            };
+            if is_segment(unit) {
+                (0..len).step_by(2).collect()
+            } else {
+                vec![]
             }
         }
+        _ => vec![],
     }
-    address_map
 }
 
-fn get_variable_size(directive: &str, value: &Operand) -> u64 {
-    match directive.to_uppercase().as_str() {
-        "DB" => match value {
-            Operand::StringLiteral(s) => s.len() as u64,
-            _ => 1,
+/// Boundary an `ALIGN n` or `EVEN` statement moves the location counter
+/// to, or `None` for other directives and invalid counts.
+pub fn alignment(
+    name: &str,
+    args: &[Operand],
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Option<u64> {
+    match (name, args) {
+        ("EVEN", []) => Some(2),
+        ("ALIGN", [arg]) => match operand_value(arg, symbols, here) {
+            Ok(Value::Absolute(n)) if n > 0 && (n as u64).is_power_of_two() => Some(n as u64),
+            _ => None,
         },
-        "DW" => 2,
-        "DD" => 4,
-        _ => 0,
+        _ => None,
     }
 }
 
-fn estimate_instruction_size(mnemonic: &str, operands: &[Operand]) -> u64 {
-    // Simplified 8086 sizing:
-    // Basic: 2 bytes
-    // Immediate 16-bit: +2 bytes or +1 byte if 8-bit
-    // Memory displacement: +2 bytes
+/// Bytes that fill the gap up to an alignment boundary: NOPs in code,
+/// so execution can run through them, zeros elsewhere.
+fn alignment_padding(
+    name: &str,
+    args: &[Operand],
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Option<Vec<u8>> {
+    let boundary = alignment(name, args, symbols, here)?;
+    let location = here.offset as u64;
+    let fill = if here.segment == "CODE" { 0x90 } else { 0x00 };
+    Some(vec![
+        fill;
+        (location.next_multiple_of(boundary) - location)
+            as usize
+    ])
+}
 
-    // This is a rough estimator. For accurate sizing, we need to know exact opcode.
-    // Pass 2 will be the authority, but Pass 1 needs to be close for labels.
+/// "B8 34 12" style rendering used by the listing views.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
 
-    let base_size = match mnemonic.to_uppercase().as_str() {
-        "RET" | "NOP" | "INT" => 1, // Single byte usually, INT is 2 but handled below
-        _ => 2,
+fn encode_data(
+    directive: &str,
+    value: &Operand,
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Vec<u8> {
+    let width = match directive.to_uppercase().as_str() {
+        "DB" => 1,
+        "DW" => 2,
+        "DD" => 4,
+        _ => return vec![],
     };
+    // Little Endian
+    let unit = |val: u64| val.to_le_bytes()[..width].to_vec();
 
-    let mut extra = 0;
-    if mnemonic.to_uppercase() == "INT" {
-        return 2;
+    match value {
+        Operand::Immediate(val, _) => unit(*val),
+        // DB spreads a string over bytes; wider directives take 'ab' as a number
+        Operand::StringLiteral(s) if width == 1 => s.bytes().collect(),
+        Operand::StringLiteral(s) if s.len() <= width => {
+            unit(s.bytes().fold(0, |acc, b| (acc << 8) | b as u64))
+        }
+        Operand::Uninitialized => vec![0; width],
+        Operand::Label(name) if let Some(value) = constant_operand(name, symbols) => {
+            encode_data(directive, &value, symbols, here)
+        }
+        // DW label: the label's offset (a segment's paragraph for DW DATA)
+        Operand::Label(name) if width > 1 => match lookup_symbol(name, symbols) {
+            Some(sym) => unit(sym.offset.unwrap_or(0)),
+            None => vec![],
+        },
+        // DW msg + 2, DB N * 4, DW SEG msg
+        Operand::Expression(expr) => match evaluate(expr, symbols, here) {
+            Ok(Value::Absolute(value)) => unit(value as u64),
+            Ok(Value::Relocatable(address)) if width > 1 => unit(address.offset as u64),
+            Ok(Value::Frame(segment)) if width > 1 => {
+                unit(symbols.get(&segment).and_then(|s| s.offset).unwrap_or(0))
+            }
+            _ => vec![],
+        },
+        Operand::Dup { count, value } => match evaluate_absolute(count, symbols, here) {
+            Ok(count) if count >= 0 => {
+                encode_data(directive, value, symbols, here).repeat(count as usize)
+            }
+            _ => vec![],
+        },
+        _ => vec![],
     }
+}
 
-    for op in operands {
-        match op {
-            Operand::Immediate(val, _) => {
-                if *val > 255 {
-                    extra += 2;
-                } else {
-                    extra += 1;
-                }
+// --- EFFECTIVE ADDRESS (ModR/M) ---
+
+/// A memory operand reduced to the 8086 addressing components:
+/// `[base + index + displacement]`, or a direct `[displacement]` when no
+/// register takes part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveAddress {
+    pub base: Option<String>,  // BX | BP
+    pub index: Option<String>, // SI | DI
+    pub displacement: i64,
+    /// The displacement includes a symbol's offset. Like MASM we then always
+    /// use a 16-bit displacement, so the size never depends on where the
+    /// symbol ends up.
+    pub relocatable: bool,
+}
+
+impl EffectiveAddress {
+    pub fn direct(displacement: i64) -> Self {
+        Self {
+            base: None,
+            index: None,
+            displacement,
+            relocatable: false,
+        }
+    }
+
+    /// Builds an address from the registers found inside the brackets.
+    /// Rejects combinations the 8086 cannot encode (e.g. `[AX]`, `[BX+BP]`).
+    pub fn from_registers(registers: &[String], displacement: i64) -> Option<Self> {
+        let mut ea = Self::direct(displacement);
+        for reg in registers {
+            match reg.to_uppercase().as_str() {
+                r @ ("BX" | "BP") if ea.base.is_none() => ea.base = Some(r.to_string()),
+                r @ ("SI" | "DI") if ea.index.is_none() => ea.index = Some(r.to_string()),
+                _ => return None,
             }
-            Operand::Memory { .. } => extra += 2,
-            _ => {}
         }
+        Some(ea)
     }
 
-    // Clamp/Fix common cases
-    if mnemonic.to_uppercase() == "MOV" {
-        // MOV reg, reg = 2
-        // MOV reg, imm16 = 3 or 4
-        // MOV reg, imm8 = 2 or 3
-        return 2 + extra.min(2); // Heuristic
+    /// The r/m field for this register combination, or `None` for a direct address.
+    fn rm(&self) -> Option<u8> {
+        match (self.base.as_deref(), self.index.as_deref()) {
+            (Some("BX"), Some("SI")) => Some(0b000),
+            (Some("BX"), Some("DI")) => Some(0b001),
+            (Some("BP"), Some("SI")) => Some(0b010),
+            (Some("BP"), Some("DI")) => Some(0b011),
+            (None, Some("SI")) => Some(0b100),
+            (None, Some("DI")) => Some(0b101),
+            (Some("BP"), None) => Some(0b110),
+            (Some("BX"), None) => Some(0b111),
+            _ => None,
+        }
     }
+}
 
-    base_size + extra
+/// Emits the ModR/M byte plus displacement for a memory operand.
+///
+/// mod=00 is used when there is no displacement, except for `[BP]`, whose
+/// mod=00 slot is taken by direct addressing and therefore needs a zero disp8.
+pub fn encode_modrm(reg_field: u8, ea: &EffectiveAddress) -> Vec<u8> {
+    let disp = ea.displacement;
+    match ea.rm() {
+        None => {
+            let d = disp as u16;
+            vec![(reg_field << 3) | 0b110, d as u8, (d >> 8) as u8]
+        }
+        Some(rm) if disp == 0 && rm != 0b110 && !ea.relocatable => vec![(reg_field << 3) | rm],
+        Some(rm) if (-128..=127).contains(&disp) && !ea.relocatable => {
+            vec![0x40 | (reg_field << 3) | rm, disp as i8 as u8]
+        }
+        Some(rm) => {
+            let d = disp as u16;
+            vec![0x80 | (reg_field << 3) | rm, d as u8, (d >> 8) as u8]
+        }
+    }
 }
 
-// PHASE 4: Generate Machine Code
-pub fn pass_two(program: &Program, _address_map: &HashMap<usize, u64>) -> HashMap<usize, String> {
-    // Returns map of Statement Index -> Hex String
+/// An operand with everything the encoder needs resolved from the symbol table.
+#[derive(Debug, Clone)]
+enum Resolved {
+    Register(u8, Width),
+    Segment(u8),
+    Memory(EffectiveAddress, Option<Width>),
+    Immediate(i64),
+    /// Paragraph of a segment, patched by the loader through a relocation.
+    SegmentBase(u16),
+    /// Code label; offset is `None` until pass one has placed it.
+    Target(Option<u64>),
+    /// FAR procedure: offset and the paragraph of its segment.
+    FarTarget(Option<u64>, u16),
+}
 
-    let mut encoding_map = HashMap::new();
+impl Resolved {
+    fn class(&self) -> OperandClass {
+        match self {
+            Resolved::Register(code, width) => OperandClass::Register {
+                code: *code,
+                width: *width,
+            },
+            Resolved::Segment(code) => OperandClass::Segment(*code),
+            Resolved::Memory(ea, width) => OperandClass::Memory {
+                direct: ea.rm().is_none(),
+                width: *width,
+            },
+            Resolved::Immediate(v) => OperandClass::Immediate(*v),
+            Resolved::SegmentBase(_) => OperandClass::SegmentBase,
+            Resolved::Target(_) => OperandClass::Target,
+            Resolved::FarTarget(..) => OperandClass::FarTarget,
+        }
+    }
 
-    for (index, spanned) in program.iter().enumerate() {
-        if let LineNode::Statement(stmt) = &spanned.node {
-            match stmt {
-                Statement::Instruction { mnemonic, operands } => {
-                    let bytes = encode_instruction(mnemonic, operands);
-                    if !bytes.is_empty() {
-                        let hex_string = bytes
-                            .iter()
-                            .map(|b| format!("{:02X}", b))
-                            .collect::<Vec<String>>()
-                            .join(" ");
-                        encoding_map.insert(index, hex_string);
+    /// ModR/M byte (plus displacement) with this operand in the r/m slot.
+    fn encode_rm(&self, reg_field: u8) -> Option<Vec<u8>> {
+        match self {
+            Resolved::Register(code, _) => Some(vec![0xC0 | (reg_field << 3) | code]),
+            Resolved::Memory(ea, _) => Some(encode_modrm(reg_field, ea)),
+            _ => None,
+        }
+    }
+
+    fn reg_code(&self) -> Option<u8> {
+        match self {
+            Resolved::Register(code, _) | Resolved::Segment(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+fn resolve_operand(op: &Operand, ctx: &EncodeContext) -> Option<Resolved> {
+    let symbols = ctx.symbols;
+    match op {
+        Operand::Register(r) if is_segment_reg(r) => Some(Resolved::Segment(segment_code(r))),
+        Operand::Register(r) if is_register(r) => {
+            Some(Resolved::Register(reg_code(r), reg_width(r)))
+        }
+        Operand::Memory {
+            base,
+            index,
+            displacement,
+            ..
+        } => {
+            let registers: Vec<String> = base.iter().chain(index.iter()).cloned().collect();
+            // [BX+N]: a number is a plain displacement; [BX+var] takes the
+            // variable's offset and size
+            let (disp, width, relocatable) = match displacement {
+                None => (0, None, false),
+                Some(expr) => match evaluate(expr, symbols, &ctx.here()).ok()? {
+                    Value::Absolute(value) => (value, None, false),
+                    Value::Relocatable(address) => {
+                        (address.offset, data_width(&address.data_type), true)
                     }
+                    Value::Frame(_) => return None,
+                },
+            };
+            let mut ea = EffectiveAddress::from_registers(&registers, disp)?;
+            ea.relocatable = relocatable;
+            Some(Resolved::Memory(ea, width))
+        }
+        Operand::Ptr { size, target } => match resolve_operand(target, ctx)? {
+            Resolved::Memory(ea, _) => Some(Resolved::Memory(
+                ea,
+                match size.as_str() {
+                    "BYTE" => Some(Width::Byte),
+                    "WORD" => Some(Width::Word),
+                    _ => None,
+                },
+            )),
+            _ => None,
+        },
+        Operand::Immediate(v, _) => Some(Resolved::Immediate(*v as i64)),
+        // 'a' and 'ab' are character constants, not data
+        Operand::StringLiteral(s) if (1..=2).contains(&s.len()) => Some(Resolved::Immediate(
+            s.bytes().fold(0i64, |acc, b| (acc << 8) | b as i64),
+        )),
+        Operand::Expression(expr) => resolve_value(evaluate(expr, symbols, &ctx.here()).ok()?, ctx),
+        Operand::Label(name) => {
+            let sym = lookup_symbol(name, symbols)?;
+            match sym.type_ {
+                // A bare variable name is a direct memory reference
+                SymbolType::Variable => {
+                    let (offset, width) = variable_address(name, symbols)?;
+                    let mut ea = EffectiveAddress::direct(offset);
+                    ea.relocatable = true;
+                    Some(Resolved::Memory(ea, width))
                 }
-                Statement::Variable {
-                    value, directive, ..
-                } => {
-                    let hex = encode_data(directive, value);
-                    encoding_map.insert(index, hex);
-                }
-                Statement::Data {
-                    value, directive, ..
-                } => {
-                    let hex = encode_data(directive, value);
-                    encoding_map.insert(index, hex);
+                SymbolType::Label | SymbolType::Procedure(Distance::Near) => {
+                    Some(Resolved::Target(sym.offset))
                 }
-                _ => {}
+                SymbolType::Procedure(Distance::Far) => Some(Resolved::FarTarget(
+                    sym.offset,
+                    symbols.get(&sym.segment)?.offset.unwrap_or(0) as u16,
+                )),
+                SymbolType::Segment => Some(Resolved::SegmentBase(sym.offset.unwrap_or(0) as u16)),
+                SymbolType::Constant => resolve_operand(&constant_operand(name, symbols)?, ctx),
             }
         }
+        _ => None,
     }
-    encoding_map
 }
 
-fn encode_data(directive: &str, value: &Operand) -> String {
+/// The operand an expression's result stands for: a number is an
+/// immediate, a variable's address a memory reference, a code address a
+/// branch target and OFFSET anything an immediate.
+fn resolve_value(value: Value, ctx: &EncodeContext) -> Option<Resolved> {
     match value {
-        Operand::Immediate(val, _) => {
-            if directive.to_uppercase() == "DW" {
-                // Little Endian
-                format!("{:02X} {:02X}", val & 0xFF, (val >> 8) & 0xFF)
-            } else {
-                format!("{:02X}", val & 0xFF)
-            }
+        Value::Absolute(value) => Some(Resolved::Immediate(value)),
+        Value::Relocatable(address) if address.offset_only => {
+            Some(Resolved::Immediate(address.offset))
         }
-        Operand::StringLiteral(s) => s
-            .bytes()
-            .map(|b| format!("{:02X}", b))
-            .collect::<Vec<String>>()
-            .join(" "),
-        _ => "".to_string(),
-    }
-}
-
-fn encode_instruction(mnemonic: &str, operands: &[Operand]) -> Vec<u8> {
-    let mnem = mnemonic.to_uppercase();
-
-    // Note: This is a partial implementation of 8086 encoding
-    match mnem.as_str() {
-        "MOV" => {
-            if operands.len() == 2 {
-                match (&operands[0], &operands[1]) {
-                    // MOV Reg, Reg
-                    (Operand::Register(dest), Operand::Register(src)) => {
-                        // Table for simplified Register encoding
-                        // AX=000, CX=001, DX=010, BX=011, SP=100, BP=101, SI=110, DI=111
-                        // Opcode 89 /r (MOV r/m16, r16) -> ModR/M byte
-                        // Mod=11 (Reg mode)
-                        // Reg=Src, R/M=Dest
-                        let d = reg_code(dest);
-                        let s = reg_code(src);
-                        let mod_rm = 0xC0 | (s << 3) | d;
-                        vec![0x89, mod_rm]
-                    }
-                    // MOV Reg, Imm
-                    (Operand::Register(dest), Operand::Immediate(val, _)) => {
-                        // B8+reg for 16-bit
-                        let reg = reg_code(dest);
-                        // Check if 8-bit or 16-bit register not fully supported here, assuming 16-bit (AX, BX etc)
-                        if is_16bit_reg(dest) {
-                            vec![0xB8 + reg, (val & 0xFF) as u8, (val >> 8) as u8]
-                        } else {
-                            // 8-bit mov: B0+reg
-                            vec![0xB0 + reg, (val & 0xFF) as u8]
-                        }
-                    }
-                    _ => vec![],
-                }
-            } else {
-                vec![]
+        Value::Relocatable(address) => match data_width(&address.data_type) {
+            Some(width) => {
+                let mut ea = EffectiveAddress::direct(address.offset);
+                ea.relocatable = true;
+                Some(Resolved::Memory(ea, Some(width)))
             }
+            None => Some(Resolved::Target(Some(address.offset as u64))),
+        },
+        Value::Frame(segment) => Some(Resolved::SegmentBase(
+            ctx.symbols.get(&segment)?.offset.unwrap_or(0) as u16,
+        )),
+    }
+}
+
+fn data_width(data_type: &DataType) -> Option<Width> {
+    match data_type {
+        DataType::Byte => Some(Width::Byte),
+        DataType::Word => Some(Width::Word),
+        DataType::None => None,
+    }
+}
+
+/// The operand an EQU or `=` name stands for: its number as an immediate,
+/// an address as the name itself (evaluated like a label), or the operand
+/// of a text equate.
+fn constant_operand(name: &str, symbols: &HashMap<String, SymbolInfo>) -> Option<Operand> {
+    let sym = symbols.get(name)?;
+    if !matches!(sym.type_, SymbolType::Constant) {
+        return None;
+    }
+    match sym.value.as_ref()? {
+        ConstantValue::Number(value) => Some(Operand::Immediate(*value as u64, value.to_string())),
+        ConstantValue::Address(_) => Some(Operand::Expression(Expr::Symbol(name.to_string()))),
+        // A text equate naming another constant is circular (see the validator)
+        ConstantValue::Text(Operand::Label(target))
+            if symbols
+                .get(target)
+                .is_some_and(|s| matches!(s.type_, SymbolType::Constant)) =>
+        {
+            None
         }
-        "INT" => {
-            if let Some(Operand::Immediate(val, _)) = operands.first() {
-                vec![0xCD, *val as u8]
-            } else {
-                vec![]
-            }
+        ConstantValue::Text(operand) => Some(operand.clone()),
+    }
+}
+
+/// Name of the code label a branch instruction jumps to, if it is defined.
+fn resolve_target<'a>(
+    operands: &'a [Operand],
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Option<&'a str> {
+    match operands {
+        [Operand::Label(name)]
+            if matches!(
+                symbols.get(name)?.type_,
+                SymbolType::Label | SymbolType::Procedure(_)
+            ) =>
+        {
+            Some(name)
         }
-        "NOP" => vec![0x90],
-        "RET" => vec![0xC3],
-        "ADD" => {
-            if operands.len() == 2 {
-                // ADD Reg, Reg -> 01 /r
-                match (&operands[0], &operands[1]) {
-                    (Operand::Register(dest), Operand::Register(src)) => {
-                        let d = reg_code(dest);
-                        let s = reg_code(src);
-                        let mod_rm = 0xC0 | (s << 3) | d;
-                        vec![0x01, mod_rm]
-                    }
-                    _ => vec![],
-                }
-            } else {
-                vec![]
-            }
+        _ => None,
+    }
+}
+
+/// Operand classes for form matching, or `None` if any operand cannot be resolved.
+pub fn classify_operands(
+    operands: &[Operand],
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Option<Vec<OperandClass>> {
+    let options = AssemblerOptions::default();
+    let ctx = EncodeContext {
+        symbols,
+        address: 0,
+        segment: "NONE",
+        long_branch: false,
+        far_return: false,
+        options: &options,
+    };
+    operands
+        .iter()
+        .map(|op| resolve_operand(op, &ctx).map(|r| r.class()))
+        .collect()
+}
+
+fn variable_address(
+    name: &str,
+    symbols: &HashMap<String, SymbolInfo>,
+) -> Option<(i64, Option<Width>)> {
+    let sym = symbols.get(name)?;
+    if !matches!(sym.type_, SymbolType::Variable) {
+        return None;
+    }
+    Some((sym.offset.unwrap_or(0) as i64, data_width(&sym.data_type)))
+}
+
+/// Segment override prefix (26h/2Eh/36h/3Eh) requested by any memory operand.
+fn segment_prefix(operands: &[Operand]) -> Option<u8> {
+    operands.iter().find_map(|op| match op {
+        Operand::Memory {
+            segment: Some(seg), ..
+        } => Some(0x26 | (segment_code(seg) << 3)),
+        Operand::Ptr { target, .. } => segment_prefix(std::slice::from_ref(target)),
+        _ => None,
+    })
+}
+
+fn push_imm(bytes: &mut Vec<u8>, value: i64, width: Width) {
+    bytes.push(value as u8);
+    if width == Width::Word {
+        bytes.push((value >> 8) as u8);
+    }
+}
+
+/// Lays out one table form: opcode (+w, +r), ModR/M, then immediates in operand order.
+fn encode_form(
+    form: &Form,
+    width: Width,
+    operands: &[Resolved],
+    ctx: &EncodeContext,
+) -> Option<Vec<u8>> {
+    let mut bytes = form.opcode.to_vec();
+    let last = bytes.len() - 1;
+
+    if form.size == Size::WidthBit && width == Width::Word {
+        bytes[last] |= 1;
+    }
+
+    let find = |wanted: &[Op]| {
+        form.operands
+            .iter()
+            .zip(operands)
+            .find(|(op, _)| wanted.contains(op))
+            .map(|(_, r)| r)
+    };
+
+    if form.reg_in_opcode {
+        match find(&[Op::Reg, Op::Sreg])? {
+            Resolved::Segment(code) => bytes[last] |= code << 3,
+            reg => bytes[last] += reg.reg_code()?,
         }
-        "SUB" => {
-            if operands.len() == 2 {
-                match (&operands[0], &operands[1]) {
-                    (Operand::Register(dest), Operand::Register(src)) => {
-                        let d = reg_code(dest);
-                        let s = reg_code(src);
-                        let mod_rm = 0xC0 | (s << 3) | d;
-                        vec![0x29, mod_rm]
-                    }
-                    _ => vec![],
+    }
+
+    match form.modrm {
+        ModRm::None => {}
+        ModRm::Reg => {
+            let reg = find(&[Op::Reg, Op::Sreg])?.reg_code()?;
+            bytes.extend(find(&[Op::RegMem, Op::Mem])?.encode_rm(reg)?);
+        }
+        ModRm::Digit(digit) => bytes.extend(find(&[Op::RegMem, Op::Mem])?.encode_rm(digit)?),
+    }
+
+    for (op, resolved) in form.operands.iter().zip(operands) {
+        match (op, resolved) {
+            (Op::Imm, Resolved::Immediate(v)) => push_imm(&mut bytes, *v, width),
+            (Op::Imm8 | Op::SImm8, Resolved::Immediate(v)) => push_imm(&mut bytes, *v, Width::Byte),
+            (Op::Imm16, Resolved::Immediate(v)) => push_imm(&mut bytes, *v, Width::Word),
+            (Op::Imm | Op::Imm16, Resolved::SegmentBase(frame)) => {
+                push_imm(&mut bytes, *frame as i64, Width::Word)
+            }
+            (Op::Moffs, Resolved::Memory(ea, _)) => {
+                push_imm(&mut bytes, ea.displacement, Width::Word)
+            }
+            // Displacements count from the end of the instruction; the branch
+            // target is always the last operand.
+            (Op::Rel8, Resolved::Target(target)) => {
+                if ctx.long_branch {
+                    return None;
                 }
-            } else {
-                vec![]
+                let disp = relative(*target, ctx.address, bytes.len() + 1);
+                if !(-128..=127).contains(&disp) {
+                    return None;
+                }
+                push_imm(&mut bytes, disp, Width::Byte);
+            }
+            (Op::Rel16, Resolved::Target(target)) => {
+                let disp = relative(*target, ctx.address, bytes.len() + 2);
+                push_imm(&mut bytes, disp, Width::Word);
             }
+            (Op::FarPtr, Resolved::FarTarget(offset, frame)) => {
+                push_imm(&mut bytes, offset.unwrap_or(0) as i64, Width::Word);
+                push_imm(&mut bytes, *frame as i64, Width::Word);
+            }
+            _ => {}
         }
-        "INC" => {
-            if let Some(Operand::Register(reg)) = operands.first() {
-                if is_16bit_reg(reg) {
-                    vec![0x40 + reg_code(reg)]
-                } else {
-                    vec![0xFE, 0xC0 + reg_code(reg)] // INC r8
-                }
+    }
+
+    Some(bytes)
+}
+
+/// Displacement from the end of an instruction of `len` bytes at `address`.
+/// Unplaced (forward) targets count as zero so the short form is tried first.
+fn relative(target: Option<u64>, address: u64, len: usize) -> i64 {
+    let next = address as i64 + len as i64;
+    target.map_or(0, |t| t as i64 - next)
+}
+
+/// `Jcc far_label` becomes `J!cc $+5` / `JMP near far_label` (8086 has no Jcc rel16).
+fn synthesize_long_jcc(
+    instruction: &Instruction,
+    target: Option<u64>,
+    ctx: &EncodeContext,
+) -> Option<Vec<u8>> {
+    if !matches!(instruction.category, InstructionType::ConditionalJump) {
+        return None;
+    }
+    let opcode = instruction.forms.first()?.opcode[0];
+    let mut bytes = vec![opcode ^ 1, 0x03, 0xE9];
+    push_imm(&mut bytes, relative(target, ctx.address, 5), Width::Word);
+    Some(bytes)
+}
+
+/// Picks the shortest table form matching the operands (first wins on ties).
+fn encode_with_table(
+    instruction: &Instruction,
+    operands: &[Resolved],
+    ctx: &EncodeContext,
+) -> Option<Vec<u8>> {
+    let classes: Vec<OperandClass> = operands.iter().map(Resolved::class).collect();
+    instruction
+        .forms
+        .iter()
+        .filter_map(|form| {
+            let width = form.matches(&classes)?;
+            encode_form(form, width, operands, ctx)
+        })
+        .reduce(|best, bytes| {
+            if bytes.len() < best.len() {
+                bytes
             } else {
-                vec![]
+                best
             }
+        })
+}
+
+fn encode_instruction(mnemonic: &str, operands: &[Operand], ctx: &EncodeContext) -> Vec<u8> {
+    // "REP MOVSB": every word but the last is a prefix
+    let words: Vec<&str> = mnemonic.split_whitespace().collect();
+    let Some((&main, prefixes)) = words.split_last() else {
+        return vec![];
+    };
+    // RET returns the way the enclosing procedure was called
+    let main = if ctx.far_return && main.eq_ignore_ascii_case("RET") {
+        "RETF"
+    } else {
+        main
+    };
+
+    let mut bytes = Vec::new();
+    if let Some(prefix) = segment_prefix(operands) {
+        bytes.push(prefix);
+    }
+    for prefix in prefixes {
+        match isa::lookup(prefix).and_then(|i| encode_with_table(i, &[], ctx)) {
+            Some(encoded) => bytes.extend(encoded),
+            None => return vec![],
         }
-        "DEC" => {
-            if let Some(Operand::Register(reg)) = operands.first() {
-                if is_16bit_reg(reg) {
-                    vec![0x48 + reg_code(reg)]
-                } else {
-                    vec![0xFE, 0xC8 + reg_code(reg)] // DEC r8
-                }
-            } else {
-                vec![]
+    }
+
+    let Some(resolved) = operands
+        .iter()
+        .map(|op| resolve_operand(op, ctx))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return vec![];
+    };
+
+    // POP CS (0Fh) does not exist on the 8086
+    if main.eq_ignore_ascii_case("POP") && matches!(resolved.as_slice(), [Resolved::Segment(1)]) {
+        return vec![];
+    }
+
+    let Some(instruction) = isa::lookup(main) else {
+        return vec![];
+    };
+    let encoded =
+        encode_with_table(instruction, &resolved, ctx).or_else(|| match resolved.as_slice() {
+            [Resolved::Target(target)] if ctx.options.relax_jumps => {
+                synthesize_long_jcc(instruction, *target, ctx)
             }
+            _ => None,
+        });
+
+    match encoded {
+        Some(encoded) => {
+            bytes.extend(encoded);
+            bytes
         }
-        _ => vec![],
+        None => vec![],
     }
 }
 
@@ -297,9 +1077,34 @@ fn reg_code(reg: &str) -> u8 {
     }
 }
 
+fn segment_code(reg: &str) -> u8 {
+    match reg.to_uppercase().as_str() {
+        "ES" => 0,
+        "CS" => 1,
+        "SS" => 2,
+        _ => 3, // DS
+    }
+}
+
+fn reg_width(reg: &str) -> Width {
+    if is_16bit_reg(reg) {
+        Width::Word
+    } else {
+        Width::Byte
+    }
+}
+
 fn is_16bit_reg(reg: &str) -> bool {
     matches!(
         reg.to_uppercase().as_str(),
         "AX" | "CX" | "DX" | "BX" | "SP" | "BP" | "SI" | "DI"
     )
 }
+
+fn is_segment_reg(reg: &str) -> bool {
+    matches!(reg.to_uppercase().as_str(), "CS" | "DS" | "SS" | "ES")
+}
+
+fn is_register(s: &str) -> bool {
+    crate::syntax::tokens::register::is_valid(&s.to_uppercase())
+}
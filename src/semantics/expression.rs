@@ -0,0 +1,304 @@
+// src/semantics/expression.rs
+//! Evaluation of assembly-time expressions and of EQU and `=` values.
+//!
+//! Every result is either a plain number, an offset inside a segment (a
+//! label, a variable or `$`, give or take a number) or the paragraph a
+//! segment is loaded at. Only combinations that keep that meaning are
+//! accepted: `msg + 2` is an offset, `fin - inicio` a number, but `msg * 2`
+//! or `msg + fin` mean nothing and are rejected.
+//!
+//! A numeric value is folded to a number when the constant is defined. Any
+//! other operand (a register, a memory reference, a long string, a `<text>`
+//! equate) is kept as text and stands in for the name wherever it is used.
+
+use crate::ast::{BinaryOp, Expr, Operand, UnaryOp};
+use crate::semantics::validator::{ConstantValue, DataType, SymbolInfo, SymbolType, lookup_symbol};
+use std::collections::HashMap;
+
+/// Result of an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A plain number.
+    Absolute(i64),
+    /// An offset inside a segment.
+    Relocatable(Address),
+    /// Paragraph of a segment (`SEG var`, `DATA`), patched by the loader.
+    Frame(String),
+}
+
+/// Offset of a location inside its segment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub offset: i64,
+    /// Segment kind ("DATA", "CODE", ...) the offset counts from.
+    pub segment: String,
+    /// Size of what is stored there; `None` for code labels and `$`.
+    pub data_type: DataType,
+    /// Taken with OFFSET: stands for the number, not for what is stored there.
+    pub offset_only: bool,
+}
+
+impl Address {
+    /// The location counter (`$`) at `offset` in `segment`.
+    pub fn here(offset: u64, segment: &str) -> Self {
+        Address {
+            offset: offset as i64,
+            segment: segment.to_string(),
+            data_type: DataType::None,
+            offset_only: false,
+        }
+    }
+}
+
+/// Evaluates `expr` with `here` as the value of `$`. Symbols placed by
+/// pass one count as offset 0 until then.
+pub fn evaluate(
+    expr: &Expr,
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Result<Value, String> {
+    match expr {
+        Expr::Number(value) => Ok(Value::Absolute(*value)),
+        Expr::Symbol(name) => symbol_value(name, symbols),
+        Expr::Here => Ok(Value::Relocatable(here.clone())),
+        Expr::Unary {
+            op: op @ (UnaryOp::Size | UnaryOp::Length),
+            operand,
+        } => {
+            let Expr::Symbol(name) = operand.as_ref() else {
+                return Err(format!("'{}' requiere el nombre de una variable", op));
+            };
+            match symbols.get(name) {
+                Some(sym) if matches!(sym.type_, SymbolType::Variable) => {
+                    let length = sym.length as i64;
+                    Ok(Value::Absolute(match op {
+                        UnaryOp::Size => length * type_size(&sym.data_type),
+                        _ => length,
+                    }))
+                }
+                Some(_) => Err(format!("'{}' requiere el nombre de una variable", op)),
+                None => Err(format!("Símbolo '{}' no definido", name)),
+            }
+        }
+        Expr::Unary { op, operand } => unary(*op, evaluate(operand, symbols, here)?),
+        Expr::Binary { op, left, right } => binary(
+            *op,
+            evaluate(left, symbols, here)?,
+            evaluate(right, symbols, here)?,
+        ),
+    }
+}
+
+/// Like [`evaluate`], for places that need a plain number (DUP counts).
+pub fn evaluate_absolute(
+    expr: &Expr,
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Result<i64, String> {
+    match evaluate(expr, symbols, here)? {
+        Value::Absolute(value) => Ok(value),
+        _ => Err("Se esperaba un número, no una dirección".to_string()),
+    }
+}
+
+/// Value of a number, a name or an expression operand (the ORG argument).
+pub fn operand_value(
+    operand: &Operand,
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Result<Value, String> {
+    match operand {
+        Operand::Immediate(value, _) => Ok(Value::Absolute(*value as i64)),
+        Operand::Label(name) => symbol_value(name, symbols),
+        Operand::Expression(expr) => evaluate(expr, symbols, here),
+        _ => Err("Se esperaba un número o una expresión".to_string()),
+    }
+}
+
+/// Names used in `expr`, in order of appearance.
+pub fn symbols(expr: &Expr) -> Vec<&String> {
+    match expr {
+        Expr::Symbol(name) => vec![name],
+        Expr::Number(_) | Expr::Here => vec![],
+        Expr::Unary { operand, .. } => symbols(operand),
+        Expr::Binary { left, right, .. } => {
+            let mut names = symbols(left);
+            names.extend(symbols(right));
+            names
+        }
+    }
+}
+
+fn symbol_value(name: &str, symbols: &HashMap<String, SymbolInfo>) -> Result<Value, String> {
+    let Some(sym) = lookup_symbol(name, symbols) else {
+        return Err(format!("Símbolo '{}' no definido", name));
+    };
+    match sym.type_ {
+        SymbolType::Constant => match &sym.value {
+            Some(ConstantValue::Number(value)) => Ok(Value::Absolute(*value)),
+            Some(ConstantValue::Address(address)) => Ok(Value::Relocatable(address.clone())),
+            _ => Err(format!("'{}' no es una constante numérica", name)),
+        },
+        SymbolType::Segment => Ok(Value::Frame(sym.segment.clone())),
+        SymbolType::Variable | SymbolType::Label | SymbolType::Procedure(_) => {
+            Ok(Value::Relocatable(Address {
+                offset: sym.offset.unwrap_or(0) as i64,
+                segment: sym.segment.clone(),
+                data_type: sym.data_type.clone(),
+                offset_only: false,
+            }))
+        }
+    }
+}
+
+/// Bytes per element for TYPE; a near label is 0FFFFh.
+fn type_size(data_type: &DataType) -> i64 {
+    match data_type {
+        DataType::Byte => 1,
+        DataType::Word => 2,
+        DataType::None => 0xFFFF,
+    }
+}
+
+fn unary(op: UnaryOp, value: Value) -> Result<Value, String> {
+    match (op, value) {
+        (UnaryOp::Offset, Value::Relocatable(address)) => Ok(Value::Relocatable(Address {
+            offset_only: true,
+            ..address
+        })),
+        (UnaryOp::Offset, Value::Absolute(value)) => Ok(Value::Absolute(value)),
+        (UnaryOp::Seg, Value::Relocatable(address)) => Ok(Value::Frame(address.segment)),
+        (UnaryOp::Seg, frame @ Value::Frame(_)) => Ok(frame),
+        (UnaryOp::Seg, Value::Absolute(_)) => {
+            Err("'SEG' requiere una etiqueta o variable".to_string())
+        }
+        (UnaryOp::Type, Value::Relocatable(address)) => {
+            Ok(Value::Absolute(type_size(&address.data_type)))
+        }
+        (UnaryOp::Type, _) => Ok(Value::Absolute(0)),
+        (op, Value::Absolute(value)) => Ok(Value::Absolute(match op {
+            UnaryOp::Negate => value.wrapping_neg(),
+            UnaryOp::Not => !value,
+            UnaryOp::High => (value >> 8) & 0xFF,
+            _ => value & 0xFF, // LOW
+        })),
+        (op, Value::Relocatable(_)) => Err(format!(
+            "'{}' no se puede aplicar a una dirección reubicable",
+            op
+        )),
+        (op, Value::Frame(_)) => Err(format!("'{}' no se puede aplicar a un segmento", op)),
+    }
+}
+
+fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    match (op, left, right) {
+        (op, Value::Absolute(left), Value::Absolute(right)) => {
+            arithmetic(op, left, right).map(Value::Absolute)
+        }
+        (BinaryOp::Add, Value::Relocatable(address), Value::Absolute(value))
+        | (BinaryOp::Add, Value::Absolute(value), Value::Relocatable(address)) => {
+            Ok(Value::Relocatable(Address {
+                offset: address.offset.wrapping_add(value),
+                ..address
+            }))
+        }
+        (BinaryOp::Sub, Value::Relocatable(address), Value::Absolute(value)) => {
+            Ok(Value::Relocatable(Address {
+                offset: address.offset.wrapping_sub(value),
+                ..address
+            }))
+        }
+        // The distance between two places of the same segment is a number
+        (BinaryOp::Sub, Value::Relocatable(left), Value::Relocatable(right)) => {
+            if left.segment == right.segment {
+                Ok(Value::Absolute(left.offset.wrapping_sub(right.offset)))
+            } else {
+                Err(format!(
+                    "No se pueden restar direcciones de segmentos distintos ({} y {})",
+                    left.segment, right.segment
+                ))
+            }
+        }
+        (op, Value::Frame(_), _) | (op, _, Value::Frame(_)) => {
+            Err(format!("'{}' no se puede aplicar a un segmento", op))
+        }
+        (op, _, _) => Err(format!(
+            "'{}' no se puede aplicar a una dirección reubicable",
+            op
+        )),
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: i64, right: i64) -> Result<i64, String> {
+    // Shifting every bit out leaves zero
+    let shift = |shifted: Option<i64>| Ok(shifted.unwrap_or(0));
+    let truth = |holds: bool| Ok(if holds { -1 } else { 0 });
+    match op {
+        BinaryOp::Add => Ok(left.wrapping_add(right)),
+        BinaryOp::Sub => Ok(left.wrapping_sub(right)),
+        BinaryOp::Mul => Ok(left.wrapping_mul(right)),
+        BinaryOp::Div | BinaryOp::Mod if right == 0 => Err("División por cero".to_string()),
+        BinaryOp::Div => Ok(left.wrapping_div(right)),
+        BinaryOp::Mod => Ok(left.wrapping_rem(right)),
+        BinaryOp::Shl => shift(u32::try_from(right).ok().and_then(|n| left.checked_shl(n))),
+        BinaryOp::Shr => shift(u32::try_from(right).ok().and_then(|n| left.checked_shr(n))),
+        BinaryOp::And => Ok(left & right),
+        BinaryOp::Or => Ok(left | right),
+        BinaryOp::Xor => Ok(left ^ right),
+        BinaryOp::Eq => truth(left == right),
+        BinaryOp::Ne => truth(left != right),
+        BinaryOp::Lt => truth(left < right),
+        BinaryOp::Le => truth(left <= right),
+        BinaryOp::Gt => truth(left > right),
+        BinaryOp::Ge => truth(left >= right),
+    }
+}
+
+/// Value a constant definition gives its name.
+pub fn constant_value(
+    value: &Operand,
+    symbols: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Result<ConstantValue, String> {
+    match value {
+        Operand::Expression(expr) => Ok(match evaluate(expr, symbols, here)? {
+            Value::Absolute(number) => ConstantValue::Number(number),
+            Value::Relocatable(address) => ConstantValue::Address(address),
+            // The segment's name stands for its paragraph wherever it is used
+            Value::Frame(segment) => ConstantValue::Text(Operand::Label(segment)),
+        }),
+        Operand::Immediate(value, _) => Ok(ConstantValue::Number(*value as i64)),
+        // 'a' and 'ab' are character constants
+        Operand::StringLiteral(s) if (1..=2).contains(&s.len()) => Ok(ConstantValue::Number(
+            s.bytes().fold(0i64, |acc, b| (acc << 8) | b as i64),
+        )),
+        // Another constant: copy its value, so text never names a constant
+        Operand::Label(name) => match symbols.get(name) {
+            Some(sym) if matches!(sym.type_, SymbolType::Constant) => sym
+                .value
+                .clone()
+                .ok_or_else(|| format!("Constante '{}' sin valor", name)),
+            _ => Ok(ConstantValue::Text(value.clone())),
+        },
+        _ => Ok(ConstantValue::Text(value.clone())),
+    }
+}
+
+/// Evaluates a definition of `name` and stores the result in its symbol.
+pub fn assign_constant(
+    name: &str,
+    value: &Operand,
+    symbols: &mut HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Result<(), String> {
+    let value = constant_value(value, symbols, here)?;
+    if let Some(sym) = symbols.get_mut(name) {
+        sym.offset = match &value {
+            ConstantValue::Number(number) => Some(*number as u16 as u64),
+            ConstantValue::Address(address) => Some(address.offset as u16 as u64),
+            ConstantValue::Text(_) => None,
+        };
+        sym.value = Some(value);
+    }
+    Ok(())
+}
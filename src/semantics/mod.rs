@@ -1,4 +1,5 @@
 pub mod analyzer;
 pub mod diagnostics;
 pub mod encoder;
+pub mod expression;
 pub mod validator;
@@ -1,6 +1,11 @@
 // src/semantics/validator.rs
-use crate::ast::{LineNode, Operand, Program, Statement};
-use std::collections::HashMap;
+use crate::ast::{Distance, Expr, LineNode, Operand, Program, Statement};
+use crate::isa;
+use crate::semantics::encoder::{AssemblerOptions, alignment, classify_operands};
+use crate::semantics::expression::{
+    self, Address, Value, assign_constant, constant_value, evaluate, evaluate_absolute,
+};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompilerError {
@@ -13,7 +18,11 @@ pub struct CompilerError {
 pub enum SymbolType {
     Variable,
     Label,
+    /// A PROC; its distance decides how CALL and RET are encoded.
+    Procedure(Distance),
     Constant,
+    /// A segment; its offset is the paragraph (frame) it starts at in the image.
+    Segment,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -23,28 +32,93 @@ pub enum DataType {
     None, // Label
 }
 
+/// How an instruction uses a symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    /// The symbol is the destination: written or modified in place.
+    Write,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub line: usize,
+    pub access: Access,
+}
+
+/// What an EQU or `=` constant stands for.
+#[derive(Debug, Clone)]
+pub enum ConstantValue {
+    Number(i64),
+    /// `msg + 2`, `$`: a location, used like the label or variable it is in.
+    Address(Address),
+    /// Text equate: the operand replaces the name wherever it is used.
+    Text(Operand),
+}
+
 #[derive(Debug, Clone)]
 pub struct SymbolInfo {
     pub type_: SymbolType,
     pub data_type: DataType,
-    pub defined: bool,
     pub segment: String,
     pub offset: Option<u64>,
     pub line_defined: usize,
+    /// Every use of the symbol, in source order.
+    pub references: Vec<Reference>,
+    /// Elements a variable declares: its DUP count, or 1.
+    pub length: u64,
+    /// Value of a constant; for `=` symbols, the latest assignment seen.
+    pub value: Option<ConstantValue>,
 }
 
-const ALLOWED_INSTRUCTIONS: &[&str] = &[
-    "CMC", "CMPSB", "NOP", "POPA", "AAD", "AAM", "MUL", "INC", "IDIV", "INT", "AND", "LEA", "OR",
-    "XOR", "JNAE", "JNE", "JNLE", "LOOPE", "JA", "JC", // , "MOV", "ADD", "SUB", "DEC", "RET",
-];
+/// Segment kind ("STACK", "DATA" or "CODE") declared by a segment
+/// statement: `.DATA SEGMENT` or the simplified `.DATA`, which switches
+/// to the segment without closing the previous one.
+pub fn segment_kind(name: &str) -> Option<&'static str> {
+    let upper = name.to_uppercase();
+    match upper.strip_suffix(" SEGMENT").unwrap_or(&upper) {
+        ".STACK" => Some("STACK"),
+        ".DATA" => Some("DATA"),
+        ".CODE" => Some("CODE"),
+        _ => None,
+    }
+}
+
+/// Segment named by an operand such as `@data` or `DATA`.
+pub fn segment_reference(name: &str) -> Option<&'static str> {
+    let upper = name.to_uppercase();
+    match upper.strip_prefix('@').unwrap_or(&upper) {
+        "STACK" => Some("STACK"),
+        "DATA" => Some("DATA"),
+        "CODE" => Some("CODE"),
+        _ => None,
+    }
+}
 
-const JUMP_INSTRUCTIONS: &[&str] = &["JNAE", "JNE", "JNLE", "LOOPE", "JA", "JC"];
+/// Looks `name` up as a symbol, falling back to the segment it names.
+pub fn lookup_symbol<'a>(
+    name: &str,
+    symbol_table: &'a HashMap<String, SymbolInfo>,
+) -> Option<&'a SymbolInfo> {
+    symbol_table
+        .get(name)
+        .or_else(|| symbol_table.get(segment_reference(name)?))
+}
 
-pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInfo>) {
+/// Checks the program and builds its symbol table, honouring layout options.
+/// In single-segment (.COM) mode data may be declared inside the code segment.
+pub fn validate_with_options(
+    ast: &Program,
+    options: &AssemblerOptions,
+) -> (Vec<CompilerError>, HashMap<String, SymbolInfo>) {
+    let data_allowed =
+        |segment: &str| segment == "DATA" || (options.single_segment && segment == "CODE");
     let mut errors = Vec::new();
     let mut symbol_table: HashMap<String, SymbolInfo> = HashMap::new();
 
     let mut current_segment = "NONE".to_string();
+    // Names defined with `=`
+    let mut assignable: HashSet<String> = HashSet::new();
 
     // PASS 1: Symbol Collection & Segment Tracking
     for (line_idx, spanned) in ast.iter().enumerate() {
@@ -53,12 +127,19 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
         if let LineNode::Statement(stmt) = &spanned.node {
             match stmt {
                 Statement::Segment { name } => {
-                    if name.to_uppercase().contains("STACK") {
-                        current_segment = "STACK".to_string();
-                    } else if name.to_uppercase().contains("DATA") {
-                        current_segment = "DATA".to_string();
-                    } else if name.to_uppercase().contains("CODE") {
-                        current_segment = "CODE".to_string();
+                    if let Some(kind) = segment_kind(name) {
+                        current_segment = kind.to_string();
+                        // Opened again: the segment goes on where it left off
+                        symbol_table.entry(kind.to_string()).or_insert(SymbolInfo {
+                            type_: SymbolType::Segment,
+                            data_type: DataType::Word,
+                            segment: kind.to_string(),
+                            offset: None,
+                            line_defined: line_num,
+                            references: Vec::new(),
+                            length: 1,
+                            value: None,
+                        });
                     }
                 }
                 Statement::SegmentEnd => {
@@ -67,52 +148,98 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
                 Statement::End { .. } => {}
                 Statement::Variable {
                     name, directive, ..
+                } if data_allowed(&current_segment) => {
+                    let dir = directive.to_uppercase();
+                    let dtype = if dir == "DB" {
+                        DataType::Byte
+                    } else {
+                        DataType::Word
+                    };
+                    symbol_table.insert(
+                        name.clone(),
+                        SymbolInfo {
+                            type_: SymbolType::Variable,
+                            data_type: dtype,
+                            segment: current_segment.clone(),
+                            offset: None,
+                            line_defined: line_num,
+                            references: Vec::new(),
+                            length: 1,
+                            value: None,
+                        },
+                    );
+                }
+                Statement::Label(name) if current_segment == "CODE" => {
+                    symbol_table.insert(
+                        name.clone(),
+                        SymbolInfo {
+                            type_: SymbolType::Label,
+                            data_type: DataType::None,
+                            segment: "CODE".to_string(),
+                            offset: None,
+                            line_defined: line_num,
+                            references: Vec::new(),
+                            length: 1,
+                            value: None,
+                        },
+                    );
+                }
+                Statement::Procedure { name, distance } if current_segment == "CODE" => {
+                    symbol_table.insert(
+                        name.clone(),
+                        SymbolInfo {
+                            type_: SymbolType::Procedure(*distance),
+                            data_type: DataType::None,
+                            segment: "CODE".to_string(),
+                            offset: None,
+                            line_defined: line_num,
+                            references: Vec::new(),
+                            length: 1,
+                            value: None,
+                        },
+                    );
+                }
+                Statement::Constant {
+                    name,
+                    value,
+                    redefinable,
                 } => {
-                    if current_segment == "DATA" {
-                        let dir = directive.to_uppercase();
-                        let dtype = if dir == "DB" {
-                            DataType::Byte
-                        } else {
-                            DataType::Word
-                        };
-                        symbol_table.insert(
-                            name.clone(),
-                            SymbolInfo {
-                                type_: SymbolType::Variable,
-                                data_type: dtype,
-                                defined: true,
-                                segment: "DATA".to_string(),
-                                offset: None,
-                                line_defined: line_num,
-                            },
-                        );
+                    // Only `=` may assign a name that `=` defined before
+                    if symbol_table.contains_key(name)
+                        && !(*redefinable && assignable.contains(name))
+                    {
+                        errors.push(CompilerError {
+                            message: format!("Símbolo '{}' redefinido", name),
+                            line: line_num,
+                            is_correct: false,
+                        });
+                        continue;
                     }
-                }
-                Statement::Label(name) => {
-                    if current_segment == "CODE" {
-                        symbol_table.insert(
-                            name.clone(),
-                            SymbolInfo {
-                                type_: SymbolType::Label,
-                                data_type: DataType::None,
-                                defined: true,
-                                segment: "CODE".to_string(),
-                                offset: None,
-                                line_defined: line_num,
-                            },
-                        );
+                    if *redefinable {
+                        assignable.insert(name.clone());
                     }
-                }
-                Statement::Constant { name, .. } => {
+                    // Forward references are retried in pass 2
+                    let here = Address::here(0, &current_segment);
+                    let value = constant_value(value, &symbol_table, &here).ok();
+                    let offset = match &value {
+                        Some(ConstantValue::Number(number)) => Some(*number as u16 as u64),
+                        Some(ConstantValue::Address(address)) => Some(address.offset as u16 as u64),
+                        _ => None,
+                    };
+                    let line_defined = symbol_table
+                        .get(name)
+                        .map_or(line_num, |sym| sym.line_defined);
                     symbol_table.insert(
                         name.clone(),
                         SymbolInfo {
                             type_: SymbolType::Constant,
                             data_type: DataType::Word,
-                            defined: true,
                             segment: current_segment.clone(),
-                            offset: None,
-                            line_defined: line_num,
+                            offset,
+                            line_defined,
+                            references: Vec::new(),
+                            length: 1,
+                            value,
                         },
                     );
                 }
@@ -123,6 +250,14 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
 
     // PASS 2: Detailed Validation
     current_segment = "NONE".to_string(); // Reset
+    let mut references: Vec<(String, Reference)> = Vec::new();
+    // PROC waiting for its ENDP: name, distance and line
+    let mut procedure: Option<(&String, Distance, usize)> = None;
+    let unclosed = |name: &String, line: usize| CompilerError {
+        message: format!("Procedimiento '{}' sin cerrar: falta '{} ENDP'", name, name),
+        line,
+        is_correct: false,
+    };
 
     for (line_idx, spanned) in ast.iter().enumerate() {
         let line_num = line_idx + 1;
@@ -130,51 +265,172 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
         if let LineNode::Statement(stmt) = &spanned.node {
             match stmt {
                 Statement::Segment { name } => {
-                    let upper = name.to_uppercase();
-                    if upper.contains("STACK") {
-                        current_segment = "STACK".to_string();
-                    } else if upper.contains("DATA") {
-                        current_segment = "DATA".to_string();
-                    } else if upper.contains("CODE") {
-                        current_segment = "CODE".to_string();
+                    if let Some(kind) = segment_kind(name) {
+                        current_segment = kind.to_string();
+                        // Switching segments ends the procedure as ENDS would
+                        if let Some((name, _, line)) = procedure.take() {
+                            errors.push(unclosed(name, line));
+                        }
                     }
                 }
                 Statement::SegmentEnd => {
                     current_segment = "NONE".to_string();
+                    if let Some((name, _, line)) = procedure.take() {
+                        errors.push(unclosed(name, line));
+                    }
+                }
+                Statement::Procedure { name, distance } => {
+                    if current_segment != "CODE" {
+                        errors.push(CompilerError {
+                            message: "Procedimientos no permitidos fuera del segmento de código"
+                                .to_string(),
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
+                    // Procedures do not nest: the open one is missing its ENDP
+                    if let Some((open, _, line)) = procedure.replace((name, *distance, line_num)) {
+                        errors.push(unclosed(open, line));
+                    }
+                }
+                Statement::ProcedureEnd { name } => match procedure.take() {
+                    Some((open, _, _)) if open == name => {}
+                    Some((open, _, line)) => {
+                        errors.push(CompilerError {
+                            message: format!(
+                                "'{} ENDP' no cierra el procedimiento abierto '{}'",
+                                name, open
+                            ),
+                            line: line_num,
+                            is_correct: false,
+                        });
+                        errors.push(unclosed(open, line));
+                    }
+                    None => errors.push(CompilerError {
+                        message: format!("'{} ENDP' sin PROC correspondiente", name),
+                        line: line_num,
+                        is_correct: false,
+                    }),
+                },
+                Statement::Constant { name, value, .. } => {
+                    if let Some(msg) = check_hex_constant(value) {
+                        errors.push(CompilerError {
+                            message: msg,
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
+                    // Later lines see this assignment until the next one
+                    let here = Address::here(0, &current_segment);
+                    if let Err(msg) = assign_constant(name, value, &mut symbol_table, &here) {
+                        errors.push(CompilerError {
+                            message: format!("Valor no válido para '{}': {}", name, msg),
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    } else if let Some(ConstantValue::Text(Operand::Label(target))) =
+                        symbol_table.get(name).and_then(|sym| sym.value.as_ref())
+                        && symbol_table
+                            .get(target)
+                            .is_some_and(|sym| matches!(sym.type_, SymbolType::Constant))
+                    {
+                        errors.push(CompilerError {
+                            message: format!("Definición circular de '{}'", name),
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
+                }
+                Statement::Directive { name, args } if name == "ORG" => {
+                    let here = Address::here(0, &current_segment);
+                    let message = match args.as_slice() {
+                        [arg] => match expression::operand_value(arg, &symbol_table, &here) {
+                            Ok(Value::Frame(_)) => {
+                                Some("ORG requiere un desplazamiento, no un segmento".to_string())
+                            }
+                            Ok(_) => None,
+                            Err(msg) => Some(msg),
+                        },
+                        _ => Some("ORG requiere un único valor".to_string()),
+                    };
+                    if let Some(msg) = message {
+                        errors.push(CompilerError {
+                            message: msg,
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
+                }
+                Statement::Directive { name, args } if name == "ALIGN" || name == "EVEN" => {
+                    let here = Address::here(0, &current_segment);
+                    if alignment(name, args, &symbol_table, &here).is_none() {
+                        errors.push(CompilerError {
+                            message: format!(
+                                "'{}' requiere una potencia de 2 (1, 2, 4, 8, 16)",
+                                name
+                            ),
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
+                }
+                Statement::End { label: Some(label) } => {
+                    references.push((
+                        label.clone(),
+                        Reference {
+                            line: line_num,
+                            access: Access::Read,
+                        },
+                    ));
                 }
-                Statement::End { .. } => {}
 
                 // --- VARIABLE DECLARATION VALIDATION ---
                 Statement::Variable {
-                    name: _,
+                    name,
                     directive,
                     value,
                 } => {
                     // Check Hex
-                    let mut op_to_check = value;
-                    if let Operand::Dup { value: inner, .. } = value {
-                        op_to_check = inner;
+                    let op_to_check = match value {
+                        Operand::Dup { value: inner, .. } => inner,
+                        _ => value,
+                    };
+                    if let Some(msg) = check_hex_constant(op_to_check) {
+                        errors.push(CompilerError {
+                            message: msg,
+                            line: line_num,
+                            is_correct: false,
+                        });
                     }
-                    if let Operand::Immediate(_, raw) = op_to_check {
-                        if raw.to_lowercase().ends_with('h') {
-                            if let Some(first) = raw.chars().next() {
-                                if !first.is_ascii_digit() {
-                                    errors.push(CompilerError {
-                                        message: format!(
-                                            "Constante Hex inválida '{}' (falta 0 inicial)",
-                                            raw
-                                        ),
-                                        line: line_num,
-                                        is_correct: false,
-                                    });
-                                }
-                            }
-                        }
+
+                    let here = Address::here(0, &current_segment);
+                    if let Some(msg) = check_data_value(directive, value, &symbol_table, &here) {
+                        errors.push(CompilerError {
+                            message: msg,
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
+                    if let Operand::Dup { count, .. } = value
+                        && let Ok(count) = evaluate_absolute(count, &symbol_table, &here)
+                        && let Some(sym) = symbol_table.get_mut(name)
+                    {
+                        sym.length = count.max(0) as u64;
+                    }
+
+                    for name in operand_symbols(value) {
+                        references.push((
+                            name.clone(),
+                            Reference {
+                                line: line_num,
+                                access: Access::Read,
+                            },
+                        ));
                     }
 
                     let dir = directive.to_uppercase();
 
-                    if current_segment == "CODE" {
+                    if current_segment == "CODE" && !data_allowed(&current_segment) {
                         errors.push(CompilerError {
                             message: "Declaración de datos no permitida en segmento de código."
                                 .to_string(),
@@ -189,18 +445,19 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
                                 is_correct: false,
                             });
                         }
-                    } else if current_segment == "DATA" {
-                        if let Operand::Label(s) = value {
-                            if dir == "DB" {
-                                errors.push(CompilerError {
-                                    message: format!(
-                                        "Texto sin comillas. Use: {} {} 'texto'",
-                                        "variable", dir
-                                    ),
-                                    line: line_num,
-                                    is_correct: false,
-                                });
-                            }
+                    } else if data_allowed(&current_segment) {
+                        if let Operand::Label(name) = value
+                            && dir == "DB"
+                            && !is_constant(name, &symbol_table)
+                        {
+                            errors.push(CompilerError {
+                                message: format!(
+                                    "Texto sin comillas. Use: {} {} 'texto'",
+                                    "variable", dir
+                                ),
+                                line: line_num,
+                                is_correct: false,
+                            });
                         }
                     } else {
                         errors.push(CompilerError {
@@ -212,35 +469,52 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
                 }
 
                 Statement::Data { directive, value } => {
+                    for name in operand_symbols(value) {
+                        references.push((
+                            name.clone(),
+                            Reference {
+                                line: line_num,
+                                access: Access::Read,
+                            },
+                        ));
+                    }
+                    let here = Address::here(0, &current_segment);
+                    if let Some(msg) = check_data_value(directive, value, &symbol_table, &here) {
+                        errors.push(CompilerError {
+                            message: msg,
+                            line: line_num,
+                            is_correct: false,
+                        });
+                    }
                     let dir = directive.to_uppercase();
-                    if current_segment == "CODE" {
+                    if current_segment == "CODE" && !data_allowed(&current_segment) {
                         errors.push(CompilerError {
                             message: format!("'{}' no permitido en segmento de código.", dir),
                             line: line_num,
                             is_correct: false,
                         });
-                    } else if current_segment == "STACK" {
-                        if dir != "DW" {
-                            errors.push(CompilerError {
-                                message: format!("'{}' no permitido en segmento de pila.", dir),
-                                line: line_num,
-                                is_correct: false,
-                            });
-                        }
+                    } else if current_segment == "STACK" && dir != "DW" {
+                        errors.push(CompilerError {
+                            message: format!("'{}' no permitido en segmento de pila.", dir),
+                            line: line_num,
+                            is_correct: false,
+                        });
                     }
-                    if let Operand::Label(_) = value {
-                        if dir == "DB" {
-                            errors.push(CompilerError {
-                                message: "Texto sin comillas. Use: DB 'texto'".to_string(),
-                                line: line_num,
-                                is_correct: false,
-                            });
-                        }
+                    if let Operand::Label(name) = value
+                        && dir == "DB"
+                        && !is_constant(name, &symbol_table)
+                    {
+                        errors.push(CompilerError {
+                            message: "Texto sin comillas. Use: DB 'texto'".to_string(),
+                            line: line_num,
+                            is_correct: false,
+                        });
                     }
                 }
 
                 Statement::Instruction { mnemonic, operands } => {
                     let mnem = mnemonic.to_uppercase();
+                    record_references(&mnem, operands, line_num, &mut references);
 
                     if current_segment != "CODE" {
                         errors.push(CompilerError {
@@ -259,105 +533,279 @@ pub fn validate(ast: &Program) -> (Vec<CompilerError>, HashMap<String, SymbolInf
                             is_correct: false,
                         });
                     } else {
-                        if !ALLOWED_INSTRUCTIONS.contains(&mnem.as_str()) {
-                            errors.push(CompilerError {
-                                message: format!("'{}' no es una instrucción válida", mnem),
-                                line: line_num,
-                                is_correct: false,
-                            });
-                        } else {
-                            if JUMP_INSTRUCTIONS.contains(&mnem.as_str()) {
-                                if let Some(Operand::Label(lbl)) = operands.first() {
-                                    if !symbol_table.contains_key(lbl) {
-                                        errors.push(CompilerError {
-                                            message: format!(
-                                                "Etiqueta '{}' no definida previamente",
-                                                lbl
-                                            ),
-                                            line: line_num,
-                                            is_correct: false,
-                                        });
-                                    }
-                                }
-                            }
-
-                            for op in operands {
-                                if let Operand::Immediate(_, raw) = op {
-                                    if raw.to_lowercase().ends_with('h') {
-                                        if let Some(first) = raw.chars().next() {
-                                            if !first.is_ascii_digit() {
-                                                errors.push(CompilerError {
-                                                     message: format!("Constante Hex inválida '{}' (falta 0 inicial)", raw),
-                                                     line: line_num,
-                                                     is_correct: false,
-                                                 });
-                                            }
-                                        }
-                                    }
-                                }
-                                if let Operand::Label(name) = op {
-                                    if !JUMP_INSTRUCTIONS.contains(&mnem.as_str()) {
-                                        if !symbol_table.contains_key(name) {
-                                            errors.push(CompilerError {
-                                                message: format!(
-                                                    "Elemento no identificado: '{}'",
-                                                    name
-                                                ),
-                                                line: line_num,
-                                                is_correct: false,
-                                            });
-                                        }
-                                    }
-                                }
-                                if let Operand::Memory { base, .. } = op {
-                                    if !symbol_table.contains_key(base) && !is_register(base) {}
-                                }
-                            }
-                        }
+                        validate_instruction(&mnem, operands, &symbol_table, line_num, &mut errors);
                     }
-                }
 
-                Statement::Label(_) => {
-                    if current_segment == "DATA" {
+                    // RET takes the procedure's distance; RETN/RETF force one
+                    if let Some((name, distance, _)) = procedure
+                        && matches!(
+                            (mnem.as_str(), distance),
+                            ("RETF", Distance::Near) | ("RETN", Distance::Far)
+                        )
+                    {
                         errors.push(CompilerError {
-                            message: "Etiquetas de código no permitidas en segmento de datos"
-                                .to_string(),
+                            message: format!(
+                                "'{}' dentro del procedimiento {} '{}'. Use RET",
+                                mnem, distance, name
+                            ),
                             line: line_num,
                             is_correct: false,
                         });
                     }
                 }
 
+                Statement::Label(_) if current_segment == "DATA" => {
+                    errors.push(CompilerError {
+                        message: "Etiquetas de código no permitidas en segmento de datos"
+                            .to_string(),
+                        line: line_num,
+                        is_correct: false,
+                    });
+                }
+
                 _ => {}
             }
         }
     }
+    if let Some((name, _, line)) = procedure {
+        errors.push(unclosed(name, line));
+    }
+
+    for (name, reference) in references {
+        let key = if symbol_table.contains_key(&name) {
+            Some(name.as_str())
+        } else {
+            segment_reference(&name)
+        };
+        if let Some(sym) = key.and_then(|k| symbol_table.get_mut(k)) {
+            sym.references.push(reference);
+        }
+    }
 
     (errors, symbol_table)
 }
 
-fn is_register(s: &str) -> bool {
-    let r = s.to_uppercase();
-    matches!(
-        r.as_str(),
-        "AX" | "BX"
-            | "CX"
-            | "DX"
-            | "SI"
-            | "DI"
-            | "SP"
-            | "BP"
-            | "AL"
-            | "AH"
-            | "BL"
-            | "BH"
-            | "CL"
-            | "CH"
-            | "DL"
-            | "DH"
-            | "CS"
-            | "DS"
-            | "SS"
-            | "ES"
-    )
+fn is_constant(name: &str, symbol_table: &HashMap<String, SymbolInfo>) -> bool {
+    symbol_table
+        .get(name)
+        .is_some_and(|sym| matches!(sym.type_, SymbolType::Constant))
+}
+
+/// Symbols named by an operand, looking through `PTR`, `DUP` and expressions.
+fn operand_symbols(op: &Operand) -> Vec<&String> {
+    match op {
+        Operand::Label(name) => vec![name],
+        Operand::Memory {
+            displacement: Some(expr),
+            ..
+        }
+        | Operand::Expression(expr) => expression::symbols(expr),
+        Operand::Ptr { target, .. } => operand_symbols(target),
+        Operand::Dup { count, value } => {
+            let mut names = expression::symbols(count);
+            names.extend(operand_symbols(value));
+            names
+        }
+        _ => vec![],
+    }
+}
+
+/// Errors in the expressions of a data value: invalid operations, DUP
+/// counts that are not plain numbers, addresses stored in a byte.
+fn check_data_value(
+    directive: &str,
+    value: &Operand,
+    symbol_table: &HashMap<String, SymbolInfo>,
+    here: &Address,
+) -> Option<String> {
+    match value {
+        Operand::Dup { count, value } => match evaluate_absolute(count, symbol_table, here) {
+            Ok(count) if count < 0 => Some(format!("Cantidad de DUP negativa: {}", count)),
+            Ok(_) => check_data_value(directive, value, symbol_table, here),
+            Err(msg) => Some(format!("Cantidad de DUP no válida: {}", msg)),
+        },
+        Operand::Expression(expr) => match evaluate(expr, symbol_table, here) {
+            Err(msg) => Some(msg),
+            Ok(Value::Relocatable(_) | Value::Frame(_)) if directive.eq_ignore_ascii_case("DB") => {
+                Some("Una dirección no cabe en un byte. Use DW".to_string())
+            }
+            Ok(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Instructions whose first operand is only read.
+fn reads_destination(mnem: &str) -> bool {
+    isa::is_branch(mnem)
+        || matches!(
+            mnem,
+            "CMP" | "TEST" | "PUSH" | "OUT" | "INT" | "MUL" | "IMUL" | "DIV" | "IDIV" | "ESC"
+        )
+}
+
+fn record_references(
+    mnem: &str,
+    operands: &[Operand],
+    line_num: usize,
+    references: &mut Vec<(String, Reference)>,
+) {
+    let main = mnem.split_whitespace().last().unwrap_or_default();
+    for (position, op) in operands.iter().enumerate() {
+        // XCHG writes both operands
+        let written = (position == 0 && !reads_destination(main)) || main == "XCHG";
+        for name in operand_symbols(op) {
+            references.push((
+                name.clone(),
+                Reference {
+                    line: line_num,
+                    access: if written { Access::Write } else { Access::Read },
+                },
+            ));
+        }
+    }
+}
+
+fn validate_instruction(
+    mnem: &str,
+    operands: &[Operand],
+    symbol_table: &HashMap<String, SymbolInfo>,
+    line_num: usize,
+    errors: &mut Vec<CompilerError>,
+) {
+    let mut push = |message: String| {
+        errors.push(CompilerError {
+            message,
+            line: line_num,
+            is_correct: false,
+        })
+    };
+
+    // "REP MOVSB": the prefix and the instruction must both exist
+    let words: Vec<&str> = mnem.split_whitespace().collect();
+    if let Some(unknown) = words.iter().find(|w| !isa::is_mnemonic(w)) {
+        push(format!("'{}' no es una instrucción válida", unknown));
+        return;
+    }
+    let main = words.last().copied().unwrap_or_default();
+
+    if isa::is_branch(main)
+        && let Some(Operand::Label(lbl)) = operands.first()
+        && !symbol_table.contains_key(lbl)
+    {
+        push(format!("Etiqueta '{}' no definida previamente", lbl));
+    }
+
+    for op in operands {
+        let op = match op {
+            Operand::Ptr { target, .. } => target,
+            _ => op,
+        };
+        if let Some(msg) = check_hex_constant(op) {
+            push(msg);
+        }
+        if let Operand::Label(name) = op
+            && !isa::is_branch(main)
+            && lookup_symbol(name, symbol_table).is_none()
+        {
+            push(format!("Elemento no identificado: '{}'", name));
+        }
+        if let Operand::Memory { base, index, .. } = op
+            && let Some(msg) = check_address_registers(base, index)
+        {
+            push(msg);
+        }
+        if let Operand::Memory {
+            displacement: Some(expr),
+            ..
+        }
+        | Operand::Expression(expr) = op
+        {
+            check_operand_expression(expr, op, symbol_table, &mut push);
+        }
+    }
+
+    // Only check the operand combination once every operand resolved cleanly
+    if let (Some(def), Some(classes)) =
+        (isa::lookup(main), classify_operands(operands, symbol_table))
+        && !def.forms.iter().any(|f| f.matches(&classes).is_some())
+    {
+        let unsized_memory = classes
+            .iter()
+            .any(|c| matches!(c, isa::OperandClass::Memory { width: None, .. }))
+            && !classes
+                .iter()
+                .any(|c| matches!(c, isa::OperandClass::Register { .. }));
+        if unsized_memory {
+            push(format!(
+                "Tamaño de operando ambiguo en '{}'. Use BYTE PTR o WORD PTR",
+                main
+            ));
+        } else {
+            push(format!("Combinación de operandos inválida para '{}'", main));
+        }
+    }
+}
+
+/// Reports unknown names in an operand's expression, or else why it cannot
+/// be evaluated.
+fn check_operand_expression(
+    expr: &Expr,
+    op: &Operand,
+    symbol_table: &HashMap<String, SymbolInfo>,
+    push: &mut impl FnMut(String),
+) {
+    let unknown: Vec<&String> = expression::symbols(expr)
+        .into_iter()
+        .filter(|name| lookup_symbol(name, symbol_table).is_none())
+        .collect();
+    for name in &unknown {
+        push(format!("Elemento no identificado: '{}'", name));
+    }
+    if !unknown.is_empty() {
+        return;
+    }
+    // Instructions only live in the code segment
+    match evaluate(expr, symbol_table, &Address::here(0, "CODE")) {
+        Err(msg) => push(msg),
+        Ok(Value::Frame(_)) if matches!(op, Operand::Memory { .. }) => {
+            push("Un segmento no puede usarse como desplazamiento".to_string())
+        }
+        Ok(_) => {}
+    }
+}
+
+fn check_hex_constant(op: &Operand) -> Option<String> {
+    match op {
+        Operand::Immediate(_, raw)
+            if raw.to_lowercase().ends_with('h')
+                && raw.chars().next().is_some_and(|c| !c.is_ascii_digit()) =>
+        {
+            Some(format!(
+                "Constante Hex inválida '{}' (falta 0 inicial)",
+                raw
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Only BX/BP may act as base and SI/DI as index in 8086 addressing.
+fn check_address_registers(base: &Option<String>, index: &Option<String>) -> Option<String> {
+    for reg in base.iter().chain(index.iter()) {
+        if !matches!(reg.as_str(), "BX" | "BP" | "SI" | "DI") {
+            return Some(format!(
+                "Registro '{}' no válido para direccionamiento",
+                reg
+            ));
+        }
+    }
+    match (base.as_deref(), index.as_deref()) {
+        (Some(b @ ("SI" | "DI")), Some(_)) | (Some(b), Some("BX" | "BP")) => Some(format!(
+            "Combinación de registros inválida: [{}+{}]",
+            b,
+            index.as_deref().unwrap_or_default()
+        )),
+        _ => None,
+    }
 }
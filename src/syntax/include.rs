@@ -0,0 +1,257 @@
+// src/syntax/include.rs
+//! Files read through `INCLUDE` / `%include`.
+//!
+//! Every file of a program gets its own range of offsets, one after the
+//! other, starting with the main file at 0. A span therefore says which
+//! file it points into, and [`SourceFiles`] turns it back into a file name
+//! and line. Spans into the main file are plain offsets into its text, as
+//! they were before includes existed.
+//!
+//! Where the text comes from is up to a [`FileProvider`]: the command line
+//! reads the disk, the browser hands over an in-memory map.
+
+use chumsky::span::SimpleSpan;
+use std::collections::HashMap;
+use std::fs;
+
+/// Index of a file in [`SourceFiles`].
+pub type FileId = usize;
+
+/// The file being assembled.
+pub const MAIN_FILE: FileId = 0;
+
+/// Reads the text of an include file, or `None` if there is no such file.
+pub trait FileProvider {
+    fn read(&self, path: &str) -> Option<String>;
+}
+
+/// Files on disk, relative to the working directory. Only the command line
+/// reads them.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiskFiles;
+
+impl FileProvider for DiskFiles {
+    fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(path).ok()
+    }
+}
+
+/// Files handed over by name, e.g. by the browser.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFiles {
+    files: HashMap<String, String>,
+}
+
+impl MemoryFiles {
+    pub fn insert(&mut self, path: &str, text: &str) {
+        self.files.insert(normalize(path), text.to_string());
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+    }
+}
+
+impl FileProvider for MemoryFiles {
+    fn read(&self, path: &str) -> Option<String> {
+        self.files.get(&normalize(path)).cloned()
+    }
+}
+
+/// How include paths are resolved.
+pub struct Includes<'a> {
+    pub provider: &'a dyn FileProvider,
+    /// Path of the main file, whose directory is searched after the
+    /// including file's own.
+    pub main_path: String,
+    /// Directories tried, in order, after the including file's and the
+    /// main file's.
+    pub search_paths: Vec<String>,
+}
+
+impl<'a> Includes<'a> {
+    pub fn new(provider: &'a dyn FileProvider, main_path: &str) -> Self {
+        Includes {
+            provider,
+            main_path: normalize(main_path),
+            search_paths: Vec::new(),
+        }
+    }
+
+    /// Finds `path` as included from the file named `from`: next to that
+    /// file first, then next to the main file, then in each search path.
+    /// Returns the name it was found under and its text.
+    pub fn resolve(&self, path: &str, from: &str) -> Option<(String, String)> {
+        let path = normalize(path);
+        let candidates = if is_absolute(&path) {
+            vec![path]
+        } else {
+            let mut candidates: Vec<String> = Vec::new();
+            let directories = [directory(from), directory(&self.main_path)];
+            for dir in directories
+                .into_iter()
+                .chain(self.search_paths.iter().map(|dir| normalize(dir)))
+            {
+                let name = join(&dir, &path);
+                if !candidates.contains(&name) {
+                    candidates.push(name);
+                }
+            }
+            candidates
+        };
+        candidates
+            .into_iter()
+            .find_map(|name| self.provider.read(&name).map(|text| (name, text)))
+    }
+}
+
+/// Forward slashes, no `./` steps, `dir/../` folded.
+fn normalize(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "." => {}
+            ".." if parts.last().is_some_and(|p| !p.is_empty() && *p != "..") => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || path.as_bytes().get(1) == Some(&b':')
+}
+
+/// `lib/io.inc` -> `lib`; `main.asm` -> empty.
+fn directory(path: &str) -> String {
+    path.rsplit_once('/')
+        .map(|(dir, _)| dir.to_string())
+        .unwrap_or_default()
+}
+
+fn join(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        path.to_string()
+    } else {
+        normalize(&format!("{}/{}", dir, path))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// The path the file was found under.
+    pub name: String,
+    pub text: String,
+    /// Offset of the file's first byte in the program's span space.
+    pub base: usize,
+    /// The `INCLUDE` line that brought the file in; `None` for the main file.
+    pub included_at: Option<SimpleSpan>,
+}
+
+/// Every file of a program, in the order they were read.
+#[derive(Debug, Clone, Default)]
+pub struct SourceFiles {
+    pub files: Vec<SourceFile>,
+}
+
+impl SourceFiles {
+    /// Adds a file after the last one; its spans start at the returned base.
+    pub fn add(&mut self, name: &str, text: &str, included_at: Option<SimpleSpan>) -> FileId {
+        // One byte of gap, so the end of a file is not the start of the next
+        let base = self
+            .files
+            .last()
+            .map_or(0, |last| last.base + last.text.len() + 1);
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            text: text.to_string(),
+            base,
+            included_at,
+        });
+        self.files.len() - 1
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(file)
+    }
+
+    /// File name for messages.
+    pub fn name(&self, file: FileId) -> &str {
+        self.files.get(file).map_or("", |f| f.name.as_str())
+    }
+
+    /// File an offset points into.
+    pub fn file_at(&self, offset: usize) -> FileId {
+        self.files
+            .iter()
+            .rposition(|f| f.base <= offset)
+            .unwrap_or(MAIN_FILE)
+    }
+
+    /// Text a span covers.
+    pub fn text(&self, span: SimpleSpan) -> &str {
+        let Some(file) = self.files.get(self.file_at(span.start)) else {
+            return "";
+        };
+        let start = (span.start - file.base).min(file.text.len());
+        let end = span
+            .end
+            .saturating_sub(file.base)
+            .clamp(start, file.text.len());
+        file.text.get(start..end).unwrap_or_default()
+    }
+
+    /// File and 1-based line of an offset.
+    pub fn line_at(&self, offset: usize) -> (FileId, usize) {
+        let id = self.file_at(offset);
+        let line = self.files.get(id).map_or(1, |file| {
+            let local = offset.saturating_sub(file.base).min(file.text.len());
+            file.text.as_bytes()[..local]
+                .iter()
+                .filter(|b| **b == b'\n')
+                .count()
+                + 1
+        });
+        (id, line)
+    }
+
+    /// The whole line an offset is on, in its own file.
+    pub fn line_text(&self, offset: usize) -> &str {
+        let Some(file) = self.files.get(self.file_at(offset)) else {
+            return "";
+        };
+        let local = offset.saturating_sub(file.base).min(file.text.len());
+        let start = file.text[..local].rfind('\n').map_or(0, |i| i + 1);
+        let end = file.text[local..]
+            .find('\n')
+            .map_or(file.text.len(), |i| local + i);
+        &file.text[start..end]
+    }
+
+    /// The span itself if it is in the main file, otherwise the span of the
+    /// main-file `INCLUDE` line its file was reached through.
+    pub fn main_span(&self, span: SimpleSpan) -> SimpleSpan {
+        let mut span = span;
+        while let Some(included_at) = self
+            .files
+            .get(self.file_at(span.start))
+            .and_then(|f| f.included_at)
+        {
+            span = included_at;
+        }
+        span
+    }
+
+    /// `12` in the main file, `lib/io.inc:12` in any other.
+    pub fn place(&self, file: FileId, line: usize) -> String {
+        if file == MAIN_FILE {
+            line.to_string()
+        } else {
+            format!("{}:{}", self.name(file), line)
+        }
+    }
+}
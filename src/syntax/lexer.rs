@@ -12,6 +12,8 @@ fn validate_constants<'src>() -> impl Parser<'src, &'src str, Token, LexerError<
         text::digits(16)
             .to_slice()
             .then_ignore(just('h').or(just('H')))
+            // AH, BH, CH and DH are registers, not hex literals
+            .filter(|s: &&str| !matches!(s.to_ascii_uppercase().as_str(), "A" | "B" | "C" | "D"))
             .map(|s: &str| {
                 Token::Constant(constant::Type::NumberHex(
                     u64::from_str_radix(s, 16).unwrap_or(0),
@@ -53,7 +55,7 @@ fn validate_constants<'src>() -> impl Parser<'src, &'src str, Token, LexerError<
                 if closed {
                     Token::Constant(constant::Type::String(s))
                 } else {
-                    Token::Error(format!("String missing closing quote"))
+                    Token::Error("String missing closing quote".to_string())
                 }
             })
     };
@@ -68,7 +70,7 @@ fn validate_constants<'src>() -> impl Parser<'src, &'src str, Token, LexerError<
                 if closed {
                     Token::Constant(constant::Type::String(s))
                 } else {
-                    Token::Error(format!("Char literal missing closing quote"))
+                    Token::Error("Char literal missing closing quote".to_string())
                 }
             })
     };
@@ -104,11 +106,6 @@ fn validate_compounds<'src>() -> impl Parser<'src, &'src str, Token, LexerError<
             .to(Token::Pseudoinstruction(format!("{} {}", first, second)))
     };
 
-    let arrays = just('[')
-        .then(none_of("]\r\n").repeated().collect::<String>())
-        .then(just(']'))
-        .map(|((_, content), _)| Token::Symbol(format!("[{}]", content)));
-
     choice((
         mk_compound(".STACK", "SEGMENT"),
         mk_compound(".DATA", "SEGMENT"),
@@ -116,14 +113,37 @@ fn validate_compounds<'src>() -> impl Parser<'src, &'src str, Token, LexerError<
         mk_compound("BYTE", "PTR"),
         mk_compound("WORD", "PTR"),
         mk_compound("DWORD", "PTR"),
-        arrays,
     ))
 }
 
+/// `INCLUDE path` (MASM) or `%include "path"` (NASM). The path runs to
+/// the end of the line or a comment; quotes or `<>` around it are dropped.
+fn validate_include<'src>() -> impl Parser<'src, &'src str, Token, LexerError<'src>> {
+    let keyword = just('%')
+        .or_not()
+        .then(text::ascii::ident())
+        .to_slice()
+        .filter(|s: &&str| s.trim_start_matches('%').eq_ignore_ascii_case("INCLUDE"));
+    let path = none_of(";\r\n").repeated().to_slice().map(|s: &str| {
+        let s = s.trim();
+        let unquoted = ['"', '\'', '<']
+            .into_iter()
+            .zip(['"', '\'', '>'])
+            .find_map(|(open, close)| s.strip_prefix(open)?.strip_suffix(close));
+        unquoted.unwrap_or(s).trim().to_string()
+    });
+    keyword
+        .then_ignore(one_of(" \t").repeated().at_least(1))
+        .ignore_then(path)
+        .map(Token::Include)
+}
+
 // --- UPDATED IDENTIFIER PARSER ---
 fn validate_identifiers<'src>() -> impl Parser<'src, &'src str, Token, LexerError<'src>> {
     text::ascii::ident()
         .or(just('.').then(text::ascii::ident()).to_slice())
+        // @data / @code name a segment
+        .or(just('@').then(text::ascii::ident()).to_slice())
         .map(|s: &str| {
             let upper = s.to_uppercase();
 
@@ -158,12 +178,20 @@ fn validate_punctuation<'src>() -> impl Parser<'src, &'src str, Token, LexerErro
         just(')').to(Token::Punctuation(PunctuationType::RParen)),
         just('+').to(Token::Punctuation(PunctuationType::Plus)),
         just('-').to(Token::Punctuation(PunctuationType::Minus)),
+        just('*').to(Token::Punctuation(PunctuationType::Star)),
+        just('/').to(Token::Punctuation(PunctuationType::Slash)),
+        just('$').to(Token::Punctuation(PunctuationType::Dollar)),
         just('.').to(Token::Punctuation(PunctuationType::Dot)),
+        just('=').to(Token::Punctuation(PunctuationType::Equals)),
+        just('<').to(Token::Punctuation(PunctuationType::LAngle)),
+        just('>').to(Token::Punctuation(PunctuationType::RAngle)),
+        just('&').to(Token::Punctuation(PunctuationType::Ampersand)),
     ))
 }
 
 pub fn lexer<'src>() -> impl Parser<'src, &'src str, Vec<(Token, SimpleSpan)>, LexerError<'src>> {
     let token_type = choice((
+        validate_include(),
         validate_compounds(),
         validate_constants(),
         validate_identifiers(), // This now handles Instruction Logic internally
@@ -1,3 +1,5 @@
+pub mod include;
 pub mod lexer;
 pub mod parser;
+pub mod preprocessor;
 pub mod tokens;
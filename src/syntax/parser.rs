@@ -1,10 +1,10 @@
 // src/syntax/parser.rs
-use crate::ast::{LineNode, Operand, Program, Statement};
-use crate::syntax::tokens::{PunctuationType, Token, constant};
+use crate::ast::{BinaryOp, Distance, Expr, LineNode, Operand, Program, Statement, UnaryOp};
+use crate::syntax::tokens::{PunctuationType, Token, constant, pseudoinstruction};
 use chumsky::input::ValueInput;
 use chumsky::prelude::*;
 
-pub fn parser<'a, I>() -> impl Parser<'a, I, Program, extra::Err<Rich<'a, Token>>>
+pub fn parser<'a, I>() -> impl Parser<'a, I, Program, Extra<'a>>
 where
     I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
 {
@@ -13,64 +13,157 @@ where
         Token::Constant(constant::Type::NumberDecimal(v)) => Operand::Immediate(v, v.to_string()),
         Token::Constant(constant::Type::NumberHex(v, raw)) => Operand::Immediate(v, raw),
         Token::Constant(constant::Type::NumberBinary(v, raw)) => Operand::Immediate(v, raw),
-        Token::Constant(constant::Type::Char(c)) => Operand::Immediate(c as u64, format!("'{}'", c)),
         Token::Constant(constant::Type::String(s)) => Operand::StringLiteral(s),
     };
 
     let reg = select! { Token::Register(r) => Operand::Register(r) };
     let lbl = select! { Token::Symbol(s) => Operand::Label(s) };
 
-    // Update Bracket Matching to use PunctuationType
-    let mem_bracket = just(Token::Punctuation(PunctuationType::LBracket))
-        .ignore_then(select! { Token::Register(r) => r })
+    let (product, expr) = expression_levels();
+
+    // Operators that can follow a value, so a lone number or name is not
+    // mistaken for the whole operand
+    let binary_op = choice((sign(), product_op(), relation_op(), and_op(), or_op()));
+
+    // --- MEMORY OPERANDS ---
+    // Terms allowed between brackets: registers and displacement products
+    let addr_term = choice((
+        select! { Token::Register(r) => AddressTerm::Register(r) },
+        product.map(AddressTerm::Value),
+    ));
+
+    // [BX+SI+2] -> [(+, BX), (+, SI), (+, 2)]
+    let bracket = just(Token::Punctuation(PunctuationType::LBracket))
+        .ignore_then(sign().or_not().map(|s| s.unwrap_or(BinaryOp::Add)))
+        .then(addr_term.clone())
+        .then(sign().then(addr_term).repeated().collect::<Vec<_>>())
         .then_ignore(just(Token::Punctuation(PunctuationType::RBracket)))
-        .map(|r| Operand::Memory {
-            base: r,
-            offset: None,
+        .map(|(first, mut rest)| {
+            rest.insert(0, first);
+            rest
         });
 
-    let operand = choice((imm, reg, mem_bracket, lbl));
+    // [BX][SI] is the same as [BX+SI]
+    let brackets = bracket
+        .repeated()
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .map(|groups| groups.concat());
 
-    // --- DUP PATTERN ---
-    let dup_val = select! {
-        Token::Constant(constant::Type::NumberDecimal(v)) => v,
-        Token::Constant(constant::Type::NumberHex(v, _)) => v,
+    let seg_override = select! {
+        Token::Register(r) if matches!(r.as_str(), "CS" | "DS" | "SS" | "ES") => r
     }
-    .then_ignore(select! { Token::Pseudoinstruction(s) if s == "DUP" => s })
-    .then_ignore(just(Token::Punctuation(PunctuationType::LParen)))
-    .then(operand.clone())
-    .then_ignore(just(Token::Punctuation(PunctuationType::RParen)))
-    .map(|(count, val)| Operand::Dup {
-        count,
-        value: Box::new(val),
-    });
+    .then_ignore(just(Token::Punctuation(PunctuationType::Colon)));
+
+    let mem_body = choice((
+        // var[SI], var[BX+2]
+        select! { Token::Symbol(s) => s }
+            .then(brackets.clone())
+            .map(|(sym, mut terms)| {
+                terms.insert(0, (BinaryOp::Add, AddressTerm::Value(Expr::Symbol(sym))));
+                terms
+            }),
+        // [BX+SI+var]
+        brackets,
+    ));
+
+    let mem_indexed =
+        seg_override
+            .clone()
+            .or_not()
+            .then(mem_body)
+            .try_map(|(segment, terms), span| {
+                build_memory(segment, terms).map_err(|msg| Rich::custom(span, msg))
+            });
+
+    // ES:var, ES:var+2
+    let mem_direct = seg_override
+        .then(expr.clone())
+        .map(|(segment, displacement)| Operand::Memory {
+            segment: Some(segment),
+            base: None,
+            index: None,
+            displacement: Some(displacement),
+        });
+
+    let memory = choice((mem_indexed, mem_direct)).boxed();
+
+    // A number or name on its own keeps its source form; anything longer is
+    // an expression evaluated once the symbols are known
+    let single = choice((imm, lbl)).then_ignore(binary_op.not());
+    let expression = expr.clone().map(Operand::Expression);
+
+    // BYTE PTR [BX], WORD PTR var
+    let ptr = select! { Token::Pseudoinstruction(p) if p.ends_with(" PTR") => p }
+        .then(choice((memory.clone(), single.clone(), expression.clone())))
+        .map(|(p, target)| Operand::Ptr {
+            size: p.trim_end_matches(" PTR").to_string(),
+            target: Box::new(target),
+        });
+
+    let operand = choice((ptr, memory, reg, single, expression)).boxed();
+
+    // --- DUP PATTERN ---
+    let dup_val = expr
+        .clone()
+        .then_ignore(select! { Token::Pseudoinstruction(s) if s == "DUP" => s })
+        .then_ignore(just(Token::Punctuation(PunctuationType::LParen)))
+        .then(operand.clone())
+        .then_ignore(just(Token::Punctuation(PunctuationType::RParen)))
+        .map(|(count, val)| Operand::Dup {
+            count,
+            value: Box::new(val),
+        });
 
     let variable_value = choice((dup_val.clone(), operand.clone()));
 
     // --- STATEMENTS ---
 
-    // 1. Instruction
-    let instruction = select! {
+    // 1. Instruction (optionally behind a REP/LOCK prefix: "REP MOVSB")
+    let mnemonic = select! {
         Token::Instruction(_, op) => op,
         Token::Symbol(op) => op,
-    }
-    .then(
-        operand
-            .clone()
-            .separated_by(just(Token::Punctuation(PunctuationType::Comma)))
-            .collect(),
-    )
-    .map(|(op, ops)| Statement::Instruction {
-        mnemonic: op,
-        operands: ops,
-    });
+    };
+    let prefixed = select! { Token::Instruction(_, p) if crate::isa::is_prefix(&p) => p }
+        .then(select! { Token::Instruction(_, op) => op })
+        .map(|(p, op)| format!("{} {}", p, op));
+
+    let instruction = choice((prefixed, mnemonic))
+        .then(
+            operand
+                .clone()
+                .separated_by(just(Token::Punctuation(PunctuationType::Comma)))
+                .collect(),
+        )
+        .map(|(op, ops)| Statement::Instruction {
+            mnemonic: op,
+            operands: ops,
+        });
 
     // 2. Label
     let label = select! { Token::Symbol(name) => name }
         .then_ignore(just(Token::Punctuation(PunctuationType::Colon)))
         .map(Statement::Label);
 
-    // 3. Variable
+    // 3. Constant: NAME EQU value, NAME = value, NAME EQU <text>
+    let assignment = choice((
+        select! { Token::Pseudoinstruction(p) if p == "EQU" => false },
+        just(Token::Punctuation(PunctuationType::Equals)).to(true),
+    ));
+    let text = operand.clone().delimited_by(
+        just(Token::Punctuation(PunctuationType::LAngle)),
+        just(Token::Punctuation(PunctuationType::RAngle)),
+    );
+    let constant = select! { Token::Symbol(name) => name }
+        .then(assignment)
+        .then(choice((text.clone(), operand.clone())))
+        .map(|((name, redefinable), value)| Statement::Constant {
+            name,
+            value,
+            redefinable,
+        });
+
+    // 4. Variable
     let variable = select! { Token::Symbol(name) => name }
         .then(select! { Token::Pseudoinstruction(d) => d })
         .then(variable_value.clone())
@@ -80,7 +173,7 @@ where
             value: val,
         });
 
-    // 4. Data (Anonymous definition)
+    // 5. Data (Anonymous definition)
     // We split this into two explicit cases to ensure DUP is prioritized
 
     // Case A: DW 100 DUP(0)
@@ -102,7 +195,42 @@ where
     // Priority: Try DUP first, then standard
     let anonymous_data = choice((anonymous_dup, anonymous_std));
 
-    // 5. Segment
+    // 6. Location counter directives: ORG expr, ALIGN n, EVEN
+    let org = select! { Token::Pseudoinstruction(d) if d == "ORG" || d == "ALIGN" => d }
+        .then(operand.clone())
+        .map(|(name, value)| Statement::Directive {
+            name,
+            args: vec![value],
+        });
+    let even = select! { Token::Pseudoinstruction(d) if d == "EVEN" => d }.map(|name| {
+        Statement::Directive {
+            name,
+            args: Vec::new(),
+        }
+    });
+
+    // 6b. Conditional assembly. The preprocessor has already picked the
+    // lines to assemble; the directives stay as a record of the choice.
+    let conditional =
+        select! { Token::Pseudoinstruction(d) if pseudoinstruction::is_conditional(&d) => d }
+            .then(choice((text.clone(), operand.clone())).or_not())
+            .then_ignore(any().and_is(just(Token::Newline).not()).repeated())
+            .map(|(name, arg)| Statement::Directive {
+                name,
+                args: arg.into_iter().collect(),
+            });
+
+    // 6c. `.MODEL name`, already applied by the preprocessor; a language
+    // after the model is ignored
+    let model = select! { Token::Pseudoinstruction(d) if d == ".MODEL" => d }
+        .then(select! { Token::Symbol(model) => Operand::Label(model) }.or_not())
+        .then_ignore(any().and_is(just(Token::Newline).not()).repeated())
+        .map(|(name, model)| Statement::Directive {
+            name,
+            args: model.into_iter().collect(),
+        });
+
+    // 7. Segment
     let segment = select! { Token::Pseudoinstruction(d) => d }.map(|name| {
         if name.to_uppercase() == "ENDS" {
             Statement::SegmentEnd
@@ -111,14 +239,37 @@ where
         }
     });
 
-    // 6. End
+    // 8. End
     let end_stmt = select! { Token::Symbol(s) if s.eq_ignore_ascii_case("END") => s }
         .then(select! { Token::Symbol(l) => l }.or_not())
         .map(|(_, l)| Statement::End { label: l });
 
+    // 9. Procedures: NAME PROC [NEAR|FAR], NAME ENDP
+    let distance = select! {
+        Token::Pseudoinstruction(d) if d == "NEAR" => Distance::Near,
+        Token::Pseudoinstruction(d) if d == "FAR" => Distance::Far,
+    };
+    let procedure = select! { Token::Symbol(name) => name }
+        .then_ignore(select! { Token::Pseudoinstruction(p) if p == "PROC" => p })
+        .then(distance.or_not())
+        .map(|(name, distance)| Statement::Procedure {
+            name,
+            distance: distance.unwrap_or(Distance::Near),
+        });
+    let procedure_end = select! { Token::Symbol(name) => name }
+        .then_ignore(select! { Token::Pseudoinstruction(p) if p == "ENDP" => p })
+        .map(|name| Statement::ProcedureEnd { name });
+
     let statement = choice((
         label,
+        procedure,
+        procedure_end,
+        constant,
         variable,
+        org,
+        even,
+        conditional,
+        model,
         anonymous_data,
         segment,
         end_stmt,
@@ -156,3 +307,230 @@ where
 
     line.repeated().collect()
 }
+
+type Extra<'a> = extra::Err<Rich<'a, Token>>;
+
+/// An assembly-time expression on its own (REPT counts in the macro
+/// preprocessor).
+pub fn expression<'a, I>() -> impl Parser<'a, I, Expr, Extra<'a>> + Clone
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    expression_levels().1
+}
+
+fn sign<'a, I>() -> impl Parser<'a, I, BinaryOp, Extra<'a>> + Clone
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    choice((
+        just(Token::Punctuation(PunctuationType::Plus)).to(BinaryOp::Add),
+        just(Token::Punctuation(PunctuationType::Minus)).to(BinaryOp::Sub),
+    ))
+}
+
+fn product_op<'a, I>() -> impl Parser<'a, I, BinaryOp, Extra<'a>> + Clone
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    select! {
+        Token::Punctuation(PunctuationType::Star) => BinaryOp::Mul,
+        Token::Punctuation(PunctuationType::Slash) => BinaryOp::Div,
+        Token::Pseudoinstruction(p) if p == "MOD" => BinaryOp::Mod,
+        Token::Instruction(_, op) if op == "SHL" => BinaryOp::Shl,
+        Token::Instruction(_, op) if op == "SHR" => BinaryOp::Shr,
+    }
+}
+
+fn relation_op<'a, I>() -> impl Parser<'a, I, BinaryOp, Extra<'a>> + Clone
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    select! {
+        Token::Pseudoinstruction(p) if p == "EQ" => BinaryOp::Eq,
+        Token::Pseudoinstruction(p) if p == "NE" => BinaryOp::Ne,
+        Token::Pseudoinstruction(p) if p == "LT" => BinaryOp::Lt,
+        Token::Pseudoinstruction(p) if p == "LE" => BinaryOp::Le,
+        Token::Pseudoinstruction(p) if p == "GT" => BinaryOp::Gt,
+        Token::Pseudoinstruction(p) if p == "GE" => BinaryOp::Ge,
+    }
+}
+
+fn and_op<'a, I>() -> impl Parser<'a, I, BinaryOp, Extra<'a>> + Clone
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    select! { Token::Instruction(_, op) if op == "AND" => BinaryOp::And }
+}
+
+fn or_op<'a, I>() -> impl Parser<'a, I, BinaryOp, Extra<'a>> + Clone
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    select! {
+        Token::Instruction(_, op) if op == "OR" => BinaryOp::Or,
+        Token::Instruction(_, op) if op == "XOR" => BinaryOp::Xor,
+    }
+}
+
+/// The `* / MOD` level (the terms of a bracketed address) and the full
+/// expression.
+///
+/// Precedence, tightest first: prefixes (OFFSET, HIGH, -, ...),
+/// * / MOD SHL SHR, + -, EQ NE LT LE GT GE, NOT, AND, OR XOR.
+fn expression_levels<'a, I>() -> (
+    Boxed<'a, 'a, I, Expr, Extra<'a>>,
+    Boxed<'a, 'a, I, Expr, Extra<'a>>,
+)
+where
+    I: ValueInput<'a, Token = Token, Span = SimpleSpan>,
+{
+    let not_op = select! { Token::Instruction(_, op) if op == "NOT" => UnaryOp::Not };
+    let prefix_op = select! {
+        Token::Punctuation(PunctuationType::Minus) => UnaryOp::Negate,
+        Token::Pseudoinstruction(p) if p == "OFFSET" => UnaryOp::Offset,
+        Token::Pseudoinstruction(p) if p == "SEG" => UnaryOp::Seg,
+        Token::Pseudoinstruction(p) if p == "TYPE" => UnaryOp::Type,
+        Token::Pseudoinstruction(p) if p == "SIZE" => UnaryOp::Size,
+        Token::Pseudoinstruction(p) if p == "LENGTH" => UnaryOp::Length,
+        Token::Pseudoinstruction(p) if p == "HIGH" => UnaryOp::High,
+        Token::Pseudoinstruction(p) if p == "LOW" => UnaryOp::Low,
+    };
+
+    let binary = |left: Expr, (op, right): (BinaryOp, Expr)| Expr::Binary {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    };
+
+    let mut expr = Recursive::declare();
+    let expr_atom = choice((
+        select! {
+            Token::Constant(constant::Type::NumberDecimal(v)) => Expr::Number(v as i64),
+            Token::Constant(constant::Type::NumberHex(v, _)) => Expr::Number(v as i64),
+            Token::Constant(constant::Type::NumberBinary(v, _)) => Expr::Number(v as i64),
+            // 'a' and 'ab' are character constants
+            Token::Constant(constant::Type::String(s)) if (1..=2).contains(&s.len()) => {
+                Expr::Number(s.bytes().fold(0i64, |acc, b| (acc << 8) | b as i64))
+            },
+            Token::Symbol(s) => Expr::Symbol(s),
+            Token::Punctuation(PunctuationType::Dollar) => Expr::Here,
+        },
+        expr.clone().delimited_by(
+            just(Token::Punctuation(PunctuationType::LParen)),
+            just(Token::Punctuation(PunctuationType::RParen)),
+        ),
+    ))
+    .boxed();
+    // A leading `+` changes nothing
+    let expr_unary = choice((
+        prefix_op.map(Some),
+        just(Token::Punctuation(PunctuationType::Plus)).to(None),
+    ))
+    .repeated()
+    .foldr(expr_atom, |op, operand| match op {
+        Some(op) => Expr::Unary {
+            op,
+            operand: Box::new(operand),
+        },
+        None => operand,
+    })
+    .boxed();
+    let product = expr_unary
+        .clone()
+        .foldl(product_op().then(expr_unary).repeated(), binary)
+        .boxed();
+    let sum = product
+        .clone()
+        .foldl(sign().then(product.clone()).repeated(), binary)
+        .boxed();
+    let relation = sum
+        .clone()
+        .foldl(relation_op().then(sum).repeated(), binary)
+        .boxed();
+    let negation = not_op
+        .repeated()
+        .foldr(relation, |op, operand| Expr::Unary {
+            op,
+            operand: Box::new(operand),
+        })
+        .boxed();
+    let conjunction = negation
+        .clone()
+        .foldl(and_op().then(negation).repeated(), binary)
+        .boxed();
+    expr.define(
+        conjunction
+            .clone()
+            .foldl(or_op().then(conjunction).repeated(), binary),
+    );
+    (product, expr.boxed())
+}
+
+#[derive(Debug, Clone)]
+enum AddressTerm {
+    Register(String),
+    Value(Expr),
+}
+
+/// Folds the signed terms of a bracketed address into an `Operand::Memory`.
+/// Register legality (e.g. `[AX]`) is left to the validator.
+fn build_memory(
+    segment: Option<String>,
+    terms: Vec<(BinaryOp, AddressTerm)>,
+) -> Result<Operand, String> {
+    let mut base: Option<String> = None;
+    let mut index: Option<String> = None;
+    let mut displacement: Option<Expr> = None;
+
+    for (op, term) in terms {
+        match term {
+            AddressTerm::Register(r) => {
+                if op == BinaryOp::Sub {
+                    return Err(format!("El registro '{}' no se puede restar", r));
+                }
+                let slot = match r.as_str() {
+                    "SI" | "DI" if index.is_none() => &mut index,
+                    _ if base.is_none() => &mut base,
+                    _ if index.is_none() => &mut index,
+                    _ => return Err("Demasiados registros en el operando de memoria".to_string()),
+                };
+                *slot = Some(r);
+            }
+            // Plain numbers are summed right away: [BX+2-1] -> [BX+1]
+            AddressTerm::Value(Expr::Number(v)) => {
+                let v = if op == BinaryOp::Sub { -v } else { v };
+                displacement = Some(match displacement {
+                    None => Expr::Number(v),
+                    Some(Expr::Number(d)) => Expr::Number(d + v),
+                    Some(left) => Expr::Binary {
+                        op: BinaryOp::Add,
+                        left: Box::new(left),
+                        right: Box::new(Expr::Number(v)),
+                    },
+                });
+            }
+            AddressTerm::Value(value) => {
+                displacement = Some(match displacement {
+                    None if op == BinaryOp::Sub => Expr::Unary {
+                        op: UnaryOp::Negate,
+                        operand: Box::new(value),
+                    },
+                    None => value,
+                    Some(left) => Expr::Binary {
+                        op,
+                        left: Box::new(left),
+                        right: Box::new(value),
+                    },
+                });
+            }
+        }
+    }
+
+    Ok(Operand::Memory {
+        segment,
+        base,
+        index,
+        displacement,
+    })
+}
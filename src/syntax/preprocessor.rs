@@ -0,0 +1,1166 @@
+// src/syntax/preprocessor.rs
+//! Macro expansion, conditional assembly and includes, between the lexer
+//! and the parser.
+//!
+//! Works on the token stream line by line. Definitions, consumed directives
+//! and the branches an `IF` leaves out become blank lines; a macro call, a
+//! repeat block, an `INCLUDE`, `.STARTUP` or `.EXIT` is followed by the
+//! lines it expands to. [`LineOrigin`] records where each line came from.
+//!
+//! As in MASM, a file is read again each time it is included; guard it
+//! with `IFNDEF name` / `name EQU 1` / `ENDIF` to include it only once.
+//! A file that includes itself, directly or not, is an error.
+
+use crate::ast::{Distance, MemoryModel};
+use crate::semantics::expression::{Address, evaluate_absolute};
+use crate::semantics::validator::{ConstantValue, DataType, SymbolInfo, SymbolType};
+use crate::syntax::include::{FileId, Includes, MAIN_FILE, SourceFiles};
+use crate::syntax::lexer::lexer;
+use crate::syntax::parser::expression;
+use crate::syntax::tokens::{PunctuationType, Token, constant, pseudoinstruction};
+use chumsky::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Expansions nested deeper than this are taken to be endless recursion.
+const MAX_DEPTH: usize = 64;
+
+/// Bytes `.STACK` reserves when it is given no size.
+pub const DEFAULT_STACK_SIZE: u64 = 0x400;
+
+/// Label `.STARTUP` places, and the entry point of a plain `END`.
+const STARTUP_LABEL: &str = "@Startup";
+
+type SpannedToken = (Token, SimpleSpan);
+
+/// Where a line of the expanded program comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LineOrigin {
+    pub file: FileId,
+    /// 1-based line in `file`; for expanded lines, the outermost invocation.
+    pub line: usize,
+    /// File and line of the macro or repeat block body an expanded line
+    /// comes from; the line itself for what `.STARTUP` and the like stand for.
+    pub macro_line: Option<(FileId, usize)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PreprocessorError {
+    pub message: String,
+    /// The line at fault, or the invocation it was expanded from.
+    pub span: SimpleSpan,
+}
+
+/// The token stream the parser sees.
+#[derive(Debug, Clone, Default)]
+pub struct Expansion {
+    pub tokens: Vec<SpannedToken>,
+    /// One entry per line of `tokens`, so per statement of the program.
+    pub origins: Vec<LineOrigin>,
+    pub errors: Vec<PreprocessorError>,
+    /// The main file and every file it included.
+    pub files: SourceFiles,
+    /// Lines (validator numbering) left out by a false condition.
+    pub inactive: Vec<usize>,
+    /// Set by `.MODEL`.
+    pub model: Option<MemoryModel>,
+}
+
+impl Expansion {
+    /// Origin of a validator line number (statement index + 1).
+    pub fn origin(&self, line: usize) -> LineOrigin {
+        self.origins
+            .get(line.wrapping_sub(1))
+            .copied()
+            .unwrap_or(LineOrigin {
+                file: MAIN_FILE,
+                line,
+                macro_line: None,
+            })
+    }
+
+    /// `12` or `lib/io.inc:12`: the source line a validator line number
+    /// is on, expanded lines counting as their invocation.
+    pub fn place(&self, line: usize) -> String {
+        let origin = self.origin(line);
+        self.files.place(origin.file, origin.line)
+    }
+
+    /// `12`, `lib/io.inc:12`, or `12 (línea 3 de la macro)` for expanded lines.
+    pub fn describe(&self, line: usize) -> String {
+        let origin = self.origin(line);
+        let place = self.place(line);
+        match origin.macro_line {
+            Some((file, body)) if (file, body) != (origin.file, origin.line) => {
+                format!(
+                    "{} (línea {} de la macro)",
+                    place,
+                    self.files.place(file, body)
+                )
+            }
+            _ => place,
+        }
+    }
+
+    /// Where a line came from when that is not simply the main-file line
+    /// it is shown on: `lib/io.inc:12`, `línea 3 de la macro` or both.
+    pub fn note(&self, line: usize) -> Option<String> {
+        let origin = self.origin(line);
+        let mut parts = Vec::new();
+        if origin.file != MAIN_FILE {
+            parts.push(self.files.place(origin.file, origin.line));
+        }
+        if let Some((file, body)) = origin.macro_line
+            && (file, body) != (origin.file, origin.line)
+        {
+            parts.push(format!(
+                "línea {} de la macro",
+                self.files.place(file, body)
+            ));
+        }
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    /// Line of the main file a validator line number belongs to: the
+    /// `INCLUDE` line for anything in an included file.
+    pub fn main_line(&self, line: usize) -> usize {
+        let origin = self.origin(line);
+        match self.files.get(origin.file) {
+            Some(file) if origin.file != MAIN_FILE => {
+                let span = self.files.main_span(SimpleSpan::from(file.base..file.base));
+                self.files.line_at(span.start).1
+            }
+            _ => origin.line,
+        }
+    }
+
+    /// `12` or `lib/io.inc:12` for an error span.
+    pub fn describe_span(&self, span: SimpleSpan) -> String {
+        let (file, line) = self.files.line_at(span.start);
+        self.files.place(file, line)
+    }
+
+    /// An error at `span` as shown on the main file: on the `INCLUDE` line
+    /// for one in an included file, with the file and line it is really at
+    /// put before the message.
+    pub fn locate(&self, span: SimpleSpan, message: String) -> (String, (usize, usize)) {
+        let main = self.files.main_span(span);
+        let message = if main == span {
+            message
+        } else {
+            format!("{}: {}", self.describe_span(span), message)
+        };
+        (message, (main.start, main.end))
+    }
+}
+
+/// Expands every macro, repeat block and include in `tokens`, the lexer
+/// output for `source`.
+pub fn expand(source: &str, tokens: Vec<SpannedToken>, includes: &Includes) -> Expansion {
+    let mut preprocessor = Preprocessor {
+        includes,
+        including: vec![includes.main_path.clone()],
+        macros: HashMap::new(),
+        constants: HashMap::new(),
+        defined: HashSet::new(),
+        next_local: 0,
+        startup: false,
+        out: Expansion::default(),
+    };
+    preprocessor
+        .out
+        .files
+        .add(&includes.main_path, source, None);
+    preprocessor.process(&split_lines(tokens, MAIN_FILE), None, 0);
+    preprocessor.out
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    tokens: Vec<SpannedToken>,
+    newline: Option<SimpleSpan>,
+    file: FileId,
+    /// 1-based line in `file`.
+    number: usize,
+}
+
+impl Line {
+    fn span(&self) -> SimpleSpan {
+        match (self.tokens.first(), self.tokens.last()) {
+            (Some((_, first)), Some((_, last))) => SimpleSpan::from(first.start..last.end),
+            _ => self.newline.unwrap_or(SimpleSpan::from(0..0)),
+        }
+    }
+
+    /// The directive word at `position`, uppercase (`REPT`, `ENDM`...).
+    fn directive(&self, position: usize) -> Option<&str> {
+        match self.tokens.get(position) {
+            Some((Token::Pseudoinstruction(p), _)) => Some(p.as_str()),
+            _ => None,
+        }
+    }
+}
+
+fn split_lines(tokens: Vec<SpannedToken>, file: FileId) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut current = Vec::new();
+    for (token, span) in tokens {
+        if token == Token::Newline {
+            lines.push(Line {
+                tokens: std::mem::take(&mut current),
+                newline: Some(span),
+                file,
+                number: lines.len() + 1,
+            });
+        } else {
+            current.push((token, span));
+        }
+    }
+    if !current.is_empty() {
+        lines.push(Line {
+            tokens: current,
+            newline: None,
+            file,
+            number: lines.len() + 1,
+        });
+    }
+    lines
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Block {
+    Macro,
+    Rept,
+    Irp,
+    Irpc,
+}
+
+/// Block a line opens: `name MACRO`, `REPT n`, `IRP p, <...>`, `IRPC p, text`.
+fn block_kind(line: &Line) -> Option<Block> {
+    match (line.directive(0), line.directive(1)) {
+        (Some("REPT"), _) => Some(Block::Rept),
+        (Some("IRP"), _) => Some(Block::Irp),
+        (Some("IRPC"), _) => Some(Block::Irpc),
+        (_, Some("MACRO")) if line.tokens.first().is_some_and(|(t, _)| word(t).is_some()) => {
+            Some(Block::Macro)
+        }
+        _ => None,
+    }
+}
+
+/// Index of the `ENDM` closing the block opened at `start`.
+fn block_end(lines: &[Line], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, line) in lines.iter().enumerate().skip(start) {
+        if block_kind(line).is_some() {
+            depth += 1;
+        } else if line.directive(0) == Some("ENDM") {
+            depth -= 1;
+            if depth == 0 {
+                return Some(index);
+            }
+        }
+    }
+    None
+}
+
+/// Name-like text of a token; macro parameters may be spelled like any of these.
+fn word(token: &Token) -> Option<&str> {
+    match token {
+        Token::Symbol(s)
+        | Token::Pseudoinstruction(s)
+        | Token::Register(s)
+        | Token::Instruction(_, s) => Some(s),
+        _ => None,
+    }
+}
+
+/// Splits on commas outside `<...>`; an argument that is wholly `<...>`
+/// loses the brackets.
+fn split_arguments(tokens: &[SpannedToken]) -> Vec<Vec<SpannedToken>> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    let mut arguments = vec![Vec::new()];
+    let mut depth = 0;
+    for token in tokens {
+        match &token.0 {
+            Token::Punctuation(PunctuationType::Comma) if depth == 0 => {
+                arguments.push(Vec::new());
+                continue;
+            }
+            Token::Punctuation(PunctuationType::LAngle) => depth += 1,
+            Token::Punctuation(PunctuationType::RAngle) => depth -= 1,
+            _ => {}
+        }
+        if let Some(argument) = arguments.last_mut() {
+            argument.push(token.clone());
+        }
+    }
+    arguments.into_iter().map(strip_angles).collect()
+}
+
+fn strip_angles(tokens: Vec<SpannedToken>) -> Vec<SpannedToken> {
+    match (tokens.first(), tokens.last()) {
+        (
+            Some((Token::Punctuation(PunctuationType::LAngle), _)),
+            Some((Token::Punctuation(PunctuationType::RAngle), _)),
+        ) if tokens.len() >= 2 => tokens[1..tokens.len() - 1].to_vec(),
+        _ => tokens,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Param {
+    /// As written; names are matched without case.
+    name: String,
+    default: Vec<SpannedToken>,
+    required: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    /// As written in the definition, for messages.
+    name: String,
+    params: Vec<Param>,
+    locals: Vec<String>,
+    body: Vec<Line>,
+}
+
+/// An `IF` block being read.
+struct Condition {
+    /// The lines around the block are assembled.
+    outer: bool,
+    /// The current branch is assembled.
+    active: bool,
+    /// Some branch has been assembled already.
+    taken: bool,
+    /// `ELSE` has been seen.
+    in_else: bool,
+    site: Site,
+}
+
+/// Where expanded lines are placed: the outermost invocation.
+#[derive(Debug, Clone, Copy)]
+struct Site {
+    span: SimpleSpan,
+    file: FileId,
+    line: usize,
+}
+
+struct Preprocessor<'a> {
+    includes: &'a Includes<'a>,
+    /// Files being included, outermost first, to catch cycles.
+    including: Vec<String>,
+    /// By uppercase name.
+    macros: HashMap<String, Macro>,
+    /// Numeric EQU and `=` values seen so far, for REPT counts.
+    constants: HashMap<String, SymbolInfo>,
+    /// Uppercase names defined so far, for IFDEF.
+    defined: HashSet<String>,
+    /// Number of the next `??nnnn` name given to a LOCAL label.
+    next_local: usize,
+    /// `.STARTUP` has been seen.
+    startup: bool,
+    out: Expansion,
+}
+
+impl Preprocessor<'_> {
+    /// Emits `lines`, expanding what they invoke. `site` is `None` for
+    /// source lines and the invocation for a body being expanded. Returns
+    /// true when EXITM ended the expansion.
+    fn process(&mut self, lines: &[Line], site: Option<Site>, depth: usize) -> bool {
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            let here = site.unwrap_or(Site {
+                span: line.span(),
+                file: line.file,
+                line: line.number,
+            });
+            let active = conditions.last().is_none_or(|c| c.active);
+
+            if let Some(directive) = line
+                .directive(0)
+                .filter(|d| pseudoinstruction::is_conditional(d))
+            {
+                // ELSE and ENDIF belong to the lines around the block
+                let opens = !directive.starts_with("ELSE") && directive != "ENDIF";
+                let shown = if opens {
+                    active
+                } else {
+                    conditions.last().is_none_or(|c| c.outer)
+                };
+                self.conditional(directive, line, here, &mut conditions);
+                match site {
+                    // Only source lines keep the directive, for the listing
+                    None if shown => self.emit(line, None),
+                    _ => self.skip(line, site),
+                }
+                index += 1;
+                continue;
+            }
+            if !active {
+                self.skip(line, site);
+                index += 1;
+                continue;
+            }
+
+            if let Some((Token::Include(path), _)) = line.tokens.first() {
+                self.blank(line, site);
+                match site {
+                    Some(_) => self.error(
+                        here,
+                        "INCLUDE no permitido dentro de una macro o bloque de repetición"
+                            .to_string(),
+                    ),
+                    None => self.include(path, line, depth),
+                }
+                index += 1;
+                continue;
+            }
+
+            if let Some(kind) = block_kind(line) {
+                let end = block_end(lines, index).unwrap_or_else(|| {
+                    self.error(here, format!("'{}' sin ENDM", line_label(line)));
+                    lines.len()
+                });
+                let body = &lines[index + 1..end];
+                // The expansion follows the whole block, as MASM lists it
+                for consumed in &lines[index..(end + 1).min(lines.len())] {
+                    self.blank(consumed, site);
+                }
+                match kind {
+                    Block::Macro => self.define(line, body, here),
+                    _ if depth >= MAX_DEPTH => {
+                        self.error(here, "Bloques de repetición anidados en exceso".to_string())
+                    }
+                    Block::Rept => self.repeat(line, body, here, depth),
+                    Block::Irp | Block::Irpc => self.repeat_each(kind, line, body, here, depth),
+                }
+                index = end + 1;
+                continue;
+            }
+
+            match line.directive(0) {
+                Some("EXITM") if depth > 0 => {
+                    self.blank(line, site);
+                    return true;
+                }
+                Some(stray @ ("ENDM" | "EXITM" | "LOCAL")) => {
+                    let message = match stray {
+                        "ENDM" => "ENDM sin MACRO, REPT, IRP o IRPC",
+                        "EXITM" => "EXITM fuera de una macro",
+                        _ => "LOCAL debe ir justo después de la línea MACRO",
+                    };
+                    self.error(here, message.to_string());
+                    self.blank(line, site);
+                    index += 1;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let invoked = line
+                .tokens
+                .first()
+                .and_then(|(t, _)| word(t))
+                .map(|name| name.to_uppercase())
+                .filter(|name| self.macros.contains_key(name));
+            match invoked {
+                Some(_) if depth >= MAX_DEPTH => {
+                    self.error(here, "Macros anidadas en exceso".to_string());
+                    self.blank(line, site);
+                }
+                Some(name) => {
+                    self.blank(line, site);
+                    if let Some(body) = self.instantiate(&name, &line.tokens[1..], here) {
+                        self.process(&body, Some(here), depth + 1);
+                    }
+                }
+                None => self.simplified(line, site, here),
+            }
+            index += 1;
+        }
+        for condition in conditions {
+            self.error(condition.site, "IF sin ENDIF".to_string());
+        }
+        false
+    }
+
+    /// Opens, switches or closes an IF block.
+    fn conditional(
+        &mut self,
+        directive: &str,
+        line: &Line,
+        here: Site,
+        conditions: &mut Vec<Condition>,
+    ) {
+        if directive == "ENDIF" {
+            if conditions.pop().is_none() {
+                self.error(here, "ENDIF sin IF".to_string());
+            }
+            return;
+        }
+        let Some(kind) = directive.strip_prefix("ELSE") else {
+            let outer = conditions.last().is_none_or(|c| c.active);
+            // A block inside a branch not taken is not even evaluated
+            let holds = outer && self.condition(directive, line, here);
+            conditions.push(Condition {
+                outer,
+                active: holds,
+                taken: holds,
+                in_else: false,
+                site: here,
+            });
+            return;
+        };
+        let Some(condition) = conditions.last_mut() else {
+            self.error(here, format!("{} sin IF", directive));
+            return;
+        };
+        if condition.in_else {
+            self.error(here, format!("{} después de ELSE", directive));
+            return;
+        }
+        let holds = condition.outer
+            && !condition.taken
+            && (kind.is_empty() || self.condition(kind, line, here));
+        condition.active = holds;
+        condition.taken |= holds;
+        condition.in_else = kind.is_empty();
+    }
+
+    /// Whether `IF expr`, `IFE expr`, `IFDEF name`, `IFB <text>`... holds.
+    fn condition(&mut self, kind: &str, line: &Line, here: Site) -> bool {
+        let args = &line.tokens[1..];
+        match kind {
+            "IF" | "IFE" => match self.evaluate(args) {
+                Ok(value) => (value != 0) == (kind == "IF"),
+                Err(msg) => {
+                    self.error(here, format!("Condición de {} no válida: {}", kind, msg));
+                    false
+                }
+            },
+            "IFDEF" | "IFNDEF" => match args {
+                [(name, _)] if word(name).is_some() => {
+                    let name = word(name).unwrap_or_default().to_uppercase();
+                    self.defined.contains(&name) == (kind == "IFDEF")
+                }
+                _ => {
+                    self.error(here, format!("{} requiere el nombre de un símbolo", kind));
+                    false
+                }
+            },
+            // IFB, IFNB
+            _ => strip_angles(args.to_vec()).is_empty() == (kind == "IFB"),
+        }
+    }
+
+    /// A line of a branch not taken: blank, and listed as inactive.
+    fn skip(&mut self, line: &Line, site: Option<Site>) {
+        let emitted = self.out.origins.len();
+        self.blank(line, site);
+        if self.out.origins.len() > emitted {
+            self.out.inactive.push(self.out.origins.len());
+        }
+    }
+
+    /// Remembers the name a label, variable, constant, procedure or
+    /// segment line defines.
+    fn record_definition(&mut self, line: &Line) {
+        let name = match line.tokens.as_slice() {
+            [
+                (Token::Symbol(name), _),
+                (Token::Punctuation(PunctuationType::Colon), _),
+                ..,
+            ]
+            | [
+                (Token::Symbol(name), _),
+                (Token::Punctuation(PunctuationType::Equals), _),
+                ..,
+            ] => name,
+            [
+                (Token::Symbol(name), _),
+                (Token::Pseudoinstruction(p), _),
+                ..,
+            ] if matches!(
+                p.as_str(),
+                "DB" | "DW" | "DD" | "EQU" | "PROC" | "SEGMENT" | "LABEL"
+            ) =>
+            {
+                name
+            }
+            _ => return,
+        };
+        self.defined.insert(name.to_uppercase());
+    }
+
+    /// Emits a line that invokes no macro, applying the simplified segment
+    /// directives on the way.
+    fn simplified(&mut self, line: &Line, site: Option<Site>, here: Site) {
+        let model = self.out.model.unwrap_or(MemoryModel::Small);
+        let args = line.tokens.get(1..).unwrap_or_default();
+        let at = |text: &str| tokens_at(text, here.span);
+        match line.directive(0) {
+            Some(".MODEL") => {
+                self.plain(line, site);
+                let Some(model) = args
+                    .first()
+                    .and_then(|(token, _)| word(token))
+                    .and_then(MemoryModel::from_name)
+                else {
+                    self.error(
+                        here,
+                        ".MODEL requiere TINY, SMALL, MEDIUM, COMPACT o LARGE".to_string(),
+                    );
+                    return;
+                };
+                if self.out.model.is_some() {
+                    self.error(here, ".MODEL solo puede indicarse una vez".to_string());
+                    return;
+                }
+                self.out.model = Some(model);
+                let far_code = model.code_distance() == Distance::Far;
+                let equates = [
+                    format!("@Model EQU {}", model.number()),
+                    format!("@CodeSize EQU {}", i64::from(far_code)),
+                    format!("@DataSize EQU {}", i64::from(model.far_data())),
+                ];
+                self.generate(line, here, equates.iter().map(|e| at(e)).collect());
+            }
+            // The segment itself, then its space
+            Some(".STACK") => {
+                let segment = Line {
+                    tokens: line.tokens[..1].to_vec(),
+                    ..line.clone()
+                };
+                self.plain(&segment, site);
+                let size = if args.is_empty() {
+                    at(&format!("0{:X}h", DEFAULT_STACK_SIZE))
+                } else {
+                    args.iter().map(|(t, _)| (t.clone(), here.span)).collect()
+                };
+                let reserve = [at("DW ("), size, at("+ 1) / 2 DUP (0)")].concat();
+                self.generate(line, here, vec![reserve]);
+            }
+            // A .COM program is loaded ready to run; anything else points
+            // DS at its data
+            Some(".STARTUP") => {
+                self.blank(line, site);
+                self.startup = true;
+                let mut lines = vec![at(&format!("{}:", STARTUP_LABEL))];
+                if model != MemoryModel::Tiny {
+                    lines.push(at("MOV DX, @data"));
+                    lines.push(at("MOV DS, DX"));
+                }
+                self.generate(line, here, lines);
+            }
+            // DOS function 4Ch, with the exit code in AL
+            Some(".EXIT") => {
+                self.blank(line, site);
+                let mut lines = Vec::new();
+                if !args.is_empty() {
+                    let code = args.iter().map(|(t, _)| (t.clone(), here.span));
+                    lines.push(at("MOV AL,").into_iter().chain(code).collect());
+                }
+                lines.push(at("MOV AH, 4Ch"));
+                lines.push(at("INT 21h"));
+                self.generate(line, here, lines);
+            }
+            _ => match line.tokens.as_slice() {
+                // name PROC: the model's distance
+                [(Token::Symbol(_), _), (Token::Pseudoinstruction(p), span)]
+                    if p == "PROC" && model.code_distance() == Distance::Far =>
+                {
+                    let mut tokens = line.tokens.clone();
+                    tokens.push((Token::Pseudoinstruction("FAR".to_string()), *span));
+                    self.plain(
+                        &Line {
+                            tokens,
+                            ..line.clone()
+                        },
+                        site,
+                    );
+                }
+                // END: the program starts where .STARTUP was
+                [(Token::Symbol(end), span)] if end.eq_ignore_ascii_case("END") && self.startup => {
+                    let mut tokens = line.tokens.clone();
+                    tokens.push((Token::Symbol(STARTUP_LABEL.to_string()), *span));
+                    self.plain(
+                        &Line {
+                            tokens,
+                            ..line.clone()
+                        },
+                        site,
+                    );
+                }
+                _ => self.plain(line, site),
+            },
+        }
+    }
+
+    /// Emits a line as it is, noting what it defines.
+    fn plain(&mut self, line: &Line, site: Option<Site>) {
+        self.record_constant(line);
+        self.record_definition(line);
+        self.emit(line, site);
+    }
+
+    /// Emits the lines a directive stands for, as an expansion of its line.
+    fn generate(&mut self, line: &Line, here: Site, lines: Vec<Vec<SpannedToken>>) {
+        for tokens in lines {
+            let generated = Line {
+                tokens,
+                newline: None,
+                ..line.clone()
+            };
+            self.record_constant(&generated);
+            self.record_definition(&generated);
+            self.emit(&generated, Some(here));
+        }
+    }
+
+    /// The line as the parser will see it.
+    fn emit(&mut self, line: &Line, site: Option<Site>) {
+        match site {
+            None => {
+                if line.tokens.is_empty() && line.newline.is_none() {
+                    return;
+                }
+                self.out.tokens.extend(line.tokens.iter().cloned());
+                // The last line of an included file still ends a statement
+                let newline = match (line.newline, line.tokens.last()) {
+                    (None, Some((_, last))) if line.file != MAIN_FILE => {
+                        Some(SimpleSpan::from(last.end..last.end))
+                    }
+                    (newline, _) => newline,
+                };
+                self.out.tokens.extend(newline.map(|s| (Token::Newline, s)));
+                self.out.origins.push(LineOrigin {
+                    file: line.file,
+                    line: line.number,
+                    macro_line: None,
+                });
+            }
+            // Blank body lines add nothing to an expansion
+            Some(_) if line.tokens.is_empty() => {}
+            Some(site) => {
+                self.out
+                    .tokens
+                    .extend(line.tokens.iter().map(|(t, _)| (t.clone(), site.span)));
+                self.out.tokens.push((Token::Newline, site.span));
+                self.out.origins.push(LineOrigin {
+                    file: site.file,
+                    line: site.line,
+                    macro_line: Some((line.file, line.number)),
+                });
+            }
+        }
+    }
+
+    /// Keeps a consumed source line as an empty one; inside an expansion
+    /// it simply disappears.
+    fn blank(&mut self, line: &Line, site: Option<Site>) {
+        if site.is_none() {
+            let blank = Line {
+                tokens: Vec::new(),
+                ..line.clone()
+            };
+            self.emit(&blank, None);
+        }
+    }
+
+    /// Emits the lines of the file `path` names, found next to the file
+    /// `line` is in, next to the main file or along the search paths.
+    fn include(&mut self, path: &str, line: &Line, depth: usize) {
+        let here = Site {
+            span: line.span(),
+            file: line.file,
+            line: line.number,
+        };
+        let from = self.out.files.name(line.file).to_string();
+        let Some((name, text)) = self.includes.resolve(path, &from) else {
+            self.error(
+                here,
+                format!("No se puede abrir el archivo incluido '{}'", path),
+            );
+            return;
+        };
+        if let Some(start) = self.including.iter().position(|n| *n == name) {
+            let mut chain = self.including[start..].to_vec();
+            chain.push(name);
+            self.error(here, format!("Inclusión circular: {}", chain.join(" -> ")));
+            return;
+        }
+        let file = self.out.files.add(&name, &text, Some(line.span()));
+        let base = self.out.files.get(file).map_or(0, |f| f.base);
+        let shift = |span: SimpleSpan| SimpleSpan::from(span.start + base..span.end + base);
+        let (tokens, errors) = lexer().parse(&text).into_output_errors();
+        for err in errors {
+            self.out.errors.push(PreprocessorError {
+                message: err.to_string(),
+                span: shift(*err.span()),
+            });
+        }
+        let tokens = tokens
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(token, span)| (token, shift(span)))
+            .collect();
+
+        self.including.push(name);
+        self.process(&split_lines(tokens, file), None, depth);
+        self.including.pop();
+    }
+
+    fn error(&mut self, site: Site, message: String) {
+        self.out.errors.push(PreprocessorError {
+            message,
+            span: site.span,
+        });
+    }
+
+    /// Records `name MACRO params` and its body (LOCAL lines first).
+    fn define(&mut self, line: &Line, body: &[Line], site: Site) {
+        let Some(name) = line.tokens.first().and_then(|(t, _)| word(t)) else {
+            return;
+        };
+        let name = name.to_string();
+
+        let mut params = Vec::new();
+        for param in split_arguments(&line.tokens[2..]) {
+            let param_name = param.first().and_then(|(t, _)| word(t)).map(str::to_string);
+            let (Some(param_name), rest) = (param_name, param.get(1..).unwrap_or_default()) else {
+                self.error(site, format!("Parámetro no válido en la macro '{}'", name));
+                return;
+            };
+            let (default, required) = match rest {
+                [] => (Vec::new(), false),
+                // p:REQ
+                [(Token::Punctuation(PunctuationType::Colon), _), (req, _)]
+                    if word(req).is_some_and(|r| r.eq_ignore_ascii_case("REQ")) =>
+                {
+                    (Vec::new(), true)
+                }
+                // p:=default, p:=<default text>
+                [
+                    (Token::Punctuation(PunctuationType::Colon), _),
+                    (Token::Punctuation(PunctuationType::Equals), _),
+                    default @ ..,
+                ] => (strip_angles(default.to_vec()), false),
+                _ => {
+                    self.error(site, format!("Parámetro no válido en la macro '{}'", name));
+                    return;
+                }
+            };
+            params.push(Param {
+                name: param_name,
+                default,
+                required,
+            });
+        }
+
+        let mut locals = Vec::new();
+        let mut start = 0;
+        while let Some(local) = body.get(start).filter(|l| l.directive(0) == Some("LOCAL")) {
+            for names in split_arguments(&local.tokens[1..]) {
+                match names.as_slice() {
+                    [(name, _)] if word(name).is_some() => {
+                        locals.push(word(name).unwrap_or_default().to_uppercase())
+                    }
+                    _ => self.error(
+                        site,
+                        format!("Nombre LOCAL no válido en la macro '{}'", name),
+                    ),
+                }
+            }
+            start += 1;
+        }
+
+        self.macros.insert(
+            name.to_uppercase(),
+            Macro {
+                name,
+                params,
+                locals,
+                body: body[start..].to_vec(),
+            },
+        );
+    }
+
+    /// Body of macro `name` with its parameters and LOCAL names replaced.
+    fn instantiate(
+        &mut self,
+        name: &str,
+        arguments: &[SpannedToken],
+        site: Site,
+    ) -> Option<Vec<Line>> {
+        let definition = self.macros.get(name)?.clone();
+        let name = &definition.name;
+        let mut arguments = split_arguments(arguments);
+        if arguments.len() > definition.params.len() {
+            self.error(
+                site,
+                format!("Demasiados argumentos para la macro '{}'", name),
+            );
+            return None;
+        }
+        arguments.resize(definition.params.len(), Vec::new());
+
+        let mut replacements = HashMap::new();
+        for (param, argument) in definition.params.iter().zip(arguments) {
+            let value = if argument.is_empty() {
+                if param.required {
+                    self.error(
+                        site,
+                        format!("La macro '{}' requiere el argumento '{}'", name, param.name),
+                    );
+                    return None;
+                }
+                param.default.clone()
+            } else {
+                argument
+            };
+            replacements.insert(param.name.to_uppercase(), value);
+        }
+        for local in &definition.locals {
+            let unique = Token::Symbol(format!("??{:04X}", self.next_local));
+            self.next_local += 1;
+            replacements.insert(local.clone(), vec![(unique, site.span)]);
+        }
+        Some(substitute(&definition.body, &replacements))
+    }
+
+    /// `REPT count`: the body `count` times.
+    fn repeat(&mut self, line: &Line, body: &[Line], site: Site, depth: usize) {
+        let count = match self.evaluate(&line.tokens[1..]) {
+            Ok(count) if count >= 0 => count,
+            Ok(count) => {
+                self.error(site, format!("Cantidad de REPT negativa: {}", count));
+                return;
+            }
+            Err(msg) => {
+                self.error(site, format!("Cantidad de REPT no válida: {}", msg));
+                return;
+            }
+        };
+        for _ in 0..count {
+            if self.process(body, Some(site), depth + 1) {
+                break;
+            }
+        }
+    }
+
+    /// `IRP p, <a, b>`: the body once per item; `IRPC p, text`: once per
+    /// character.
+    fn repeat_each(&mut self, kind: Block, line: &Line, body: &[Line], site: Site, depth: usize) {
+        let (param, items) = match line.tokens.as_slice() {
+            [
+                _,
+                (param, _),
+                (Token::Punctuation(PunctuationType::Comma), _),
+                items @ ..,
+            ] if word(param).is_some() => (word(param).unwrap_or_default().to_uppercase(), items),
+            _ => {
+                self.error(
+                    site,
+                    format!("Se esperaba: {} nombre, <elementos>", line_label(line)),
+                );
+                return;
+            }
+        };
+        let values: Vec<Vec<SpannedToken>> = if kind == Block::Irp {
+            split_arguments(&strip_angles(items.to_vec()))
+        } else {
+            let text = match (items.first(), items.last()) {
+                (Some((_, first)), Some((_, last))) => {
+                    self.out.files.text(SimpleSpan::from(first.start..last.end))
+                }
+                _ => "",
+            };
+            let text = text.trim_start_matches('<').trim_end_matches('>');
+            text.chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| tokens_at(&c.to_string(), site.span))
+                .collect()
+        };
+
+        for value in values {
+            let replacements = HashMap::from([(param.clone(), value)]);
+            if self.process(&substitute(body, &replacements), Some(site), depth + 1) {
+                break;
+            }
+        }
+    }
+
+    /// Value of an expression made of numbers and constants defined above.
+    fn evaluate(&self, tokens: &[SpannedToken]) -> Result<i64, String> {
+        let eoi = tokens.last().map_or(0, |(_, s)| s.end);
+        let stream = chumsky::input::Stream::from_iter(tokens.iter().cloned())
+            .map(SimpleSpan::from(eoi..eoi), |(t, s)| (t, s));
+        let expr = expression()
+            .then_ignore(end())
+            .parse(stream)
+            .into_result()
+            .map_err(|_| "se esperaba una expresión".to_string())?;
+        evaluate_absolute(&expr, &self.constants, &Address::here(0, "NONE"))
+    }
+
+    /// Remembers `NAME EQU n` and `NAME = n` for later REPT counts.
+    fn record_constant(&mut self, line: &Line) {
+        let [(Token::Symbol(name), _), (assignment, _), value @ ..] = line.tokens.as_slice() else {
+            return;
+        };
+        let defines = matches!(assignment, Token::Pseudoinstruction(p) if p == "EQU")
+            || *assignment == Token::Punctuation(PunctuationType::Equals);
+        if !defines {
+            return;
+        }
+        if let Ok(number) = self.evaluate(value) {
+            self.constants.insert(
+                name.clone(),
+                SymbolInfo {
+                    type_: SymbolType::Constant,
+                    data_type: DataType::Word,
+                    segment: "NONE".to_string(),
+                    offset: Some(number as u16 as u64),
+                    line_defined: line.number,
+                    references: Vec::new(),
+                    length: 1,
+                    value: Some(ConstantValue::Number(number)),
+                },
+            );
+        }
+    }
+}
+
+/// Tokens of `text`, all placed at `span`.
+fn tokens_at(text: &str, span: SimpleSpan) -> Vec<SpannedToken> {
+    lexer()
+        .parse(text)
+        .into_output()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(token, _)| (token, span))
+        .collect()
+}
+
+/// `REPT`, `IRP`, `IRPC` or the macro name, for messages.
+fn line_label(line: &Line) -> String {
+    match line.tokens.first() {
+        Some((token, _)) => word(token).unwrap_or_default().to_string(),
+        None => String::new(),
+    }
+}
+
+/// Copies `lines` replacing every name in `replacements` (matched without case).
+fn substitute(lines: &[Line], replacements: &HashMap<String, Vec<SpannedToken>>) -> Vec<Line> {
+    lines
+        .iter()
+        .map(|line| Line {
+            tokens: substitute_tokens(&line.tokens, replacements),
+            ..line.clone()
+        })
+        .collect()
+}
+
+/// One line of [`substitute`]. An `&` next to a parameter joins it to the
+/// token on its other side (`lbl&n&:` with n = 3 is `lbl3:`) and is
+/// dropped; inside a string, `&name`, `name&` and `&name&` are replaced.
+/// Any other `&` is left alone, for a macro defined inside this one.
+fn substitute_tokens(
+    tokens: &[SpannedToken],
+    replacements: &HashMap<String, Vec<SpannedToken>>,
+) -> Vec<SpannedToken> {
+    let ampersand =
+        |token: &SpannedToken| token.0 == Token::Punctuation(PunctuationType::Ampersand);
+    // Each token, with what replaces it and whether it was a parameter
+    let pieces: Vec<(Vec<SpannedToken>, bool)> = tokens
+        .iter()
+        .map(|token| match &token.0 {
+            Token::Constant(constant::Type::String(text)) => {
+                let substituted = substitute_text(text, replacements);
+                let replaced = substituted != *text;
+                let string = Token::Constant(constant::Type::String(substituted));
+                (vec![(string, token.1)], replaced)
+            }
+            other => match word(other).and_then(|w| replacements.get(&w.to_uppercase())) {
+                Some(replacement) => (replacement.clone(), true),
+                None => (vec![token.clone()], false),
+            },
+        })
+        .collect();
+
+    let mut out: Vec<SpannedToken> = Vec::new();
+    // An `&` was dropped: the next token joins the last one out
+    let mut join = false;
+    for (index, (piece, _)) in pieces.iter().enumerate() {
+        let is_operator = tokens.get(index).is_some_and(ampersand)
+            && (index > 0 && pieces[index - 1].1 || pieces.get(index + 1).is_some_and(|p| p.1));
+        if is_operator {
+            join = !out.is_empty();
+            continue;
+        }
+        let mut piece = piece.iter().cloned();
+        if join && let Some(first) = piece.next() {
+            join = false;
+            match out
+                .last()
+                .and_then(|last| Some((token_text(&last.0)?, token_text(&first.0)?)))
+            {
+                Some((left, right)) => {
+                    let span = out.pop().map_or(first.1, |(_, span)| span);
+                    out.extend(tokens_at(&(left + &right), span));
+                }
+                None => out.push(first),
+            }
+        }
+        // A blank argument leaves the join for the next token
+        out.extend(piece);
+    }
+    out
+}
+
+/// Replaces the parameters an `&` marks inside a string.
+fn substitute_text(text: &str, replacements: &HashMap<String, Vec<SpannedToken>>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        let end = rest[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(rest.len(), |n| start + n);
+        let name = &rest[start..end];
+        let before = rest[..start].strip_suffix('&');
+        let after = rest[end..].strip_prefix('&');
+        match replacements.get(&name.to_uppercase()) {
+            Some(value) if before.is_some() || after.is_some() => {
+                out += before.unwrap_or(&rest[..start]);
+                out += &value
+                    .iter()
+                    .filter_map(|(token, _)| token_text(token))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                rest = after.unwrap_or(&rest[end..]);
+            }
+            _ => {
+                out += &rest[..end];
+                rest = &rest[end..];
+            }
+        }
+    }
+    out + rest
+}
+
+/// Source text of a name or number token, for joining with `&`.
+fn token_text(token: &Token) -> Option<String> {
+    match token {
+        Token::Constant(constant::Type::NumberDecimal(value)) => Some(value.to_string()),
+        Token::Constant(
+            constant::Type::NumberHex(_, text) | constant::Type::NumberBinary(_, text),
+        ) => Some(text.clone()),
+        Token::Constant(constant::Type::String(text)) => Some(text.clone()),
+        _ => word(token).map(str::to_string),
+    }
+}
@@ -11,7 +11,6 @@ pub mod constant {
         NumberDecimal(u64),
         NumberHex(u64, String),
         NumberBinary(u64, String),
-        Char(char),
     }
 }
 
@@ -51,15 +50,53 @@ pub mod pseudoinstruction {
                 | "DD"
                 | "EQU"
                 | "ORG"
+                | "ALIGN"
+                | "EVEN"
                 | "OFFSET"
+                | "SEG"
+                | "TYPE"
+                | "SIZE"
+                | "LENGTH"
+                | "HIGH"
+                | "LOW"
+                | "MOD"
+                | "EQ"
+                | "NE"
+                | "LT"
+                | "LE"
+                | "GT"
+                | "GE"
                 | "ENDS"
                 | "SEGMENT"
                 | "DUP"
+                | "PROC"
+                | "ENDP"
+                | "NEAR"
+                | "FAR"
+                | "MACRO"
+                | "ENDM"
+                | "LOCAL"
+                | "EXITM"
+                | "REPT"
+                | "IRP"
+                | "IRPC"
                 | ".CODE"
                 | ".DATA"
                 | ".STACK"
                 | ".MODEL"
-        )
+                | ".STARTUP"
+                | ".EXIT"
+        ) || is_conditional(s)
+    }
+
+    /// Conditional assembly: `IF`, `IFE`, `IFDEF`, `IFNDEF`, `IFB`, `IFNB`,
+    /// the `ELSEIF` form of each, `ELSE` and `ENDIF`.
+    pub fn is_conditional(s: &str) -> bool {
+        let condition = |s: &str| matches!(s, "IF" | "IFE" | "IFDEF" | "IFNDEF" | "IFB" | "IFNB");
+        condition(s)
+            || s.strip_prefix("ELSE")
+                .is_some_and(|rest| rest.is_empty() || condition(rest))
+            || s == "ENDIF"
     }
 }
 
@@ -72,9 +109,9 @@ pub enum InstructionType {
     ControlTransfer,
     FlagControl,
     ConditionalJump,
+    StringManipulation,
     Interrupt,
     ProcessorControl,
-    Unknown,
 }
 
 impl fmt::Display for InstructionType {
@@ -86,23 +123,15 @@ impl fmt::Display for InstructionType {
             Self::ControlTransfer => write!(f, "Control Transfer"),
             Self::FlagControl => write!(f, "Flag Control"),
             Self::ConditionalJump => write!(f, "Conditional Jump"),
+            Self::StringManipulation => write!(f, "String Manipulation"),
             Self::Interrupt => write!(f, "Interrupt"),
             Self::ProcessorControl => write!(f, "Processor Control"),
-            Self::Unknown => write!(f, "Instruction"),
         }
     }
 }
 
 pub fn classify_instruction(mnemonic: &str) -> Option<InstructionType> {
-    match mnemonic {
-        "POP" | "LES" | "LDS" | "MOV" | "XCHG" => Some(InstructionType::DataTransfer),
-        "DEC" | "IDIV" | "IMUL" | "ADC" | "CMP" | "AAD" => Some(InstructionType::Arithmetic),
-        "AAA" => Some(InstructionType::Arithmetic),
-        "STC" | "CLC" => Some(InstructionType::FlagControl),
-        "JAE" | "JC" | "JGE" | "JNB" | "JNG" | "JNO" => Some(InstructionType::ConditionalJump),
-        "HLT" | "SCASW" | "INTO" | "INT" => Some(InstructionType::Interrupt),
-        _ => None,
-    }
+    crate::isa::lookup(mnemonic).map(|i| i.category.clone())
 }
 
 // --- 4. PUNCTUATION ---
@@ -116,7 +145,15 @@ pub enum PunctuationType {
     RParen,
     Plus,
     Minus,
+    Star,
+    Slash,
+    Dollar,
     Dot,
+    Equals,
+    LAngle,
+    RAngle,
+    /// `&`: joins a macro parameter to the text around it.
+    Ampersand,
 }
 
 impl fmt::Display for PunctuationType {
@@ -126,8 +163,12 @@ impl fmt::Display for PunctuationType {
             Self::Colon => write!(f, "Definition"), // or "Label Definition"
             Self::LBracket | Self::RBracket => write!(f, "Memory Access"),
             Self::LParen | Self::RParen => write!(f, "Grouping"),
-            Self::Plus | Self::Minus => write!(f, "Operator"),
+            Self::Plus | Self::Minus | Self::Star | Self::Slash => write!(f, "Operator"),
+            Self::Dollar => write!(f, "Location Counter"),
             Self::Dot => write!(f, "Access"),
+            Self::Equals => write!(f, "Assignment"),
+            Self::LAngle | Self::RAngle => write!(f, "Text Delimiter"),
+            Self::Ampersand => write!(f, "Substitution"),
         }
     }
 }
@@ -141,6 +182,8 @@ pub enum Token {
     Constant(constant::Type),
     Symbol(String),
     Punctuation(PunctuationType),
+    /// `INCLUDE path` or `%include "path"`, with the path unquoted.
+    Include(String),
     Error(String),
     Newline,
 }
@@ -154,6 +197,7 @@ impl Token {
             Token::Constant(_) => "Constant".to_string(),
             Token::Symbol(_) => "Symbol".to_string(),
             Token::Punctuation(_) => "Punctuation".to_string(),
+            Token::Include(_) => "Directive".to_string(),
             Token::Error(_) => "Error".to_string(),
             Token::Newline => "Control".to_string(),
         }
@@ -172,9 +216,9 @@ impl Token {
                 constant::Type::NumberDecimal(_) => "Decimal".to_string(),
                 constant::Type::NumberHex(_, _) => "Hexadecimal".to_string(),
                 constant::Type::NumberBinary(_, _) => "Binary".to_string(),
-                constant::Type::Char(_) => "Char".to_string(),
             },
-            Token::Error(e) => format!("{}", e),
+            Token::Include(_) => "Include".to_string(),
+            Token::Error(e) => e.clone(),
             Token::Newline => "Newline".to_string(),
         }
     }
@@ -190,6 +234,7 @@ impl fmt::Display for Token {
             Token::Symbol(s) => write!(f, "{}", s),
             Token::Punctuation(p) => write!(f, "{:?}", p),
             Token::Constant(c) => write!(f, "{:?}", c),
+            Token::Include(path) => write!(f, "INCLUDE {}", path),
             Token::Error(s) => write!(f, "Error({})", s),
             Token::Newline => write!(f, "\\n"),
         }